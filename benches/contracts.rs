@@ -0,0 +1,174 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use inkwell::context::Context;
+use primitive_types::{H160, U256};
+use jitevm::code::{EvmCode, EvmOpParserMode, IndexedEvmCode};
+use jitevm::constants::EVM_STACK_SIZE;
+use jitevm::interpreter::{EvmContext, EvmInnerContext, EvmOuterContext, EvmInterpreterError};
+use jitevm::jit::{JitEvmEngine, JitEvmExecutionContext, JitEvmExecutionContextHolder, EVM_JIT_STATUS_OK};
+use jitevm::state::EvmJournaledState;
+use jitevm::test_data;
+
+
+// Whole-contract benchmarks: each workload is measured in the interpreter, JIT compilation,
+// execution of the JIT-compiled code, and revm as the baseline. Workloads a backend does
+// not support (yet) are skipped for that backend with a note on stderr.
+//
+// The ERC-20 and Uniswap workloads are synthetic SLOAD/SSTORE loops in the shape of those
+// contracts, not the contracts themselves. snailtracer is the only real contract, and until
+// the interpreter implements RETURN and the JIT memory and calldata, only revm runs it.
+//
+// Compare against an earlier run with criterion's baselines:
+//   cargo bench --bench contracts -- --save-baseline before
+//   cargo bench --bench contracts -- --baseline before
+
+
+const CONTRACT: u64 = 0x1000;
+const CALLER: u64 = 0x2000;
+
+struct Workload {
+    name: &'static str,
+    code: EvmCode,
+    calldata: Vec<u8>,
+    sample_size: usize,
+}
+
+fn workloads() -> Vec<Workload> {
+    vec![
+        Workload { name: "fibonacci", code: EvmCode { ops: test_data::get_code_ops_fibonacci() }, calldata: vec![], sample_size: 100 },
+        Workload { name: "synthetic erc20 transfers", code: EvmCode { ops: test_data::get_code_ops_erc20_transfers() }, calldata: vec![], sample_size: 100 },
+        Workload { name: "synthetic uniswap swaps", code: EvmCode { ops: test_data::get_code_ops_uniswap_swaps() }, calldata: vec![], sample_size: 100 },
+        Workload { name: "keccak chain", code: EvmCode { ops: test_data::get_code_ops_keccak_chain() }, calldata: vec![], sample_size: 100 },
+        Workload {
+            name: "snailtracer",
+            code: EvmCode::new_from_bytes(&test_data::get_code_bin_revm_test1(), EvmOpParserMode::Lax).unwrap(),
+            calldata: hex::decode("30627b7c").unwrap(),
+            sample_size: 10,
+        },
+    ]
+}
+
+
+fn interpreter_context<'a>(code: &'a IndexedEvmCode, calldata: &[u8]) -> EvmContext<'a> {
+    EvmContext {
+        outer: EvmOuterContext {
+            calldata: calldata.to_vec(),
+            state: EvmJournaledState::new(),
+            address: H160::from_low_u64_be(CONTRACT),
+            callvalue: U256::zero(),
+        },
+        inner: EvmInnerContext {
+            code,
+            stack: [U256::zero(); EVM_STACK_SIZE],
+            pc: 0,
+            sp: 0,
+            memory: vec![],
+        },
+    }
+}
+
+fn run_interpreter(ctx: &mut EvmContext) -> Result<(), EvmInterpreterError> {
+    while ctx.tick()? {}
+    Ok(())
+}
+
+fn bench_interpreter(c: &mut Criterion, w: &Workload) {
+    let code = w.code.index();
+    if let Err(e) = run_interpreter(&mut interpreter_context(&code, &w.calldata)) {
+        eprintln!("{}: skipping interpreter: {}", w.name, e);
+        return;
+    }
+
+    let mut group = c.benchmark_group(w.name);
+    group.sample_size(w.sample_size);
+    group.bench_function("interpreter", |b| b.iter_batched_ref(
+        || interpreter_context(&code, &w.calldata),
+        |ctx| black_box(run_interpreter(ctx)),
+        BatchSize::LargeInput,
+    ));
+    group.finish();
+}
+
+fn bench_jit(c: &mut Criterion, w: &Workload) {
+    let code = w.code.index();
+    let context = Context::create();
+    let engine = JitEvmEngine::new_from_context(&context).unwrap();
+    let fn_contract = match engine.jit_compile_contract(&code, None, None) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{}: skipping JIT: {}", w.name, e);
+            return;
+        },
+    };
+
+    let mut group = c.benchmark_group(w.name);
+    group.sample_size(w.sample_size);
+
+    // a fresh engine for every compilation, so that functions do not accumulate in one module
+    group.bench_function("jit compile", |b| b.iter_batched_ref(
+        || JitEvmEngine::new_from_context(&context).unwrap(),
+        |engine| black_box(engine.jit_compile_contract(&code, None, None).is_ok()),
+        BatchSize::PerIteration,
+    ));
+
+    group.bench_function("jit execution", |b| b.iter_batched_ref(
+        || {
+            let mut holder = Box::new(JitEvmExecutionContextHolder::new_from_empty());
+            holder.address = H160::from_low_u64_be(CONTRACT);
+            holder
+        },
+        |holder| {
+            let mut ctx = JitEvmExecutionContext::new_from_holder(holder);
+            let ret = unsafe { fn_contract.call(&mut ctx as *mut _ as usize) };
+            assert_eq!(ret, EVM_JIT_STATUS_OK);
+        },
+        BatchSize::PerIteration,
+    ));
+
+    group.finish();
+}
+
+fn bench_revm(c: &mut Criterion, w: &Workload) {
+    use revm::{AccountInfo, Bytecode, InMemoryDB, Return, SpecId, TransactTo};
+
+    let bytecode = w.code.to_bytes();
+    let new_evm = || {
+        let mut db = InMemoryDB::default();
+        db.insert_account_info(H160::from_low_u64_be(CONTRACT), AccountInfo::new(U256::zero(), 1, Bytecode::new_raw(bytecode.clone().into())));
+
+        let mut evm = revm::new();
+        evm.database(db);
+        evm.env.cfg.spec_id = SpecId::LONDON;
+        evm.env.tx.caller = H160::from_low_u64_be(CALLER);
+        evm.env.tx.transact_to = TransactTo::Call(H160::from_low_u64_be(CONTRACT));
+        evm.env.tx.data = w.calldata.clone().into();
+        evm.env.tx.gas_limit = u64::MAX;
+        evm
+    };
+
+    let result = new_evm().transact_commit();
+    if !matches!(result.exit_reason, Return::Stop | Return::Return) {
+        eprintln!("{}: skipping revm: {:?}", w.name, result.exit_reason);
+        return;
+    }
+
+    let mut group = c.benchmark_group(w.name);
+    group.sample_size(w.sample_size);
+    group.bench_function("revm", |b| b.iter_batched_ref(
+        new_evm,
+        |evm| black_box(evm.transact_commit()),
+        BatchSize::LargeInput,
+    ));
+    group.finish();
+}
+
+
+fn bench_contracts(c: &mut Criterion) {
+    for w in workloads() {
+        bench_interpreter(c, &w);
+        bench_jit(c, &w);
+        bench_revm(c, &w);
+    }
+}
+
+criterion_group!(benches, bench_contracts);
+criterion_main!(benches);
@@ -0,0 +1,55 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rand::Rng;
+use primitive_types::U256;
+use jitevm::operations;
+
+
+fn random_operands(n: usize, small: bool) -> Vec<(U256, U256)> {
+    let mut rng = rand::thread_rng();
+    (0..n).map(|_| {
+        if small {
+            (U256::from(rng.gen::<u32>()), U256::from(rng.gen_range(1u32..256)))
+        } else {
+            (U256::from_big_endian(&rng.gen::<[u8; 32]>()), U256::from_big_endian(&rng.gen::<[u8; 32]>()))
+        }
+    }).collect()
+}
+
+macro_rules! bench_op2 {
+    ($c:ident, $fname:ident, $small:expr) => {{
+        let operands = random_operands(1000, $small);
+        let mut group = $c.benchmark_group(format!("{}{}", stringify!($fname), if $small { " (small)" } else { "" }));
+        group.bench_function("wrapper", |b| b.iter(|| {
+            for (x, y) in operands.iter() {
+                black_box(operations::$fname(black_box(*x), black_box(*y)));
+            }
+        }));
+        group.bench_function("native", |b| b.iter(|| {
+            for (x, y) in operands.iter() {
+                black_box(operations::native::$fname(black_box(*x), black_box(*y)));
+            }
+        }));
+        group.finish();
+    }};
+}
+
+
+fn bench_operations(c: &mut Criterion) {
+    bench_op2!(c, Add, false);
+    bench_op2!(c, Sub, false);
+    bench_op2!(c, Mul, false);
+    bench_op2!(c, Div, false);
+    bench_op2!(c, Div, true);
+    bench_op2!(c, Mod, false);
+    bench_op2!(c, Mod, true);
+    bench_op2!(c, Exp, false);
+    bench_op2!(c, Exp, true);
+    bench_op2!(c, Lt, false);
+    bench_op2!(c, Slt, false);
+    bench_op2!(c, Eq, false);
+    bench_op2!(c, Shl, true);
+    bench_op2!(c, Shr, true);
+}
+
+criterion_group!(benches, bench_operations);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+use jitevm::statetest::{run_path, EvmStateTestBackend, EvmStateTestOutcome, EvmStateTestReport};
+use std::error::Error;
+use std::path::PathBuf;
+
+const USAGE: &str = "usage: statetest [--filter <name>] [--backend interpreter|jit|both] [--verbose] <fixture file or directory>...";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut filter = None;
+    let mut backends = vec![EvmStateTestBackend::Interpreter, EvmStateTestBackend::Jit];
+    let mut verbose = false;
+    let mut paths = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--filter" => filter = Some(args.next().ok_or(USAGE)?),
+            "--backend" => backends = match args.next().as_deref() {
+                Some("interpreter") => vec![EvmStateTestBackend::Interpreter],
+                Some("jit") => vec![EvmStateTestBackend::Jit],
+                Some("both") => vec![EvmStateTestBackend::Interpreter, EvmStateTestBackend::Jit],
+                _ => return Err(USAGE.into()),
+            },
+            "--verbose" => verbose = true,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            },
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let mut results = Vec::new();
+    for path in &paths {
+        results.extend(run_path(path, filter.as_deref(), &backends)?);
+    }
+
+    for r in &results {
+        match &r.outcome {
+            EvmStateTestOutcome::Fail(reason) => println!("FAIL {} {}[{}] {:?}: {}", r.name, r.fork, r.index, r.backend, reason),
+            EvmStateTestOutcome::Skip(reason) if verbose => println!("SKIP {} {}[{}] {:?}: {}", r.name, r.fork, r.index, r.backend, reason),
+            _ => {},
+        }
+    }
+
+    let report = EvmStateTestReport::new(&results);
+    println!();
+    print!("{}", report);
+
+    if report.failures() > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
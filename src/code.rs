@@ -1,6 +1,14 @@
 use thiserror::Error;
 use primitive_types::U256;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, BTreeSet};
+
+pub mod cfg;
+pub mod absint;
+pub mod disasm;
+pub mod asm;
+pub mod metadata;
+pub mod eof;
+pub mod peephole;
 
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -12,11 +20,11 @@ pub enum EvmOp {
     Div,
     Sdiv,
     Mod,
-    // Smod,
-    // Addmod,
-    // Mulmod,
+    Smod,
+    Addmod,
+    Mulmod,
     Exp,
-    // Signextend,
+    Signextend,
     Lt,
     Gt,
     Slt,
@@ -25,12 +33,12 @@ pub enum EvmOp {
     Iszero,
     And,
     Or,
-    // Xor,
+    Xor,
     Not,   // 0x19 = 25
-    // Byte,
+    Byte,
     Shl,
     Shr,
-    // Sar,
+    Sar,
     Sha3,   // 0x20 = 32
     // Address,
     // Balance,
@@ -51,6 +59,8 @@ pub enum EvmOp {
     Jump,
     Jumpi,
     Jumpdest,
+    Tload,
+    Tstore,
 
     Push(usize, U256),
     Dup1,
@@ -93,12 +103,40 @@ pub enum EvmOp {
     Invalid,
     // Selfdestruct,
 
+    // superinstructions (see peephole::EvmPass), each one has the bytes of the instructions it
+    // replaces (see EvmOp::unfused)
     AugmentedPushJump(usize, U256),
     AugmentedPushJumpi(usize, U256),
+    AugmentedPushAdd(usize, U256),
+    AugmentedPushCalldataload(usize, U256),
+    AugmentedSwapPop(Vec<usize>),                          // SwapN Pop, repeated (N of each)
+    AugmentedDupIszeroPushJumpi(usize, usize, U256),       // DupN Iszero Push Jumpi: jumps if the Nth element is zero
+    AugmentedComparePushJumpi(Box<EvmOp>, usize, U256),    // Lt, Gt, Slt, Sgt, Eq or Iszero, then Push Jumpi
+    AugmentedConst(Vec<EvmOp>, U256),                      // pure instructions on constants, folded
+
+    // EOF only (see eof::EvmEofContainer), relative offsets count from the next instruction
+    Rjump(i16),
+    Rjumpi(i16),
+    Rjumpv(Vec<i16>),
+    Callf(u16),
+    Retf,
+    Jumpf(u16),
 
     Unknown(u8),
+    Data(Vec<u8>),   // unreachable bytes (data, metadata) kept to preserve offsets, never executed
 }
 
+pub(crate) const DUPS: [EvmOp; 16] = {
+    use EvmOp::*;
+    [Dup1, Dup2, Dup3, Dup4, Dup5, Dup6, Dup7, Dup8, Dup9, Dup10, Dup11, Dup12, Dup13, Dup14, Dup15, Dup16]
+};
+
+pub(crate) const SWAPS: [EvmOp; 16] = {
+    use EvmOp::*;
+    [Swap1, Swap2, Swap3, Swap4, Swap5, Swap6, Swap7, Swap8, Swap9, Swap10, Swap11, Swap12, Swap13, Swap14, Swap15, Swap16]
+};
+
+
 #[derive(Error, Debug)]
 pub enum EvmOpError {
     #[error("parser error: incomplete instruction")]
@@ -121,11 +159,159 @@ impl EvmOp {
             Push(len, _) => 1 + len,
             AugmentedPushJump(len, _) => 1 + len + 1,
             AugmentedPushJumpi(len, _) => 1 + len + 1,
+            AugmentedPushAdd(len, _) | AugmentedPushCalldataload(len, _) => 1 + len + 1,
+            AugmentedSwapPop(depths) => 2 * depths.len(),
+            AugmentedDupIszeroPushJumpi(_, len, _) => 1 + 1 + 1 + len + 1,
+            AugmentedComparePushJumpi(_, len, _) => 1 + 1 + len + 1,
+            AugmentedConst(ops, _) => ops.iter().map(|op| op.len()).sum(),
+            Rjump(_) | Rjumpi(_) | Callf(_) | Jumpf(_) => 3,
+            Rjumpv(offsets) => 2 + 2 * offsets.len(),
             Unknown(_) => 1,
+            Data(bytes) => bytes.len(),
             _ => 1,
         }
     }
 
+    // number of stack elements (consumed, produced) by the instruction
+    pub fn stack_effect(&self) -> (usize, usize) {
+        use EvmOp::*;
+
+        match self {
+            Stop | Jumpdest | Invalid | Unknown(_) | Data(_) => (0, 0),
+            Add | Mul | Sub | Div | Sdiv | Mod | Smod | Exp | Signextend => (2, 1),
+            Addmod | Mulmod => (3, 1),
+            Lt | Gt | Slt | Sgt | Eq | And | Or | Xor | Byte | Shl | Shr | Sar => (2, 1),
+            Iszero | Not => (1, 1),
+            Sha3 => (2, 1),
+            Origin | Caller | Callvalue | Calldatasize => (0, 1),
+            Calldataload => (1, 1),
+            Pop => (1, 0),
+            Mload | Sload => (1, 1),
+            Mstore | Mstore8 | Sstore => (2, 0),
+            Tload => (1, 1),
+            Tstore => (2, 0),
+            Jump => (1, 0),
+            Jumpi => (2, 0),
+            Push(_, _) => (0, 1),
+            Dup1 => (1, 2),
+            Dup2 => (2, 3),
+            Dup3 => (3, 4),
+            Dup4 => (4, 5),
+            Dup5 => (5, 6),
+            Dup6 => (6, 7),
+            Dup7 => (7, 8),
+            Dup8 => (8, 9),
+            Dup9 => (9, 10),
+            Dup10 => (10, 11),
+            Dup11 => (11, 12),
+            Dup12 => (12, 13),
+            Dup13 => (13, 14),
+            Dup14 => (14, 15),
+            Dup15 => (15, 16),
+            Dup16 => (16, 17),
+            Swap1 => (2, 2),
+            Swap2 => (3, 3),
+            Swap3 => (4, 4),
+            Swap4 => (5, 5),
+            Swap5 => (6, 6),
+            Swap6 => (7, 7),
+            Swap7 => (8, 8),
+            Swap8 => (9, 9),
+            Swap9 => (10, 10),
+            Swap10 => (11, 11),
+            Swap11 => (12, 12),
+            Swap12 => (13, 13),
+            Swap13 => (14, 14),
+            Swap14 => (15, 15),
+            Swap15 => (16, 16),
+            Swap16 => (17, 17),
+            Log2 => (4, 0),
+            Return | Revert => (2, 0),
+            AugmentedPushJump(_, _) => (0, 0),
+            AugmentedPushJumpi(_, _) => (1, 0),
+            AugmentedPushAdd(_, _) => (1, 1),
+            AugmentedPushCalldataload(_, _) => (0, 1),
+            AugmentedSwapPop(depths) => {
+                // each pair consumes one element more than it produces, the deepest swap
+                // determines how many are needed
+                let consumed = depths.iter().enumerate().map(|(i, n)| i + n + 1).max().unwrap_or(0);
+                (consumed, consumed - depths.len())
+            },
+            AugmentedDupIszeroPushJumpi(n, _, _) => (*n, *n),
+            AugmentedComparePushJumpi(cmp, _, _) => (cmp.stack_effect().0, 0),
+            AugmentedConst(_, _) => (0, 1),
+            Rjump(_) | Retf => (0, 0),
+            Rjumpi(_) | Rjumpv(_) => (1, 0),
+            Callf(_) | Jumpf(_) => (0, 0),   // depends on the type of the code section, see eof
+        }
+    }
+
+    pub fn is_block_terminator(&self) -> bool {
+        use EvmOp::*;
+
+        match self {
+            Stop | Return | Revert | Invalid | Unknown(_) | Data(_) => true,
+            Jump | Jumpi | AugmentedPushJump(_, _) | AugmentedPushJumpi(_, _) => true,
+            AugmentedDupIszeroPushJumpi(_, _, _) | AugmentedComparePushJumpi(_, _, _) => true,
+            Rjump(_) | Rjumpi(_) | Rjumpv(_) | Retf | Jumpf(_) => true,
+            _ => false,
+        }
+    }
+
+    // mnemonic as used by go-ethereum (e.g., in EIP-3155 traces)
+    pub fn name(&self) -> String {
+        use EvmOp::*;
+
+        match self {
+            Sha3 => "KECCAK256".to_string(),
+            Push(len, _) => format!("PUSH{}", len),
+            op if op.is_augmented() => op.unfused().iter().map(|op| op.name()).collect(),   // e.g. PUSH1JUMPI
+            Rjump(_) => "RJUMP".to_string(),
+            Rjumpi(_) => "RJUMPI".to_string(),
+            Rjumpv(_) => "RJUMPV".to_string(),
+            Callf(_) => "CALLF".to_string(),
+            Jumpf(_) => "JUMPF".to_string(),
+            Unknown(opcode) => format!("opcode {:#04x} not defined", opcode),
+            Data(_) => "DATA".to_string(),
+            op => format!("{:?}", op).to_uppercase(),
+        }
+    }
+
+    pub fn is_augmented(&self) -> bool {
+        use EvmOp::*;
+
+        matches!(self,
+            AugmentedPushJump(_, _) | AugmentedPushJumpi(_, _) | AugmentedPushAdd(_, _) | AugmentedPushCalldataload(_, _)
+            | AugmentedSwapPop(_) | AugmentedDupIszeroPushJumpi(_, _, _) | AugmentedComparePushJumpi(_, _, _) | AugmentedConst(_, _))
+    }
+
+    // the instructions a superinstruction replaces (or just the instruction itself)
+    pub fn unfused(&self) -> Vec<EvmOp> {
+        use EvmOp::*;
+
+        match self {
+            AugmentedPushJump(len, val) => vec![Push(*len, *val), Jump],
+            AugmentedPushJumpi(len, val) => vec![Push(*len, *val), Jumpi],
+            AugmentedPushAdd(len, val) => vec![Push(*len, *val), Add],
+            AugmentedPushCalldataload(len, val) => vec![Push(*len, *val), Calldataload],
+            AugmentedSwapPop(depths) => depths.iter().flat_map(|n| [SWAPS[n-1].clone(), Pop]).collect(),
+            AugmentedDupIszeroPushJumpi(n, len, val) => vec![DUPS[n-1].clone(), Iszero, Push(*len, *val), Jumpi],
+            AugmentedComparePushJumpi(cmp, len, val) => vec![(**cmp).clone(), Push(*len, *val), Jumpi],
+            AugmentedConst(ops, _) => ops.clone(),
+            op => vec![op.clone()],
+        }
+    }
+
+    // N of DupN
+    pub fn dup_depth(&self) -> Option<usize> {
+        DUPS.iter().position(|op| op == self).map(|i| i + 1)
+    }
+
+    // N of SwapN
+    pub fn swap_depth(&self) -> Option<usize> {
+        SWAPS.iter().position(|op| op == self).map(|i| i + 1)
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         use EvmOp::*;
 
@@ -137,7 +323,11 @@ impl EvmOp {
             Div => vec![0x04],
             Sdiv => vec![0x05],
             Mod => vec![0x06],
+            Smod => vec![0x07],
+            Addmod => vec![0x08],
+            Mulmod => vec![0x09],
             Exp => vec![0x0a],
+            Signextend => vec![0x0b],
             Lt => vec![0x10],
             Gt => vec![0x11],
             Slt => vec![0x12],
@@ -146,10 +336,12 @@ impl EvmOp {
             Iszero => vec![0x15],
             And => vec![0x16],
             Or => vec![0x17],
+            Xor => vec![0x18],
             Not => vec![0x19],
+            Byte => vec![0x1a],
             Shl => vec![0x1b],
             Shr => vec![0x1c],
-            // Sar => vec![0x1d],
+            Sar => vec![0x1d],
             Sha3 => vec![0x20],
 
             // Address => vec![0x30],
@@ -168,6 +360,8 @@ impl EvmOp {
             Jump => vec![0x56],
             Jumpi => vec![0x57],
             Jumpdest => vec![0x5b],
+            Tload => vec![0x5c],
+            Tstore => vec![0x5d],
 
             Push(len, val) => {
                 assert!(*len >= 1);
@@ -222,8 +416,26 @@ impl EvmOp {
 
             AugmentedPushJump(len, val) => Push(*len, *val).to_bytes().into_iter().chain(Jump.to_bytes().into_iter()).collect(),
             AugmentedPushJumpi(len, val) => Push(*len, *val).to_bytes().into_iter().chain(Jumpi.to_bytes().into_iter()).collect(),
+            AugmentedPushAdd(_, _) | AugmentedPushCalldataload(_, _) | AugmentedSwapPop(_) => self.unfused().iter().flat_map(|op| op.to_bytes()).collect(),
+            AugmentedDupIszeroPushJumpi(_, _, _) | AugmentedComparePushJumpi(_, _, _) | AugmentedConst(_, _) => self.unfused().iter().flat_map(|op| op.to_bytes()).collect(),
+
+            Rjump(offset) => [&[0xe0], &offset.to_be_bytes()[..]].concat(),
+            Rjumpi(offset) => [&[0xe1], &offset.to_be_bytes()[..]].concat(),
+            Rjumpv(offsets) => {
+                assert!(!offsets.is_empty() && offsets.len() <= 256);
+
+                let mut w = vec![0xe2, (offsets.len() - 1) as u8];
+                for offset in offsets {
+                    w.extend_from_slice(&offset.to_be_bytes());
+                }
+                w
+            },
+            Callf(section) => [&[0xe3], &section.to_be_bytes()[..]].concat(),
+            Retf => vec![0xe4],
+            Jumpf(section) => [&[0xe5], &section.to_be_bytes()[..]].concat(),
 
             Unknown(opcode) => vec![*opcode],
+            Data(bytes) => bytes.clone(),
         }
     }
 
@@ -256,7 +468,11 @@ impl EvmOp {
                 0x04 => Ok((Div, 1)),
                 0x05 => Ok((Sdiv, 1)),
                 0x06 => Ok((Mod, 1)),
+                0x07 => Ok((Smod, 1)),
+                0x08 => Ok((Addmod, 1)),
+                0x09 => Ok((Mulmod, 1)),
                 0x0a => Ok((Exp, 1)),
+                0x0b => Ok((Signextend, 1)),
                 0x10 => Ok((Lt, 1)),
                 0x11 => Ok((Gt, 1)),
                 0x12 => Ok((Slt, 1)),
@@ -265,9 +481,12 @@ impl EvmOp {
                 0x15 => Ok((Iszero, 1)),
                 0x16 => Ok((And, 1)),
                 0x17 => Ok((Or, 1)),
+                0x18 => Ok((Xor, 1)),
                 0x19 => Ok((Not, 1)),
+                0x1a => Ok((Byte, 1)),
                 0x1b => Ok((Shl, 1)),
                 0x1c => Ok((Shr, 1)),
+                0x1d => Ok((Sar, 1)),
                 0x20 => Ok((Sha3, 1)),
 
                 0x32 => Ok((Origin, 1)),
@@ -285,6 +504,8 @@ impl EvmOp {
                 0x56 => Ok((Jump, 1)),
                 0x57 => Ok((Jumpi, 1)),
                 0x5b => Ok((Jumpdest, 1)),
+                0x5c => Ok((Tload, 1)),
+                0x5d => Ok((Tstore, 1)),
                 
                 0x80 => Ok((Dup1, 1)),
                 0x81 => Ok((Dup2, 1)),
@@ -353,25 +574,75 @@ pub enum EvmCodeError {
 }
 
 impl EvmCode {
+    // Bytes that cannot be reached become Data ops, so that they are neither executed nor
+    // compiled, and do not make the Strict parser fail: everything after a terminator (other
+    // than Jumpi) up to the next Jumpdest, e.g., data tables and constructor arguments. If
+    // such a region extends to the end of the code and ends in solc metadata (see
+    // metadata::EvmMetadata), the metadata is a Data op of its own.
+    // Jumpdests are found by the same linear sweep over all bytes as in the EVM, so jump
+    // targets do not change, and a 0x5b in what looks like metadata is a Jumpdest all the same.
     pub fn new_from_bytes(b: &[u8], mode: EvmOpParserMode) -> Result<Self, EvmCodeError> {
-        let mut idx = 0;
-        let mut ops = Vec::new();
 
+        // LINEAR SWEEP
+
+        let mut parsed = Vec::new();   // offset and op, None for a PUSH cut off by the end
+        let mut idx = 0;
         while idx < b.len() {
-            match EvmOp::new_from_bytes(&b[idx..], mode) {
-                Ok((op, offset)) => {
-                    ops.push(op);
-                    idx += offset;
+            match EvmOp::new_from_bytes(&b[idx..], EvmOpParserMode::Lax) {
+                Ok((op, len)) => {
+                    parsed.push((idx, Some(op)));
+                    idx += len;
+                },
+                Err(_) => {
+                    parsed.push((idx, None));
+                    break;
+                },
+            }
+        }
+
+        // DATA REGIONS
+
+        let mut ops = Vec::new();
+        let mut data_start = None;
+        for (offset, op) in parsed {
+            match (op, data_start) {
+                (Some(EvmOp::Jumpdest), Some(start)) => {
+                    if start < offset {
+                        ops.push(EvmOp::Data(b[start..offset].to_vec()));
+                    }
+                    ops.push(EvmOp::Jumpdest);
+                    data_start = None;
                 },
-                Err(EvmOpError::ParserErrorIncompleteInstruction) => {
-                    return Err(EvmCodeError::ParserErrorIncompleteInstruction(idx));
+                (_, Some(_)) => {},
+                (None, None) => {
+                    return Err(EvmCodeError::ParserErrorIncompleteInstruction(offset));
                 },
-                Err(EvmOpError::ParserErrorUnknownInstruction(opcode)) => {
-                    return Err(EvmCodeError::ParserErrorUnknownInstruction(idx, opcode));
+                (Some(EvmOp::Unknown(opcode)), None) if matches!(mode, EvmOpParserMode::Strict) => {
+                    return Err(EvmCodeError::ParserErrorUnknownInstruction(offset, opcode));
+                },
+                (Some(op), None) => {
+                    if op.is_block_terminator() && op != EvmOp::Jumpi {
+                        data_start = Some(offset + op.len());
+                    }
+                    ops.push(op);
                 },
             }
         }
 
+        if let Some(start) = data_start.filter(|start| *start < b.len()) {
+            // metadata only where nothing can reach it
+            let metadata_start = metadata::metadata_range(b).map(|m| m.start).filter(|m| *m >= start);
+            match metadata_start {
+                Some(m) => {
+                    if start < m {
+                        ops.push(EvmOp::Data(b[start..m].to_vec()));
+                    }
+                    ops.push(EvmOp::Data(b[m..].to_vec()));
+                },
+                None => ops.push(EvmOp::Data(b[start..].to_vec())),
+            }
+        }
+
         Ok(Self { ops })
     }
 
@@ -385,32 +656,9 @@ impl EvmCode {
         ret
     }
 
+    // fuses Push Jump and Push Jumpi (see EvmCode::optimize for the other passes)
     pub fn augment(&self) -> Self {
-        use EvmOp::*;
-
-        let mut ops = Vec::new();
-        let mut idx = 0;
-
-        while idx < self.ops.len() {
-            if idx < self.ops.len() - 1 {
-                if let Push(len, val) = self.ops[idx] {
-                    if self.ops[idx+1] == Jump {
-                        ops.push(AugmentedPushJump(len, val));
-                        idx += 2;
-                        continue;
-                    } else if self.ops[idx+1] == Jumpi {
-                        ops.push(AugmentedPushJumpi(len, val));
-                        idx += 2;
-                        continue;
-                    }
-                }
-            }
-            
-            ops.push(self.ops[idx].clone());
-            idx += 1;
-        }
-
-        Self { ops }
+        self.optimize(&[peephole::EvmPass::PushJump])
     }
 
     pub fn index(&self) -> IndexedEvmCode {
@@ -424,14 +672,14 @@ pub struct IndexedEvmCode {
     pub code: EvmCode,
     pub opidx2target: HashMap<usize, U256>,
     pub target2opidx: HashMap<U256, usize>,
-    pub jumpdests: HashSet<usize>,
+    pub jumpdests: BTreeSet<usize>,
 }
 
 impl IndexedEvmCode {
     pub fn new_from_evmcode(code: EvmCode) -> Self {
         let mut opidx2target = HashMap::new();
         let mut target2opidx = HashMap::new();
-        let mut jumpdests = HashSet::new();
+        let mut jumpdests = BTreeSet::new();
 
         let mut target = 0;
         for opidx in 0..code.ops.len() {
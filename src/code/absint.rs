@@ -0,0 +1,370 @@
+use std::collections::BTreeSet;
+use primitive_types::U256;
+use crate::code::{EvmOp, IndexedEvmCode};
+use crate::code::cfg::{EvmBasicBlock, EvmBlockExit, EvmJumpTarget};
+use crate::constants::EVM_STACK_SIZE;
+
+#[cfg(test)]
+mod test;
+
+
+// beyond this many possible constants, a value is considered unknown
+const ABSINT_MAX_CONSTANTS: usize = 16;
+// only this many topmost stack elements are tracked across blocks
+const ABSINT_MAX_DEPTH: usize = 32;
+// beyond this many distinct entry stacks, a block's entry stacks are joined into one
+const ABSINT_MAX_CONTEXTS: usize = 16;
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvmAbstractValue {
+    Constants(BTreeSet<U256>),   // value is one of these (never empty)
+    Unknown,
+}
+
+impl EvmAbstractValue {
+    pub fn new_constant(val: U256) -> Self {
+        Self::Constants(BTreeSet::from([val]))
+    }
+
+    pub fn join(&self, other: &Self) -> Self {
+        use EvmAbstractValue::*;
+
+        match (self, other) {
+            (Constants(a), Constants(b)) => {
+                let c: BTreeSet<U256> = a.union(b).cloned().collect();
+                if c.len() > ABSINT_MAX_CONSTANTS {
+                    Unknown
+                } else {
+                    Constants(c)
+                }
+            },
+            _ => Unknown,
+        }
+    }
+
+    fn as_single(&self) -> Option<U256> {
+        match self {
+            Self::Constants(c) if c.len() == 1 => c.iter().next().cloned(),
+            _ => None,
+        }
+    }
+
+    // constant folding is only attempted if all operands are exactly known
+    fn fold1(a: Self, f: fn(U256) -> U256) -> Self {
+        match a.as_single() {
+            Some(a) => Self::new_constant(f(a)),
+            None => Self::Unknown,
+        }
+    }
+
+    fn fold2(a: Self, b: Self, f: fn(U256, U256) -> U256) -> Self {
+        match (a.as_single(), b.as_single()) {
+            (Some(a), Some(b)) => Self::new_constant(f(a, b)),
+            _ => Self::Unknown,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvmAbstractStack {
+    // topmost known elements (top of stack last), everything below is unknown
+    pub elements: Vec<EvmAbstractValue>,
+}
+
+impl EvmAbstractStack {
+    pub fn new_empty() -> Self {
+        Self { elements: Vec::new() }
+    }
+
+    pub fn push(&mut self, val: EvmAbstractValue) {
+        if self.elements.len() == EVM_STACK_SIZE {
+            self.elements.remove(0);
+        }
+        self.elements.push(val);
+    }
+
+    pub fn pop(&mut self) -> EvmAbstractValue {
+        self.elements.pop().unwrap_or(EvmAbstractValue::Unknown)
+    }
+
+    // idx 1 is the top of the stack
+    pub fn peek(&self, idx: usize) -> EvmAbstractValue {
+        if idx <= self.elements.len() {
+            self.elements[self.elements.len() - idx].clone()
+        } else {
+            EvmAbstractValue::Unknown
+        }
+    }
+
+    fn ensure(&mut self, len: usize) {
+        while self.elements.len() < len {
+            self.elements.insert(0, EvmAbstractValue::Unknown);
+        }
+    }
+
+    fn dup(&mut self, idx: usize) {
+        let val = self.peek(idx);
+        self.push(val);
+    }
+
+    fn swap(&mut self, idx: usize) {
+        self.ensure(idx + 1);
+        let len = self.elements.len();
+        self.elements.swap(len - 1, len - 1 - idx);
+    }
+
+    // stacks are aligned at the top, the result only keeps what both have in common
+    pub fn join(&self, other: &Self) -> Self {
+        let len = std::cmp::min(self.elements.len(), other.elements.len());
+        let a = &self.elements[self.elements.len()-len..];
+        let b = &other.elements[other.elements.len()-len..];
+        Self { elements: a.iter().zip(b.iter()).map(|(a, b)| a.join(b)).collect() }
+    }
+
+    pub fn execute(&mut self, op: &EvmOp) {
+        use EvmOp::*;
+        use EvmAbstractValue as V;
+
+        match op {
+            Push(_, val) => self.push(V::new_constant(*val)),
+            Pop => { self.pop(); },
+            Jumpdest => {},
+            Dup1 => self.dup(1),
+            Dup2 => self.dup(2),
+            Dup3 => self.dup(3),
+            Dup4 => self.dup(4),
+            Dup5 => self.dup(5),
+            Dup6 => self.dup(6),
+            Dup7 => self.dup(7),
+            Dup8 => self.dup(8),
+            Dup9 => self.dup(9),
+            Dup10 => self.dup(10),
+            Dup11 => self.dup(11),
+            Dup12 => self.dup(12),
+            Dup13 => self.dup(13),
+            Dup14 => self.dup(14),
+            Dup15 => self.dup(15),
+            Dup16 => self.dup(16),
+            Swap1 => self.swap(1),
+            Swap2 => self.swap(2),
+            Swap3 => self.swap(3),
+            Swap4 => self.swap(4),
+            Swap5 => self.swap(5),
+            Swap6 => self.swap(6),
+            Swap7 => self.swap(7),
+            Swap8 => self.swap(8),
+            Swap9 => self.swap(9),
+            Swap10 => self.swap(10),
+            Swap11 => self.swap(11),
+            Swap12 => self.swap(12),
+            Swap13 => self.swap(13),
+            Swap14 => self.swap(14),
+            Swap15 => self.swap(15),
+            Swap16 => self.swap(16),
+            Add | Sub | Mul | And | Or | Xor | Shl | Shr => {
+                let a = self.pop();
+                let b = self.pop();
+                let d = match op {
+                    Add => V::fold2(a, b, |a, b| a.overflowing_add(b).0),
+                    Sub => V::fold2(a, b, |a, b| a.overflowing_sub(b).0),
+                    Mul => V::fold2(a, b, |a, b| a.overflowing_mul(b).0),
+                    And => V::fold2(a, b, |a, b| a & b),
+                    Or => V::fold2(a, b, |a, b| a | b),
+                    Xor => V::fold2(a, b, |a, b| a ^ b),
+                    Shl => V::fold2(a, b, |a, b| if a < U256::from(256) { b << a.as_usize() } else { U256::zero() }),
+                    Shr => V::fold2(a, b, |a, b| if a < U256::from(256) { b >> a.as_usize() } else { U256::zero() }),
+                    _ => unreachable!(),
+                };
+                self.push(d);
+            },
+            Not => {
+                let a = self.pop();
+                self.push(V::fold1(a, |a| !a));
+            },
+            AugmentedConst(_, val) => self.push(V::new_constant(*val)),
+            op if op.is_augmented() => {
+                for op in op.unfused() {
+                    self.execute(&op);
+                }
+            },
+            _ => {
+                // anything else: consume inputs, produce unknown outputs
+                let (consumed, produced) = op.stack_effect();
+                for _ in 0..consumed {
+                    self.pop();
+                }
+                for _ in 0..produced {
+                    self.push(V::Unknown);
+                }
+            },
+        }
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct EvmJumpAnalysis {
+    pub entry_stacks: Vec<Vec<EvmAbstractStack>>,   // one per context, empty for blocks found unreachable
+    pub exits: Vec<EvmBlockExit>,
+}
+
+impl EvmJumpAnalysis {
+    // Data-flow fixpoint over the blocks, propagating abstract stacks along the edges that are
+    // discovered on the way. A block is analyzed separately for each distinct entry stack (up to
+    // ABSINT_MAX_CONTEXTS, then they are joined), so an internal function called from several
+    // places returns to each caller with the caller's stack, and the return address pushed by
+    // each caller resolves the returning Jump.
+    pub fn new(code: &IndexedEvmCode, blocks: &[EvmBasicBlock], opidx2block: &[usize], jumpdest_blocks: &[usize]) -> Self {
+        let mut entry_stacks: Vec<Vec<EvmAbstractStack>> = vec![Vec::new(); blocks.len()];
+        let mut collapsed = vec![false; blocks.len()];
+        let mut exits: Vec<EvmBlockExit> = blocks.iter().map(|b| b.exit.clone()).collect();
+
+        if blocks.is_empty() {
+            return Self { entry_stacks, exits };
+        }
+
+        let resolve = |val: EvmAbstractValue| {
+            match val {
+                EvmAbstractValue::Unknown => EvmJumpTarget::Dynamic,
+                EvmAbstractValue::Constants(c) => {
+                    let targets: Vec<Option<usize>> = c.iter().map(|t| {
+                        match code.target2opidx.get(t) {
+                            Some(opidx) if code.jumpdests.contains(opidx) => Some(opidx2block[*opidx]),
+                            _ => None,
+                        }
+                    }).collect();
+
+                    if targets.iter().all(|t| t.is_none()) {
+                        EvmJumpTarget::Invalid
+                    } else if targets.iter().any(|t| t.is_none()) {
+                        // partially invalid, leave the check to runtime
+                        EvmJumpTarget::Dynamic
+                    } else {
+                        EvmJumpTarget::Static(targets.into_iter().flatten().collect())
+                    }
+                },
+            }
+        };
+
+        // targets of the same jump in different contexts
+        let merge = |a: EvmJumpTarget, b: EvmJumpTarget| {
+            use EvmJumpTarget::*;
+
+            match (a, b) {
+                (Static(mut a), Static(b)) => {
+                    a.extend(b);
+                    a.sort();
+                    a.dedup();
+                    Static(a)
+                },
+                (Invalid, Invalid) => Invalid,
+                _ => Dynamic,
+            }
+        };
+
+        // across blocks, only keep constants that can be jump targets (the others, e.g. loop
+        // counters, would otherwise create a new context for every iteration)
+        let is_jumpdest = |t: &U256| {
+            match code.target2opidx.get(t) {
+                Some(opidx) => code.jumpdests.contains(opidx),
+                None => false,
+            }
+        };
+        let normalize = |mut stack: EvmAbstractStack| {
+            if stack.elements.len() > ABSINT_MAX_DEPTH {
+                stack.elements.drain(..stack.elements.len()-ABSINT_MAX_DEPTH);
+            }
+            for val in stack.elements.iter_mut() {
+                if let EvmAbstractValue::Constants(c) = val {
+                    if !c.iter().all(is_jumpdest) {
+                        *val = EvmAbstractValue::Unknown;
+                    }
+                }
+            }
+            stack
+        };
+
+        entry_stacks[0].push(EvmAbstractStack::new_empty());
+        let mut worklist = BTreeSet::from([0]);
+
+        while let Some(b) = worklist.pop_first() {
+            let ops = &code.code.ops[blocks[b].ops.clone()];
+            let mut exit: Option<EvmBlockExit> = None;
+            let mut outgoing: Vec<(usize, EvmAbstractStack)> = Vec::new();
+
+            for entry_stack in entry_stacks[b].iter() {
+                let mut stack = entry_stack.clone();
+
+                for op in ops[..ops.len()-1].iter() {
+                    stack.execute(op);
+                }
+
+                let this_exit = match ops.last().unwrap() {
+                    EvmOp::Jump => {
+                        let target = stack.pop();
+                        EvmBlockExit::Jump(resolve(target))
+                    },
+                    EvmOp::Jumpi => {
+                        let target = stack.pop();
+                        stack.pop();
+                        EvmBlockExit::Jumpi(resolve(target))
+                    },
+                    op => {
+                        stack.execute(op);
+                        blocks[b].exit.clone()
+                    },
+                };
+
+                match &this_exit {
+                    EvmBlockExit::Jump(target) | EvmBlockExit::Jumpi(target) => {
+                        match target {
+                            EvmJumpTarget::Static(t) => outgoing.extend(t.iter().map(|s| (*s, stack.clone()))),
+                            EvmJumpTarget::Invalid => {},
+                            EvmJumpTarget::Dynamic => outgoing.extend(jumpdest_blocks.iter().map(|s| (*s, stack.clone()))),
+                        }
+                    },
+                    _ => {},
+                }
+                match &this_exit {
+                    EvmBlockExit::Fallthrough | EvmBlockExit::Jumpi(_) if b+1 < blocks.len() => {
+                        outgoing.push((b+1, stack.clone()));
+                    },
+                    _ => {},
+                }
+
+                exit = Some(match (exit, this_exit) {
+                    (None, e) => e,
+                    (Some(EvmBlockExit::Jump(a)), EvmBlockExit::Jump(b)) => EvmBlockExit::Jump(merge(a, b)),
+                    (Some(EvmBlockExit::Jumpi(a)), EvmBlockExit::Jumpi(b)) => EvmBlockExit::Jumpi(merge(a, b)),
+                    (Some(e), _) => e,
+                });
+            }
+
+            exits[b] = exit.unwrap();
+
+            for (s, stack) in outgoing {
+                let stack = normalize(stack);
+                if entry_stacks[s].contains(&stack) {
+                    continue;
+                }
+
+                if collapsed[s] || entry_stacks[s].len() == ABSINT_MAX_CONTEXTS {
+                    // too many contexts, fall back to a single joined entry stack
+                    let joined = entry_stacks[s].iter().fold(stack, |acc, e| acc.join(e));
+                    if entry_stacks[s] != vec![joined.clone()] {
+                        entry_stacks[s] = vec![joined];
+                        worklist.insert(s);
+                    }
+                    collapsed[s] = true;
+                } else {
+                    entry_stacks[s].push(stack);
+                    worklist.insert(s);
+                }
+            }
+        }
+
+        Self { entry_stacks, exits }
+    }
+}
@@ -0,0 +1,76 @@
+use primitive_types::U256;
+use crate::code::EvmCode;
+use crate::code::cfg::{EvmBlockExit, EvmJumpTarget};
+
+
+#[test]
+fn absint_internal_function_returns() {
+    use crate::code::EvmOp::*;
+
+    let code = EvmCode { ops: vec![
+        Push(1, U256::zero() + 5),    // return address
+        Push(1, U256::zero() + 13),   // call FUNC
+        Jump,
+        Jumpdest,                     // byte 5
+        Push(1, U256::zero() + 11),   // return address
+        Push(1, U256::zero() + 13),   // call FUNC
+        Jump,
+        Jumpdest,                     // byte 11
+        Stop,
+        Jumpdest,                     // FUNC: byte 13
+        Push(1, U256::one()),
+        Pop,
+        Jump,                         // return
+    ]}.index();
+    let cfg = code.cfg();
+
+    assert_eq!(cfg.blocks.len(), 4);
+    assert_eq!(cfg.blocks[0].exit, EvmBlockExit::Jump(EvmJumpTarget::Static(vec![3])));
+    assert_eq!(cfg.blocks[1].exit, EvmBlockExit::Jump(EvmJumpTarget::Static(vec![3])));
+    assert_eq!(cfg.blocks[3].exit, EvmBlockExit::Jump(EvmJumpTarget::Static(vec![1, 2])));
+    assert!(cfg.dynamic_jumps().is_empty());
+    assert_eq!(cfg.blocks[3].predecessors, vec![0, 1]);
+}
+
+#[test]
+fn absint_stack_shuffling_and_folding() {
+    use crate::code::EvmOp::*;
+
+    let code = EvmCode { ops: vec![
+        Push(1, U256::zero() + 5),    // target, pushed early
+        Callvalue,
+        Swap1,
+        Jump,
+        Jumpdest,                     // byte 5
+        Pop,
+        Push(1, U256::zero() + 10),
+        Push(1, U256::zero() + 3),
+        Add,
+        Jump,                         // to byte 10+3
+        Jumpdest,                     // byte 13
+        Stop,
+    ]}.index();
+    let cfg = code.cfg();
+
+    assert_eq!(cfg.blocks.len(), 3);
+    assert_eq!(cfg.blocks[0].exit, EvmBlockExit::Jump(EvmJumpTarget::Static(vec![1])));
+    assert_eq!(cfg.blocks[1].exit, EvmBlockExit::Jump(EvmJumpTarget::Static(vec![2])));
+    assert_eq!(cfg.reachable(), vec![true; 3]);
+}
+
+#[test]
+fn absint_unknown_target() {
+    use crate::code::EvmOp::*;
+
+    let code = EvmCode { ops: vec![
+        Push(1, U256::zero()),
+        Calldataload,
+        Jump,
+        Jumpdest,
+        Stop,
+    ]}.index();
+    let cfg = code.cfg();
+
+    assert_eq!(cfg.blocks[0].exit, EvmBlockExit::Jump(EvmJumpTarget::Dynamic));
+    assert_eq!(cfg.dynamic_jumps(), vec![0]);
+}
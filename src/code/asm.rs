@@ -0,0 +1,235 @@
+use thiserror::Error;
+use primitive_types::U256;
+use std::collections::HashMap;
+use crate::code::{EvmCode, EvmOp, EvmOpParserMode};
+
+#[cfg(test)]
+mod test;
+
+
+// Assembler for a textual form of EVM code, e.g.
+//
+//     PUSH 5            // PUSH with the smallest size that fits the value
+//     loop:             // label, the following instruction should be a JUMPDEST
+//     JUMPDEST
+//     PUSH1 1 SWAP1 SUB
+//     DUP1 PUSH @loop   // label reference, sized to fit the label's offset
+//     JUMPI
+//     STOP
+//     DATA 0xdeadbeef   // raw bytes, as a Data op
+//
+// Line breaks are not significant and comments start with `//` or `;`, so the same text can
+// be written inline with `evm_asm!`, except for `;` comments: `stringify!` puts everything on
+// one line, the comment would swallow the rest of it, so the macro rejects `;`.
+
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum EvmAsmError {
+    #[error("assembler error: line {0}: unknown mnemonic {1}")]
+    UnknownMnemonic(usize, String),
+    #[error("assembler error: line {0}: {1} needs an immediate")]
+    MissingImmediate(usize, String),
+    #[error("assembler error: line {0}: invalid immediate {1}")]
+    InvalidImmediate(usize, String),
+    #[error("assembler error: line {0}: immediate {1} does not fit")]
+    ImmediateTooLarge(usize, String),
+    #[error("assembler error: line {0}: label {1} defined twice")]
+    DuplicateLabel(usize, String),
+    #[error("assembler error: line {0}: undefined label {1}")]
+    UndefinedLabel(usize, String),
+    #[error("assembler error: `;` comments are not supported inline, use `//`")]
+    InlineSemicolon,
+}
+
+
+#[derive(Debug, Clone)]
+enum EvmAsmItem {
+    Op(EvmOp),
+    PushLabel(Option<usize>, String, usize),   // fixed size (or auto-sized), label, line
+    Data(Vec<u8>),
+}
+
+impl EvmAsmItem {
+    fn len(&self, label_sizes: &HashMap<usize, usize>, idx: usize) -> usize {
+        match self {
+            EvmAsmItem::Op(op) => op.len(),
+            EvmAsmItem::PushLabel(size, _, _) => 1 + size.unwrap_or_else(|| label_sizes[&idx]),
+            EvmAsmItem::Data(data) => data.len(),
+        }
+    }
+}
+
+
+// tokens with the line they are on, comments removed
+fn tokenize(src: &str) -> Vec<(usize, String)> {
+    let mut tokens = Vec::new();
+
+    for (line, text) in src.lines().enumerate() {
+        let text = text.split("//").next().unwrap().split(';').next().unwrap();
+        // `stringify!` may separate `@` and `:` from the label
+        let text = text.replace('@', " @ ").replace(':', " : ");
+        let mut words = text.split_whitespace().peekable();
+
+        while let Some(word) = words.next() {
+            let token = match word {
+                "@" => format!("@{}", words.next().unwrap_or("")),
+                _ if words.peek() == Some(&":") => {
+                    words.next();
+                    format!("{}:", word)
+                },
+                _ => word.to_string(),
+            };
+            tokens.push((line + 1, token));
+        }
+    }
+
+    tokens
+}
+
+fn parse_immediate(line: usize, s: &str) -> Result<U256, EvmAsmError> {
+    let parsed = match s.strip_prefix("0x") {
+        Some(digits) => U256::from_str_radix(digits, 16).ok(),
+        None => U256::from_dec_str(s).ok(),
+    };
+    parsed.ok_or_else(|| EvmAsmError::InvalidImmediate(line, s.to_string()))
+}
+
+// bytes needed for the value, at least one
+fn immediate_size(val: &U256) -> usize {
+    ((val.bits() + 7) / 8).max(1)
+}
+
+// mnemonics of all ops without immediate, see EvmOp::name
+fn mnemonics() -> HashMap<String, EvmOp> {
+    let mut mnemonics = HashMap::new();
+    for opcode in 0..=255u8 {
+        if let Ok((op, _)) = EvmOp::new_from_bytes(&[opcode], EvmOpParserMode::Strict) {
+            mnemonics.insert(op.name(), op);
+        }
+    }
+    mnemonics.insert("SHA3".to_string(), EvmOp::Sha3);
+    mnemonics
+}
+
+
+impl EvmCode {
+    pub fn new_from_asm(src: &str) -> Result<Self, EvmAsmError> {
+        let mnemonics = mnemonics();
+        let mut items = Vec::new();
+        let mut labels: HashMap<String, usize> = HashMap::new();   // label -> item index
+
+        // PARSE
+
+        let mut tokens = tokenize(src).into_iter();
+        while let Some((line, token)) = tokens.next() {
+            let upper = token.to_uppercase();
+
+            if let Some(label) = token.strip_suffix(':') {
+                if labels.insert(label.to_string(), items.len()).is_some() {
+                    return Err(EvmAsmError::DuplicateLabel(line, label.to_string()));
+                }
+            } else if upper == "PUSH" || (upper.starts_with("PUSH") && upper[4..].parse::<usize>().is_ok()) {
+                let size = match upper[4..].parse::<usize>() {
+                    Ok(size) if (1..=32).contains(&size) => Some(size),
+                    Ok(_) => return Err(EvmAsmError::UnknownMnemonic(line, token)),
+                    Err(_) => None,
+                };
+                let (_, immediate) = tokens.next().ok_or_else(|| EvmAsmError::MissingImmediate(line, token.clone()))?;
+
+                if let Some(label) = immediate.strip_prefix('@') {
+                    items.push(EvmAsmItem::PushLabel(size, label.to_string(), line));
+                } else {
+                    let val = parse_immediate(line, &immediate)?;
+                    let size = size.unwrap_or_else(|| immediate_size(&val));
+                    if immediate_size(&val) > size {
+                        return Err(EvmAsmError::ImmediateTooLarge(line, immediate));
+                    }
+                    items.push(EvmAsmItem::Op(EvmOp::Push(size, val)));
+                }
+            } else if upper == "DATA" {
+                let (_, data) = tokens.next().ok_or_else(|| EvmAsmError::MissingImmediate(line, token.clone()))?;
+                let bytes = hex::decode(data.trim_start_matches("0x")).map_err(|_| EvmAsmError::InvalidImmediate(line, data.clone()))?;
+                if !bytes.is_empty() {
+                    items.push(EvmAsmItem::Data(bytes));
+                }
+            } else {
+                let op = mnemonics.get(&upper).ok_or(EvmAsmError::UnknownMnemonic(line, token))?;
+                items.push(EvmAsmItem::Op(op.clone()));
+            }
+        }
+
+        // LAYOUT
+
+        // grow auto-sized label references until all offsets fit (sizes only increase,
+        // so this terminates)
+        let mut label_sizes: HashMap<usize, usize> = items.iter().enumerate()
+            .filter(|(_, item)| matches!(item, EvmAsmItem::PushLabel(None, _, _)))
+            .map(|(idx, _)| (idx, 1))
+            .collect();
+        let offsets = loop {
+            let mut offsets = Vec::with_capacity(items.len() + 1);
+            let mut offset = 0;
+            for (idx, item) in items.iter().enumerate() {
+                offsets.push(offset);
+                offset += item.len(&label_sizes, idx);
+            }
+            offsets.push(offset);   // for labels at the end
+
+            let mut grown = false;
+            for (idx, item) in items.iter().enumerate() {
+                if let EvmAsmItem::PushLabel(None, label, _) = item {
+                    if let Some(target) = labels.get(label) {
+                        let size = immediate_size(&(U256::zero() + offsets[*target]));
+                        if size > label_sizes[&idx] {
+                            label_sizes.insert(idx, size);
+                            grown = true;
+                        }
+                    }
+                }
+            }
+            if !grown {
+                break offsets;
+            }
+        };
+
+        // EMIT
+
+        let mut ops = Vec::new();
+        for (idx, item) in items.into_iter().enumerate() {
+            match item {
+                EvmAsmItem::Op(op) => ops.push(op),
+                EvmAsmItem::PushLabel(size, label, line) => {
+                    let target = labels.get(&label).ok_or_else(|| EvmAsmError::UndefinedLabel(line, label.clone()))?;
+                    let val = U256::zero() + offsets[*target];
+                    let size = size.unwrap_or_else(|| label_sizes[&idx]);
+                    if immediate_size(&val) > size {
+                        return Err(EvmAsmError::ImmediateTooLarge(line, format!("@{}", label)));
+                    }
+                    ops.push(EvmOp::Push(size, val));
+                },
+                EvmAsmItem::Data(bytes) => ops.push(EvmOp::Data(bytes)),
+            }
+        }
+
+        Ok(Self { ops })
+    }
+
+    // assembly as stringified by `evm_asm!`, all on one line
+    pub fn new_from_asm_inline(src: &str) -> Result<Self, EvmAsmError> {
+        if src.contains(';') {
+            return Err(EvmAsmError::InlineSemicolon);
+        }
+        Self::new_from_asm(src)
+    }
+}
+
+
+// EvmCode from assembly written inline, panics if it does not assemble:
+//
+//     let code = evm_asm!(PUSH 1 loop: JUMPDEST PUSH @loop JUMP);
+#[macro_export]
+macro_rules! evm_asm {
+    ($($t:tt)*) => {
+        $crate::code::EvmCode::new_from_asm_inline(stringify!($($t)*)).unwrap()
+    };
+}
@@ -0,0 +1,87 @@
+use primitive_types::U256;
+use crate::code::EvmCode;
+use crate::code::EvmOp::*;
+use crate::code::asm::EvmAsmError;
+use crate::test_data;
+
+
+#[test]
+fn asm_fibonacci() {
+    // same program as the hand-written one, with offsets computed by the assembler
+    let code = EvmCode::new_from_asm("
+        PUSH 5998          // which fib number we want
+        PUSH 0
+        PUSH 1
+
+        mainloop:
+        JUMPDEST
+        DUP3 ISZERO PUSH @cleanup JUMPI
+        DUP2 DUP2 ADD SWAP2 POP SWAP1
+        SWAP2 PUSH1 1 SWAP1 SUB SWAP2
+        PUSH @mainloop JUMP
+
+        cleanup:
+        JUMPDEST
+        SWAP2 POP POP      ; requested fib number is the only element on the stack
+        STOP
+    ").unwrap();
+
+    assert_eq!(code.ops, test_data::get_code_ops_fibonacci());
+}
+
+#[test]
+fn asm_macro_labels_and_data() {
+    let code = crate::evm_asm!(
+        PUSH1 0x02 PUSH @end JUMPI
+        DATA 0xfe00
+        end: JUMPDEST
+        push2 @end
+        keccak256 SHA3
+    );
+
+    assert_eq!(code.ops, vec![
+        Push(1, U256::zero() + 2),
+        Push(1, U256::zero() + 7),
+        Jumpi,
+        Data(vec![0xfe, 0x00]),
+        Jumpdest,
+        Push(2, U256::zero() + 7),
+        Sha3,
+        Sha3,
+    ]);
+    assert_eq!(hex::encode(code.to_bytes()), "6002600757fe005b6100072020");
+}
+
+#[test]
+fn asm_label_references_grow() {
+    // the label is beyond 255 only once its own reference is two bytes
+    let mut src = String::from("PUSH @end JUMP ");
+    src.push_str(&"JUMPDEST ".repeat(253));
+    src.push_str("end: JUMPDEST");
+    let code = EvmCode::new_from_asm(&src).unwrap();
+
+    assert_eq!(code.ops[0], Push(2, U256::zero() + 257));
+    assert_eq!(code.to_bytes()[257], 0x5b);
+
+    // fixed-size references do not grow
+    let src = format!("PUSH1 @end {} end:", "JUMPDEST ".repeat(300));
+    assert_eq!(EvmCode::new_from_asm(&src).unwrap_err(), EvmAsmError::ImmediateTooLarge(1, "@end".to_string()));
+}
+
+#[test]
+fn asm_errors() {
+    assert_eq!(EvmCode::new_from_asm("PUSH1 1\nFOO").unwrap_err(), EvmAsmError::UnknownMnemonic(2, "FOO".to_string()));
+    assert_eq!(EvmCode::new_from_asm("PUSH1 256").unwrap_err(), EvmAsmError::ImmediateTooLarge(1, "256".to_string()));
+    assert_eq!(EvmCode::new_from_asm("PUSH").unwrap_err(), EvmAsmError::MissingImmediate(1, "PUSH".to_string()));
+    assert_eq!(EvmCode::new_from_asm("PUSH 0xzz").unwrap_err(), EvmAsmError::InvalidImmediate(1, "0xzz".to_string()));
+    assert_eq!(EvmCode::new_from_asm("a: a: STOP").unwrap_err(), EvmAsmError::DuplicateLabel(1, "a".to_string()));
+    assert_eq!(EvmCode::new_from_asm("PUSH @nowhere").unwrap_err(), EvmAsmError::UndefinedLabel(1, "nowhere".to_string()));
+}
+
+#[test]
+fn asm_macro_rejects_semicolon_comments() {
+    // on one line, everything after the `;` would be a comment
+    assert_eq!(EvmCode::new_from_asm_inline(stringify!(PUSH 1 ; one STOP)).unwrap_err(), EvmAsmError::InlineSemicolon);
+    assert_eq!(EvmCode::new_from_asm("PUSH 1 ; one\nSTOP").unwrap().ops, vec![Push(1, U256::one()), Stop]);
+    assert!(std::panic::catch_unwind(|| crate::evm_asm!(PUSH 1 ; one STOP)).is_err());
+}
@@ -0,0 +1,432 @@
+use std::ops::Range;
+use std::collections::BTreeSet;
+use primitive_types::U256;
+use std::fmt::Write;
+use crate::code::{EvmOp, IndexedEvmCode};
+use crate::code::absint::EvmJumpAnalysis;
+
+#[cfg(test)]
+mod test;
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvmJumpTarget {
+    Static(Vec<usize>),   // indices of the blocks starting with the (valid) Jumpdests that can be jumped to (or relative jump targets)
+    Invalid,              // constant target that is not a Jumpdest, jump fails at runtime
+    Dynamic,              // target only known at runtime
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvmBlockExit {
+    Halt,                    // Stop, Return, Revert, Invalid, unknown instruction (and Retf, Jumpf)
+    Fallthrough,             // continues with the next block (or runs off the end of the code)
+    Jump(EvmJumpTarget),     // also Rjump
+    Jumpi(EvmJumpTarget),    // falls through to the next block if the condition is zero (or the Rjumpv index out of range)
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvmBasicBlock {
+    pub ops: Range<usize>,       // op indices (into IndexedEvmCode::code.ops) covered by this block
+    pub offsets: Range<usize>,   // byte offsets (into the serialized code) covered by this block
+    pub exit: EvmBlockExit,
+    pub successors: Vec<usize>,
+    pub predecessors: Vec<usize>,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvmLoop {
+    pub header: usize,
+    pub latches: Vec<usize>,       // blocks with a back edge to the header
+    pub blocks: BTreeSet<usize>,   // natural loop body, including header and latches
+}
+
+
+#[derive(Debug, Clone)]
+pub struct EvmCfg {
+    pub blocks: Vec<EvmBasicBlock>,
+    pub opidx2block: Vec<usize>,
+    pub jumpdest_blocks: Vec<usize>,   // blocks starting with a Jumpdest, in code order
+}
+
+impl EvmCfg {
+    pub fn new_from_indexedcode(code: &IndexedEvmCode) -> Self {
+        let ops = &code.code.ops;
+
+        // PARTITION INTO BLOCKS
+
+        let relative_targets: BTreeSet<usize> = (0..ops.len())
+            .flat_map(|opidx| Self::relative_targets(code, opidx))
+            .flatten()
+            .collect();
+
+        let mut ranges = Vec::new();
+        let mut opidx2block = Vec::with_capacity(ops.len());
+
+        let mut start = 0;
+        for opidx in 0..ops.len() {
+            // a Jumpdest (or target of a relative jump) always starts a new block (unless the
+            // block is still empty) ...
+            if (ops[opidx] == EvmOp::Jumpdest || relative_targets.contains(&opidx)) && opidx > start {
+                ranges.push(start..opidx);
+                start = opidx;
+            }
+
+            opidx2block.push(ranges.len());
+
+            // ... and jumps, terminators and invalid instructions end the current one
+            if ops[opidx].is_block_terminator() {
+                ranges.push(start..opidx+1);
+                start = opidx + 1;
+            }
+        }
+
+        if start < ops.len() {
+            ranges.push(start..ops.len());
+        }
+
+        let jumpdest_blocks: Vec<usize> = ranges.iter().enumerate()
+            .filter(|(_, r)| ops[r.start] == EvmOp::Jumpdest)
+            .map(|(b, _)| b)
+            .collect();
+
+        // BLOCK EXITS AND EDGES
+
+        let mut blocks: Vec<EvmBasicBlock> = Vec::with_capacity(ranges.len());
+        let mut offset = 0;
+        for ops_range in ranges {
+            let offsets_start = offset;
+            for opidx in ops_range.clone() {
+                offset += ops[opidx].len();
+            }
+
+            let exit = Self::resolve_exit(code, &opidx2block, ops_range.clone());
+
+            blocks.push(EvmBasicBlock {
+                ops: ops_range,
+                offsets: offsets_start..offset,
+                exit,
+                successors: Vec::new(),
+                predecessors: Vec::new(),
+            });
+        }
+
+        // refine jump targets by abstract interpretation (unreachable blocks keep the above)
+        let analysis = EvmJumpAnalysis::new(code, &blocks, &opidx2block, &jumpdest_blocks);
+        for (block, exit) in blocks.iter_mut().zip(analysis.exits.into_iter()) {
+            block.exit = exit;
+        }
+
+        let blocks_len = blocks.len();
+        for b in 0..blocks_len {
+            let mut successors = Vec::new();
+
+            let mut add_jump = |target: &EvmJumpTarget| {
+                match target {
+                    EvmJumpTarget::Static(t) => successors.extend(t.iter()),
+                    EvmJumpTarget::Invalid => {},
+                    // conservatively, a dynamic jump can reach every Jumpdest
+                    EvmJumpTarget::Dynamic => successors.extend(jumpdest_blocks.iter()),
+                }
+            };
+
+            match &blocks[b].exit {
+                EvmBlockExit::Halt => {},
+                EvmBlockExit::Fallthrough => {},
+                EvmBlockExit::Jump(target) => add_jump(target),
+                EvmBlockExit::Jumpi(target) => add_jump(target),
+            }
+
+            match &blocks[b].exit {
+                EvmBlockExit::Fallthrough | EvmBlockExit::Jumpi(_) if b+1 < blocks_len => {
+                    successors.push(b+1);
+                },
+                _ => {},
+            }
+
+            successors.sort();
+            successors.dedup();
+
+            for s in successors.iter() {
+                blocks[*s].predecessors.push(b);
+            }
+            blocks[b].successors = successors;
+        }
+
+        Self { blocks, opidx2block, jumpdest_blocks }
+    }
+
+    // op indices a relative jump (EOF) can continue at, None for targets that are not the
+    // start of an instruction
+    fn relative_targets(code: &IndexedEvmCode, opidx: usize) -> Vec<Option<usize>> {
+        let op = &code.code.ops[opidx];
+        let next = code.opidx2target[&opidx].as_usize() + op.len();
+        let resolve = |rel: &i16| {
+            let target = next as isize + *rel as isize;
+            if target < 0 {
+                return None;
+            }
+            code.target2opidx.get(&(U256::zero() + target as usize)).copied()
+        };
+
+        match op {
+            EvmOp::Rjump(rel) | EvmOp::Rjumpi(rel) => vec![resolve(rel)],
+            EvmOp::Rjumpv(rels) => rels.iter().map(resolve).collect(),
+            _ => vec![],
+        }
+    }
+
+    fn resolve_exit(code: &IndexedEvmCode, opidx2block: &[usize], ops_range: Range<usize>) -> EvmBlockExit {
+        use EvmOp::*;
+
+        let ops = &code.code.ops[ops_range.clone()];
+
+        let resolve_target = |val| {
+            match code.target2opidx.get(val) {
+                Some(opidx) if code.jumpdests.contains(opidx) => EvmJumpTarget::Static(vec![opidx2block[*opidx]]),
+                _ => EvmJumpTarget::Invalid,
+            }
+        };
+
+        // constant target pushed right before the jump (not yet augmented)
+        let pushed_target = if ops.len() >= 2 {
+            match &ops[ops.len()-2] {
+                Push(_, val) => resolve_target(val),
+                _ => EvmJumpTarget::Dynamic,
+            }
+        } else {
+            EvmJumpTarget::Dynamic
+        };
+
+        match ops.last() {
+            Some(Jump) => EvmBlockExit::Jump(pushed_target),
+            Some(Jumpi) => EvmBlockExit::Jumpi(pushed_target),
+            Some(AugmentedPushJump(_, val)) => EvmBlockExit::Jump(resolve_target(val)),
+            Some(AugmentedPushJumpi(_, val)) => EvmBlockExit::Jumpi(resolve_target(val)),
+            Some(AugmentedDupIszeroPushJumpi(_, _, val) | AugmentedComparePushJumpi(_, _, val)) => EvmBlockExit::Jumpi(resolve_target(val)),
+            Some(Rjump(_) | Rjumpi(_) | Rjumpv(_)) => {
+                let targets: Option<Vec<usize>> = Self::relative_targets(code, ops_range.end - 1).into_iter()
+                    .map(|t| t.map(|opidx| opidx2block[opidx]))
+                    .collect();
+                let target = match targets {
+                    Some(t) => EvmJumpTarget::Static(t),
+                    None => EvmJumpTarget::Invalid,
+                };
+                match ops.last() {
+                    Some(Rjump(_)) => EvmBlockExit::Jump(target),
+                    _ => EvmBlockExit::Jumpi(target),
+                }
+            },
+            Some(op) if op.is_block_terminator() => EvmBlockExit::Halt,
+            _ => EvmBlockExit::Fallthrough,
+        }
+    }
+
+    pub fn dynamic_jumps(&self) -> Vec<usize> {
+        self.blocks.iter().enumerate()
+            .filter(|(_, block)| matches!(block.exit, EvmBlockExit::Jump(EvmJumpTarget::Dynamic) | EvmBlockExit::Jumpi(EvmJumpTarget::Dynamic)))
+            .map(|(b, _)| b)
+            .collect()
+    }
+
+
+    // ANALYSES
+
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut worklist = Vec::new();
+
+        if !self.blocks.is_empty() {
+            reachable[0] = true;
+            worklist.push(0);
+        }
+
+        while let Some(b) = worklist.pop() {
+            for s in self.blocks[b].successors.iter() {
+                if !reachable[*s] {
+                    reachable[*s] = true;
+                    worklist.push(*s);
+                }
+            }
+        }
+
+        reachable
+    }
+
+    fn reverse_postorder(&self) -> Vec<usize> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut postorder = Vec::with_capacity(self.blocks.len());
+
+        if self.blocks.is_empty() {
+            return postorder;
+        }
+
+        // iterative DFS, the stack holds (block, index of next successor to visit)
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((b, i)) = stack.pop() {
+            if i < self.blocks[b].successors.len() {
+                stack.push((b, i+1));
+                let s = self.blocks[b].successors[i];
+                if !visited[s] {
+                    visited[s] = true;
+                    stack.push((s, 0));
+                }
+            } else {
+                postorder.push(b);
+            }
+        }
+
+        postorder.reverse();
+        postorder
+    }
+
+    // immediate dominators (Cooper, Harvey, Kennedy: "A Simple, Fast Dominance Algorithm"),
+    // the entry block is its own immediate dominator, unreachable blocks have none
+    pub fn dominators(&self) -> Vec<Option<usize>> {
+        let mut idom: Vec<Option<usize>> = vec![None; self.blocks.len()];
+
+        let rpo = self.reverse_postorder();
+        let mut rpo_idx = vec![usize::MAX; self.blocks.len()];
+        for (i, b) in rpo.iter().enumerate() {
+            rpo_idx[*b] = i;
+        }
+
+        if rpo.is_empty() {
+            return idom;
+        }
+        idom[0] = Some(0);
+
+        let intersect = |idom: &Vec<Option<usize>>, mut a: usize, mut b: usize| {
+            while a != b {
+                while rpo_idx[a] > rpo_idx[b] {
+                    a = idom[a].unwrap();
+                }
+                while rpo_idx[b] > rpo_idx[a] {
+                    b = idom[b].unwrap();
+                }
+            }
+            a
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for b in rpo.iter().skip(1) {
+                let mut new_idom = None;
+                for p in self.blocks[*b].predecessors.iter() {
+                    if idom[*p].is_none() {
+                        continue;
+                    }
+                    new_idom = match new_idom {
+                        None => Some(*p),
+                        Some(d) => Some(intersect(&idom, *p, d)),
+                    };
+                }
+                if idom[*b] != new_idom {
+                    idom[*b] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        idom
+    }
+
+    pub fn dominates(idom: &[Option<usize>], a: usize, b: usize) -> bool {
+        let mut b = b;
+        loop {
+            if a == b {
+                return true;
+            }
+            match idom[b] {
+                Some(d) if d != b => { b = d; },
+                _ => { return false; },
+            }
+        }
+    }
+
+    // natural loops, identified by back edges to a dominating header
+    pub fn loops(&self) -> Vec<EvmLoop> {
+        let idom = self.dominators();
+        let mut loops: Vec<EvmLoop> = Vec::new();
+
+        for (b, block) in self.blocks.iter().enumerate() {
+            if idom[b].is_none() {
+                continue;
+            }
+
+            for h in block.successors.iter() {
+                if !Self::dominates(&idom, *h, b) {
+                    continue;
+                }
+
+                // collect body by walking backwards from the latch until the header
+                let mut body = BTreeSet::new();
+                body.insert(*h);
+                let mut worklist = vec![b];
+                while let Some(n) = worklist.pop() {
+                    if body.insert(n) {
+                        worklist.extend(self.blocks[n].predecessors.iter().filter(|p| idom[**p].is_some()));
+                    }
+                }
+
+                match loops.iter_mut().find(|l| l.header == *h) {
+                    Some(l) => {
+                        l.latches.push(b);
+                        l.blocks.extend(body);
+                    },
+                    None => {
+                        loops.push(EvmLoop { header: *h, latches: vec![b], blocks: body });
+                    },
+                }
+            }
+        }
+
+        loops.sort_by_key(|l| l.header);
+        loops
+    }
+
+
+    // EXPORT
+
+    pub fn to_dot(&self, code: &IndexedEvmCode) -> String {
+        let reachable = self.reachable();
+        let mut out = String::new();
+
+        writeln!(out, "digraph cfg {{").unwrap();
+        writeln!(out, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+        for (b, block) in self.blocks.iter().enumerate() {
+            let mut label = format!("Block #{}\\l", b);
+            let mut offset = block.offsets.start;
+            for opidx in block.ops.clone() {
+                let op = &code.code.ops[opidx];
+                write!(label, "{:#06x}: {:?}\\l", offset, op).unwrap();
+                offset += op.len();
+            }
+
+            let style = if reachable[b] { "solid" } else { "dashed" };
+            writeln!(out, "    b{} [label=\"{}\", style={}];", b, label.replace('"', "\\\""), style).unwrap();
+        }
+
+        for (b, block) in self.blocks.iter().enumerate() {
+            let dynamic = matches!(block.exit, EvmBlockExit::Jump(EvmJumpTarget::Dynamic) | EvmBlockExit::Jumpi(EvmJumpTarget::Dynamic));
+            for s in block.successors.iter() {
+                let fallthrough = *s == b+1 && matches!(block.exit, EvmBlockExit::Fallthrough | EvmBlockExit::Jumpi(_));
+                let style = if dynamic && !fallthrough { "dotted" } else { "solid" };
+                writeln!(out, "    b{} -> b{} [style={}];", b, s, style).unwrap();
+            }
+        }
+
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+impl IndexedEvmCode {
+    pub fn cfg(&self) -> EvmCfg {
+        EvmCfg::new_from_indexedcode(self)
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::BTreeSet;
+use primitive_types::U256;
+use crate::code::EvmCode;
+use crate::code::cfg::{EvmBlockExit, EvmJumpTarget};
+use crate::test_data;
+
+
+#[test]
+fn cfg_fibonacci() {
+    let code = EvmCode { ops: test_data::get_code_ops_fibonacci() }.index();
+    let cfg = code.cfg();
+
+    assert_eq!(cfg.blocks.len(), 4);
+    assert_eq!(cfg.blocks.iter().map(|b| b.ops.clone()).collect::<Vec<_>>(), vec![0..3, 3..8, 8..21, 21..26]);
+    assert_eq!(cfg.blocks.iter().map(|b| b.offsets.clone()).collect::<Vec<_>>(), vec![0..7, 7..13, 13..28, 28..33]);
+    assert_eq!(cfg.jumpdest_blocks, vec![1, 3]);
+
+    assert_eq!(cfg.blocks[0].exit, EvmBlockExit::Fallthrough);
+    assert_eq!(cfg.blocks[1].exit, EvmBlockExit::Jumpi(EvmJumpTarget::Static(vec![3])));
+    assert_eq!(cfg.blocks[2].exit, EvmBlockExit::Jump(EvmJumpTarget::Static(vec![1])));
+    assert_eq!(cfg.blocks[3].exit, EvmBlockExit::Halt);
+
+    assert_eq!(cfg.blocks[1].successors, vec![2, 3]);
+    assert_eq!(cfg.blocks[1].predecessors, vec![0, 2]);
+    assert!(cfg.dynamic_jumps().is_empty());
+    assert_eq!(cfg.reachable(), vec![true; 4]);
+
+    // the augmented code has the same graph
+    let augmented = code.code.augment().index().cfg();
+    for (a, b) in augmented.blocks.iter().zip(cfg.blocks.iter()) {
+        assert_eq!(a.offsets, b.offsets);
+        assert_eq!(a.exit, b.exit);
+        assert_eq!(a.successors, b.successors);
+    }
+
+    assert_eq!(cfg.dominators(), vec![Some(0), Some(0), Some(1), Some(1)]);
+
+    let loops = cfg.loops();
+    assert_eq!(loops.len(), 1);
+    assert_eq!(loops[0].header, 1);
+    assert_eq!(loops[0].latches, vec![2]);
+    assert_eq!(loops[0].blocks, BTreeSet::from([1, 2]));
+
+    let dot = cfg.to_dot(&code);
+    assert!(dot.starts_with("digraph cfg {"));
+    assert!(dot.contains("b2 -> b1 [style=solid];"));
+}
+
+#[test]
+fn cfg_dynamic_and_unreachable() {
+    use crate::code::EvmOp::*;
+
+    let code = EvmCode { ops: vec![
+        Push(1, U256::zero() + 6),
+        Callvalue,
+        Add,
+        Jump,       // dynamic (target depends on input)
+        Stop,       // unreachable
+        Jumpdest,   // byte 6
+        Push(1, U256::zero() + 5),
+        Jump,       // constant, but not a Jumpdest
+        Jumpdest,   // byte 10
+        Stop,
+    ]}.index();
+    let cfg = code.cfg();
+
+    assert_eq!(cfg.blocks.len(), 4);
+    assert_eq!(cfg.blocks[0].exit, EvmBlockExit::Jump(EvmJumpTarget::Dynamic));
+    assert_eq!(cfg.blocks[0].successors, vec![2, 3]);
+    assert_eq!(cfg.blocks[2].exit, EvmBlockExit::Jump(EvmJumpTarget::Invalid));
+    assert!(cfg.blocks[2].successors.is_empty());
+    assert_eq!(cfg.dynamic_jumps(), vec![0]);
+    assert_eq!(cfg.reachable(), vec![true, false, true, true]);
+    assert_eq!(cfg.dominators(), vec![Some(0), None, Some(0), Some(0)]);
+    assert!(cfg.loops().is_empty());
+}
@@ -0,0 +1,226 @@
+use std::fmt;
+use serde_json::{json, Value};
+use crate::code::{EvmCode, EvmCodeError, EvmOp, EvmOpParserMode};
+use crate::code::cfg::{EvmBlockExit, EvmJumpTarget};
+
+#[cfg(test)]
+mod test;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvmDisasmStyle {
+    Listing,   // offsets, labels, resolved jump targets and annotations
+    Evmasm,    // `evmasm -d` (pyevmasm): offset and instruction only, data regions omitted
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvmDisasmAnnotation {
+    Unreachable,             // in a block that no path from the entry reaches
+    UnknownInstruction,      // byte that is not a defined opcode
+    InvalidJumpTarget,       // constant jump target that is not a Jumpdest
+    IncompleteInstruction,   // PUSH cut off by the end of the code
+    Data,                    // bytes after a terminator that no jump can reach
+    Metadata,                // CBOR metadata appended by solc
+}
+
+impl EvmDisasmAnnotation {
+    pub fn name(&self) -> &'static str {
+        use EvmDisasmAnnotation::*;
+
+        match self {
+            Unreachable => "unreachable",
+            UnknownInstruction => "unknown instruction",
+            InvalidJumpTarget => "invalid jump target",
+            IncompleteInstruction => "incomplete instruction",
+            Data => "data",
+            Metadata => "solidity metadata",
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvmDisasmLine {
+    pub offset: usize,
+    pub op: Option<EvmOp>,          // None for data
+    pub bytes: Vec<u8>,
+    pub jump_targets: Vec<usize>,   // offsets of the Jumpdests a Jump/Jumpi with constant target continues at
+    pub annotations: Vec<EvmDisasmAnnotation>,
+}
+
+impl EvmDisasmLine {
+    // for Jumpdests
+    pub fn label(&self) -> Option<String> {
+        match self.op {
+            Some(EvmOp::Jumpdest) => Some(label(self.offset)),
+            _ => None,
+        }
+    }
+
+    pub fn mnemonic(&self, style: EvmDisasmStyle) -> String {
+        use EvmOp::*;
+
+        match (&self.op, style) {
+            (None, _) => format!("DATA 0x{}", hex::encode(&self.bytes)),
+            (Some(Push(len, val)), _) => format!("PUSH{} {:#x}", len, val),
+            (Some(AugmentedPushJump(len, val)), _) => format!("PUSH{}JUMP {:#x}", len, val),
+            (Some(AugmentedPushJumpi(len, val)), _) => format!("PUSH{}JUMPI {:#x}", len, val),
+            (Some(Unknown(opcode)), EvmDisasmStyle::Listing) => format!("UNKNOWN {:#04x}", opcode),
+            (Some(Unknown(_)), EvmDisasmStyle::Evmasm) => "INVALID".to_string(),
+            (Some(Sha3), EvmDisasmStyle::Evmasm) => "SHA3".to_string(),
+            (Some(op), _) => op.name(),
+        }
+    }
+}
+
+fn label(offset: usize) -> String {
+    format!("L{:04x}", offset)
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvmDisassembly {
+    pub lines: Vec<EvmDisasmLine>,
+}
+
+impl EvmDisassembly {
+    pub fn new_from_bytes(b: &[u8]) -> Self {
+        // a PUSH cut off by the end of live code leaves the rest as data
+        let (code, code_end) = match EvmCode::new_from_bytes(b, EvmOpParserMode::Lax) {
+            Ok(code) => (code, b.len()),
+            Err(EvmCodeError::ParserErrorIncompleteInstruction(idx)) => {
+                (EvmCode::new_from_bytes(&b[..idx], EvmOpParserMode::Lax).expect("parses up to the incomplete instruction"), idx)
+            },
+            Err(e) => unreachable!("lax parser only fails on incomplete instructions: {}", e),
+        };
+        let has_metadata = code.metadata().is_some();
+
+        let code = code.index();
+        let cfg = code.cfg();
+        let reachable = cfg.reachable();
+
+        let mut lines = Vec::with_capacity(code.code.ops.len() + 1);
+        let mut offset = 0;
+        for (opidx, op) in code.code.ops.iter().enumerate() {
+            if let EvmOp::Data(bytes) = op {
+                let annotation = if has_metadata && opidx + 1 == code.code.ops.len() {
+                    EvmDisasmAnnotation::Metadata
+                } else {
+                    EvmDisasmAnnotation::Data
+                };
+                lines.push(EvmDisasmLine {
+                    offset,
+                    op: None,
+                    bytes: bytes.clone(),
+                    jump_targets: vec![],
+                    annotations: vec![annotation],
+                });
+                offset += op.len();
+                continue;
+            }
+
+            let b_idx = cfg.opidx2block[opidx];
+            let block = &cfg.blocks[b_idx];
+            let mut annotations = Vec::new();
+            let mut jump_targets = Vec::new();
+
+            if !reachable[b_idx] {
+                annotations.push(EvmDisasmAnnotation::Unreachable);
+            }
+            if matches!(op, EvmOp::Unknown(_)) {
+                annotations.push(EvmDisasmAnnotation::UnknownInstruction);
+            }
+            if opidx + 1 == block.ops.end {
+                match &block.exit {
+                    EvmBlockExit::Jump(EvmJumpTarget::Static(targets)) | EvmBlockExit::Jumpi(EvmJumpTarget::Static(targets)) => {
+                        jump_targets = targets.iter().map(|t| cfg.blocks[*t].offsets.start).collect();
+                    },
+                    EvmBlockExit::Jump(EvmJumpTarget::Invalid) | EvmBlockExit::Jumpi(EvmJumpTarget::Invalid) => {
+                        annotations.push(EvmDisasmAnnotation::InvalidJumpTarget);
+                    },
+                    _ => {},
+                }
+            }
+
+            lines.push(EvmDisasmLine {
+                offset,
+                op: Some(op.clone()),
+                bytes: b[offset..offset + op.len()].to_vec(),
+                jump_targets,
+                annotations,
+            });
+            offset += op.len();
+        }
+
+        if code_end < b.len() {
+            lines.push(EvmDisasmLine {
+                offset: code_end,
+                op: None,
+                bytes: b[code_end..].to_vec(),
+                jump_targets: vec![],
+                annotations: vec![EvmDisasmAnnotation::IncompleteInstruction],
+            });
+        }
+
+        Self { lines }
+    }
+
+    pub fn format(&self, style: EvmDisasmStyle) -> String {
+        let mut out = String::new();
+
+        for line in self.lines.iter() {
+            match style {
+                EvmDisasmStyle::Listing => {
+                    if let Some(label) = line.label() {
+                        out.push_str(&format!("{}:\n", label));
+                    }
+
+                    let mut comments: Vec<String> = Vec::new();
+                    if !line.jump_targets.is_empty() {
+                        comments.push(format!("-> {}", line.jump_targets.iter().map(|t| label(*t)).collect::<Vec<_>>().join(", ")));
+                    }
+                    comments.extend(line.annotations.iter().map(|a| a.name().to_string()));
+
+                    let text = format!("{:#06x}:     {}", line.offset, line.mnemonic(style));
+                    if comments.is_empty() {
+                        out.push_str(&format!("{}\n", text));
+                    } else {
+                        out.push_str(&format!("{:<40} ; {}\n", text, comments.join("; ")));
+                    }
+                },
+                EvmDisasmStyle::Evmasm => {
+                    if line.op.is_some() {
+                        out.push_str(&format!("{:08x}: {}\n", line.offset, line.mnemonic(style)));
+                    }
+                },
+            }
+        }
+
+        out
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Array(self.lines.iter().map(|line| {
+            let immediate = match &line.op {
+                Some(EvmOp::Push(_, val)) | Some(EvmOp::AugmentedPushJump(_, val)) | Some(EvmOp::AugmentedPushJumpi(_, val)) => Some(format!("{:#x}", val)),
+                _ => None,
+            };
+            json!({
+                "offset": line.offset,
+                "bytes": format!("0x{}", hex::encode(&line.bytes)),
+                "mnemonic": line.op.as_ref().map(|_| line.mnemonic(EvmDisasmStyle::Listing)),
+                "immediate": immediate,
+                "label": line.label(),
+                "jumpTargets": line.jump_targets,
+                "annotations": line.annotations.iter().map(|a| a.name()).collect::<Vec<_>>(),
+            })
+        }).collect())
+    }
+}
+
+impl fmt::Display for EvmDisassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(EvmDisasmStyle::Listing))
+    }
+}
+
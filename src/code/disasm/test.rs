@@ -0,0 +1,69 @@
+use crate::code::EvmOp;
+use crate::code::disasm::{EvmDisassembly, EvmDisasmAnnotation, EvmDisasmStyle};
+
+
+// conditional jump, a data region after STOP, invalid jump, an unreachable Jumpdest and
+// unknown instruction, a PUSH2 cut off by the metadata, and metadata {"solc": 0x00080f}
+const CODE: &str = "6001600857000c005b6003565b0c6100a164736f6c634300080f000a";
+
+
+#[test]
+fn disasm_listing() {
+    let disasm = EvmDisassembly::new_from_bytes(&hex::decode(CODE).unwrap());
+    assert_eq!(disasm.to_string().lines().collect::<Vec<_>>(), vec![
+        "0x0000:     PUSH1 0x1",
+        "0x0002:     PUSH1 0x8",
+        "0x0004:     JUMPI                        ; -> L0008",
+        "0x0005:     STOP",
+        "0x0006:     DATA 0x0c00                  ; data",
+        "L0008:",
+        "0x0008:     JUMPDEST",
+        "0x0009:     PUSH1 0x3",
+        "0x000b:     JUMP                         ; invalid jump target",
+        "L000c:",
+        "0x000c:     JUMPDEST                     ; unreachable",
+        "0x000d:     UNKNOWN 0x0c                 ; unreachable; unknown instruction",
+        "0x000e:     DATA 0x6100                  ; data",
+        "0x0010:     DATA 0xa164736f6c634300080f000a ; solidity metadata",
+    ]);
+}
+
+#[test]
+fn disasm_lines_and_json() {
+    let disasm = EvmDisassembly::new_from_bytes(&hex::decode(CODE).unwrap());
+    assert_eq!(disasm.lines.len(), 12);
+    assert_eq!(disasm.lines[2].op, Some(EvmOp::Jumpi));
+    assert_eq!(disasm.lines[2].jump_targets, vec![8]);
+    assert_eq!(disasm.lines[4].annotations, vec![EvmDisasmAnnotation::Data]);
+    assert_eq!(disasm.lines[9].annotations, vec![EvmDisasmAnnotation::Unreachable, EvmDisasmAnnotation::UnknownInstruction]);
+    assert_eq!(disasm.lines[11].annotations, vec![EvmDisasmAnnotation::Metadata]);
+    assert_eq!(disasm.lines[11].bytes.len(), 12);
+
+    let json = disasm.to_json();
+    assert_eq!(json[1]["mnemonic"], "PUSH1 0x8");
+    assert_eq!(json[1]["immediate"], "0x8");
+    assert_eq!(json[2]["jumpTargets"][0], 8);
+    assert_eq!(json[5]["label"], "L0008");
+    assert_eq!(json[7]["annotations"][0], "invalid jump target");
+    assert!(json[11]["mnemonic"].is_null());
+    assert_eq!(json[11]["bytes"], "0xa164736f6c634300080f000a");
+
+    // a PUSH cut off in live code
+    let disasm = EvmDisassembly::new_from_bytes(&hex::decode("600161").unwrap());
+    assert_eq!(disasm.lines.len(), 2);
+    assert_eq!(disasm.lines[1].offset, 2);
+    assert_eq!(disasm.lines[1].annotations, vec![EvmDisasmAnnotation::IncompleteInstruction]);
+}
+
+#[test]
+fn disasm_evmasm() {
+    let disasm = EvmDisassembly::new_from_bytes(&hex::decode("6080604052600c20fe").unwrap());
+    assert_eq!(disasm.format(EvmDisasmStyle::Evmasm), "\
+00000000: PUSH1 0x80
+00000002: PUSH1 0x40
+00000004: MSTORE
+00000005: PUSH1 0xc
+00000007: SHA3
+00000008: INVALID
+");
+}
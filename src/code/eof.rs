@@ -0,0 +1,429 @@
+use thiserror::Error;
+use primitive_types::U256;
+use crate::code::{EvmCode, EvmOp, EvmOpParserMode, EvmOpError, IndexedEvmCode};
+use crate::constants::EVM_STACK_SIZE;
+
+#[cfg(test)]
+mod test;
+
+
+// EVM Object Format (EIP-3540, EIP-3670, EIP-4200, EIP-4750, EIP-5450): a container with
+// typed code sections and a data section, e.g.
+//
+//     ef00 01                    magic, version
+//     01 0008                    type section (4 bytes per code section)
+//     02 0002 0009 0003          two code sections of 9 and 3 bytes
+//     04 0000                    data section
+//     00                         end of header
+//     00 80 0002  01 01 0002     types: inputs, outputs (0x80: non-returning), max stack height
+//     ...                        code sections, data
+//
+// Code sections have no dynamic jumps (RJUMP, RJUMPI and RJUMPV have relative immediates,
+// CALLF, RETF and JUMPF call between sections) and their stack heights are validated, so
+// the JIT can compile each section to a function with direct branches (see
+// JitEvmEngine::jit_compile_eof). Container sections (EOFCREATE) are not supported.
+
+pub const EOF_MAGIC: [u8; 2] = [0xef, 0x00];
+pub const EOF_VERSION: u8 = 0x01;
+pub const EOF_NON_RETURNING: u8 = 0x80;
+pub const EOF_MAX_CODE_SECTIONS: usize = 1024;
+pub const EOF_MAX_STACK_HEIGHT: usize = 1023;
+
+const EOF_KIND_TYPE: u8 = 0x01;
+const EOF_KIND_CODE: u8 = 0x02;
+const EOF_KIND_CONTAINER: u8 = 0x03;
+const EOF_KIND_DATA: u8 = 0x04;
+const EOF_TERMINATOR: u8 = 0x00;
+
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EvmEofError {
+    #[error("eof error: invalid magic")]
+    InvalidMagic,
+    #[error("eof error: unsupported version {0}")]
+    UnsupportedVersion(u8),
+    #[error("eof error: malformed header: {0}")]
+    MalformedHeader(&'static str),
+    #[error("eof error: container size does not match the header")]
+    InvalidContainerSize,
+    #[error("eof error: section {0}: invalid type: {1}")]
+    InvalidType(usize, &'static str),
+    #[error("eof error: section {0} is not reachable from the first one")]
+    UnreachableSection(usize),
+    #[error("eof error: section {0}, offset {1}: undefined instruction {2:#04x}")]
+    UndefinedInstruction(usize, usize, u8),
+    #[error("eof error: section {0}, offset {1}: truncated immediate")]
+    TruncatedImmediate(usize, usize),
+    #[error("eof error: section {0}, offset {1}: invalid relative jump destination")]
+    InvalidJumpDestination(usize, usize),
+    #[error("eof error: section {0}, offset {1}: invalid section {2}")]
+    InvalidSectionIndex(usize, usize, usize),
+    #[error("eof error: section {0}, offset {1}: call does not match the type of section {2}")]
+    InvalidSectionCall(usize, usize, usize),
+    #[error("eof error: section {0}, offset {1}: stack underflow")]
+    StackUnderflow(usize, usize),
+    #[error("eof error: section {0}, offset {1}: stack overflow")]
+    StackOverflow(usize, usize),
+    #[error("eof error: section {0}, offset {1}: stack height differs between paths")]
+    StackHeightMismatch(usize, usize),
+    #[error("eof error: section {0}, offset {1}: unreachable code")]
+    UnreachableCode(usize, usize),
+    #[error("eof error: section {0}: code does not end with a terminating instruction")]
+    MissingTerminator(usize),
+    #[error("eof error: section {0}: max stack height is {1}, not {2}")]
+    MaxStackHeightMismatch(usize, usize, usize),
+    #[error("eof error: section {0}: returning flag does not match the code")]
+    ReturningMismatch(usize),
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvmEofType {
+    pub inputs: u8,
+    pub outputs: u8,   // EOF_NON_RETURNING if the section never returns (no RETF)
+    pub max_stack_height: u16,
+}
+
+impl EvmEofType {
+    pub fn is_returning(&self) -> bool {
+        self.outputs != EOF_NON_RETURNING
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvmEofContainer {
+    pub types: Vec<EvmEofType>,
+    pub code: Vec<EvmCode>,
+    pub data: Vec<u8>,
+}
+
+impl EvmEofContainer {
+    pub fn is_eof(b: &[u8]) -> bool {
+        b.starts_with(&EOF_MAGIC)
+    }
+
+    // parses and validates the container
+    pub fn new_from_bytes(b: &[u8]) -> Result<Self, EvmEofError> {
+        // HEADER
+
+        let mut header = EvmEofReader { b, pos: 0 };
+        if header.bytes(2) != Some(&EOF_MAGIC[..]) {
+            return Err(EvmEofError::InvalidMagic);
+        }
+        match header.u8() {
+            Some(EOF_VERSION) => {},
+            Some(version) => return Err(EvmEofError::UnsupportedVersion(version)),
+            None => return Err(EvmEofError::MalformedHeader("missing version")),
+        }
+
+        if header.u8() != Some(EOF_KIND_TYPE) {
+            return Err(EvmEofError::MalformedHeader("missing type section"));
+        }
+        let types_size = header.u16().ok_or(EvmEofError::MalformedHeader("missing type section size"))?;
+
+        if header.u8() != Some(EOF_KIND_CODE) {
+            return Err(EvmEofError::MalformedHeader("missing code sections"));
+        }
+        let num_code = header.u16().ok_or(EvmEofError::MalformedHeader("missing number of code sections"))?;
+        if num_code == 0 || num_code > EOF_MAX_CODE_SECTIONS {
+            return Err(EvmEofError::MalformedHeader("invalid number of code sections"));
+        }
+        let mut code_sizes = Vec::with_capacity(num_code);
+        for _ in 0..num_code {
+            match header.u16() {
+                Some(0) => return Err(EvmEofError::MalformedHeader("empty code section")),
+                Some(size) => code_sizes.push(size),
+                None => return Err(EvmEofError::MalformedHeader("missing code section size")),
+            }
+        }
+        if types_size != 4 * num_code {
+            return Err(EvmEofError::MalformedHeader("type section size does not match the number of code sections"));
+        }
+
+        match header.u8() {
+            Some(EOF_KIND_DATA) => {},
+            Some(EOF_KIND_CONTAINER) => return Err(EvmEofError::MalformedHeader("container sections are not supported")),
+            _ => return Err(EvmEofError::MalformedHeader("missing data section")),
+        }
+        let data_size = header.u16().ok_or(EvmEofError::MalformedHeader("missing data section size"))?;
+
+        if header.u8() != Some(EOF_TERMINATOR) {
+            return Err(EvmEofError::MalformedHeader("missing terminator"));
+        }
+
+        // BODY
+
+        let body_size = types_size + code_sizes.iter().sum::<usize>() + data_size;
+        if b.len() - header.pos != body_size {
+            return Err(EvmEofError::InvalidContainerSize);
+        }
+        let mut body = header;
+
+        let types: Vec<EvmEofType> = (0..num_code).map(|_| {
+            let (inputs, outputs, max_stack_height) = (body.u8().unwrap(), body.u8().unwrap(), body.u16().unwrap());
+            EvmEofType { inputs, outputs, max_stack_height: max_stack_height as u16 }
+        }).collect();
+
+        let mut code = Vec::with_capacity(num_code);
+        for (s, size) in code_sizes.into_iter().enumerate() {
+            code.push(parse_section(s, body.bytes(size).unwrap())?);
+        }
+
+        let data = body.bytes(data_size).unwrap().to_vec();
+
+        let container = Self { types, code, data };
+        container.validate()?;
+        Ok(container)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let code: Vec<Vec<u8>> = self.code.iter().map(|c| c.to_bytes()).collect();
+
+        let mut w = EOF_MAGIC.to_vec();
+        w.push(EOF_VERSION);
+        w.push(EOF_KIND_TYPE);
+        w.extend_from_slice(&(4 * self.types.len() as u16).to_be_bytes());
+        w.push(EOF_KIND_CODE);
+        w.extend_from_slice(&(code.len() as u16).to_be_bytes());
+        for c in code.iter() {
+            w.extend_from_slice(&(c.len() as u16).to_be_bytes());
+        }
+        w.push(EOF_KIND_DATA);
+        w.extend_from_slice(&(self.data.len() as u16).to_be_bytes());
+        w.push(EOF_TERMINATOR);
+
+        for t in self.types.iter() {
+            w.push(t.inputs);
+            w.push(t.outputs);
+            w.extend_from_slice(&t.max_stack_height.to_be_bytes());
+        }
+        for c in code.iter() {
+            w.extend_from_slice(c);
+        }
+        w.extend_from_slice(&self.data);
+        w
+    }
+
+
+    // VALIDATION
+
+    fn validate(&self) -> Result<(), EvmEofError> {
+        for (s, t) in self.types.iter().enumerate() {
+            if s == 0 && (t.inputs != 0 || t.is_returning()) {
+                return Err(EvmEofError::InvalidType(s, "first section must take no inputs and not return"));
+            }
+            if t.inputs > 0x7f || (t.outputs > 0x7f && t.is_returning()) {
+                return Err(EvmEofError::InvalidType(s, "too many inputs or outputs"));
+            }
+            if t.max_stack_height as usize > EOF_MAX_STACK_HEIGHT {
+                return Err(EvmEofError::InvalidType(s, "max stack height too large"));
+            }
+        }
+
+        let mut reachable = vec![false; self.code.len()];
+        let mut worklist = vec![0];
+        reachable[0] = true;
+        while let Some(s) = worklist.pop() {
+            for called in self.validate_section(s)? {
+                if !reachable[called] {
+                    reachable[called] = true;
+                    worklist.push(called);
+                }
+            }
+        }
+        match reachable.iter().position(|r| !r) {
+            Some(s) => Err(EvmEofError::UnreachableSection(s)),
+            None => Ok(()),
+        }
+    }
+
+    // relative jump targets, section indices and stack heights (EIP-5450) of one code section,
+    // returns the sections it calls or jumps to
+    fn validate_section(&self, s: usize) -> Result<Vec<usize>, EvmEofError> {
+        use EvmOp::*;
+
+        let code = self.code[s].index();
+        let ops = &code.code.ops;
+        let this_type = self.types[s];
+        let offset = |i: usize| code.opidx2target[&i].as_usize();
+
+        // (min, max) stack height before each instruction, over all paths reaching it
+        let mut heights: Vec<Option<(usize, usize)>> = vec![None; ops.len()];
+        heights[0] = Some((this_type.inputs as usize, this_type.inputs as usize));
+        let mut max_height = this_type.inputs as usize;
+        let mut returns = false;
+        let mut called = Vec::new();
+
+        for (i, op) in ops.iter().enumerate() {
+            let (min, max) = heights[i].ok_or(EvmEofError::UnreachableCode(s, offset(i)))?;
+
+            let mut section_type = |section: &u16| {
+                called.push(*section as usize);
+                self.types.get(*section as usize).copied().ok_or(EvmEofError::InvalidSectionIndex(s, offset(i), *section as usize))
+            };
+            let check_overflow = |t: &EvmEofType| {
+                if max + t.max_stack_height as usize > EVM_STACK_SIZE + t.inputs as usize {
+                    Err(EvmEofError::StackOverflow(s, offset(i)))
+                } else {
+                    Ok(())
+                }
+            };
+
+            // STACK HEIGHT AFTER THE INSTRUCTION
+
+            let (consumed, produced) = match op {
+                Callf(section) => {
+                    let t = section_type(section)?;
+                    if !t.is_returning() {
+                        return Err(EvmEofError::InvalidSectionCall(s, offset(i), *section as usize));
+                    }
+                    check_overflow(&t)?;
+                    (t.inputs as usize, t.outputs as usize)
+                },
+                Jumpf(section) => {
+                    let t = section_type(section)?;
+                    check_overflow(&t)?;
+                    if t.is_returning() {
+                        // the called section returns to this one's caller
+                        if !this_type.is_returning() || t.outputs > this_type.outputs {
+                            return Err(EvmEofError::InvalidSectionCall(s, offset(i), *section as usize));
+                        }
+                        returns = true;
+                        let expected = this_type.outputs as usize + t.inputs as usize - t.outputs as usize;
+                        if min != expected || max != expected {
+                            return Err(EvmEofError::StackHeightMismatch(s, offset(i)));
+                        }
+                    }
+                    (t.inputs as usize, 0)
+                },
+                Retf => {
+                    if !this_type.is_returning() {
+                        return Err(EvmEofError::ReturningMismatch(s));
+                    }
+                    returns = true;
+                    if min != this_type.outputs as usize || max != this_type.outputs as usize {
+                        return Err(EvmEofError::StackHeightMismatch(s, offset(i)));
+                    }
+                    (this_type.outputs as usize, 0)
+                },
+                op => op.stack_effect(),
+            };
+            if min < consumed {
+                return Err(EvmEofError::StackUnderflow(s, offset(i)));
+            }
+            let after = (min - consumed + produced, max - consumed + produced);
+            if after.1 > EOF_MAX_STACK_HEIGHT {
+                return Err(EvmEofError::StackOverflow(s, offset(i)));
+            }
+            max_height = max_height.max(after.1);
+
+            // SUCCESSORS
+
+            let next = offset(i) + op.len();
+            let relative = |rel: &i16| {
+                let target = next as isize + *rel as isize;
+                match code.target2opidx.get(&(U256::zero() + target.max(0) as usize)) {
+                    Some(opidx) if target >= 0 => Ok(*opidx),
+                    _ => Err(EvmEofError::InvalidJumpDestination(s, offset(i))),
+                }
+            };
+            let successors = match op {
+                Stop | Return | Revert | Invalid | Retf | Jumpf(_) => vec![],
+                Rjump(rel) => vec![relative(rel)?],
+                Rjumpi(rel) => vec![i + 1, relative(rel)?],
+                Rjumpv(rels) => std::iter::once(Ok(i + 1)).chain(rels.iter().map(relative)).collect::<Result<_, _>>()?,
+                _ => vec![i + 1],
+            };
+
+            for succ in successors {
+                if succ >= ops.len() {
+                    return Err(EvmEofError::MissingTerminator(s));
+                }
+                heights[succ] = match heights[succ] {
+                    None => Some(after),
+                    Some(h) if succ > i => Some((h.0.min(after.0), h.1.max(after.1))),
+                    // backward jumps must not change the stack height
+                    Some(h) if h == after => Some(h),
+                    Some(_) => return Err(EvmEofError::StackHeightMismatch(s, offset(i))),
+                };
+            }
+        }
+
+        if returns != this_type.is_returning() {
+            return Err(EvmEofError::ReturningMismatch(s));
+        }
+        if max_height != this_type.max_stack_height as usize {
+            return Err(EvmEofError::MaxStackHeightMismatch(s, this_type.max_stack_height as usize, max_height));
+        }
+
+        Ok(called)
+    }
+
+    pub fn index(&self) -> Vec<IndexedEvmCode> {
+        self.code.iter().map(|c| c.index()).collect()
+    }
+}
+
+
+struct EvmEofReader<'a> {
+    b: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> EvmEofReader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.b.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<usize> {
+        self.bytes(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+    }
+}
+
+
+// instructions of a code section (EIP-3670): the EOF ones, and the legacy ones except for
+// those EOF removes (jumps, PC, GAS, code introspection, ...) or that this crate does not
+// implement
+fn parse_section(s: usize, b: &[u8]) -> Result<EvmCode, EvmEofError> {
+    let mut ops = Vec::new();
+    let mut reader = EvmEofReader { b, pos: 0 };
+
+    while reader.pos < b.len() {
+        let offset = reader.pos;
+        let truncated = EvmEofError::TruncatedImmediate(s, offset);
+
+        let op = match reader.u8().unwrap() {
+            0xe0 => EvmOp::Rjump(reader.u16().ok_or(truncated)? as u16 as i16),
+            0xe1 => EvmOp::Rjumpi(reader.u16().ok_or(truncated)? as u16 as i16),
+            0xe2 => {
+                let count = reader.u8().ok_or(truncated.clone())? as usize + 1;
+                let rels = (0..count).map(|_| reader.u16().map(|rel| rel as u16 as i16)).collect::<Option<_>>();
+                EvmOp::Rjumpv(rels.ok_or(truncated)?)
+            },
+            0xe3 => EvmOp::Callf(reader.u16().ok_or(truncated)? as u16),
+            0xe4 => EvmOp::Retf,
+            0xe5 => EvmOp::Jumpf(reader.u16().ok_or(truncated)? as u16),
+            opcode @ (0x56 | 0x57) => return Err(EvmEofError::UndefinedInstruction(s, offset, opcode)),
+            _ => {
+                match EvmOp::new_from_bytes(&b[offset..], EvmOpParserMode::Strict) {
+                    Ok((op, len)) => {
+                        reader.pos = offset + len;
+                        op
+                    },
+                    Err(EvmOpError::ParserErrorIncompleteInstruction) => return Err(truncated),
+                    Err(EvmOpError::ParserErrorUnknownInstruction(opcode)) => return Err(EvmEofError::UndefinedInstruction(s, offset, opcode)),
+                }
+            },
+        };
+        ops.push(op);
+    }
+
+    Ok(EvmCode { ops })
+}
@@ -0,0 +1,109 @@
+use primitive_types::U256;
+use crate::code::EvmOp::*;
+use crate::code::cfg::{EvmBlockExit, EvmJumpTarget};
+use crate::code::eof::{EvmEofContainer, EvmEofError, EvmEofType};
+
+
+// container from (inputs, outputs, max stack height) and code of each section, and data
+fn container(types: &[(u8, u8, u16)], code: &[&str], data: &str) -> Vec<u8> {
+    let code: Vec<Vec<u8>> = code.iter().map(|c| hex::decode(c).unwrap()).collect();
+    let data = hex::decode(data).unwrap();
+
+    let mut b = vec![0xef, 0x00, 0x01, 0x01];
+    b.extend_from_slice(&(4 * types.len() as u16).to_be_bytes());
+    b.push(0x02);
+    b.extend_from_slice(&(code.len() as u16).to_be_bytes());
+    for c in code.iter() {
+        b.extend_from_slice(&(c.len() as u16).to_be_bytes());
+    }
+    b.push(0x04);
+    b.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    b.push(0x00);
+    for (inputs, outputs, max_stack_height) in types {
+        b.extend_from_slice(&[*inputs, *outputs]);
+        b.extend_from_slice(&max_stack_height.to_be_bytes());
+    }
+    for c in code.iter() {
+        b.extend_from_slice(c);
+    }
+    b.extend_from_slice(&data);
+    b
+}
+
+fn parse_err(types: &[(u8, u8, u16)], code: &[&str]) -> EvmEofError {
+    EvmEofContainer::new_from_bytes(&container(types, code, "")).unwrap_err()
+}
+
+
+#[test]
+fn eof_parse_sections() {
+    // PUSH1 5 CALLF 1 PUSH1 0 SSTORE STOP, and DUP1 ADD RETF
+    let b = container(&[(0, 0x80, 2), (1, 1, 2)], &["6005e3000160005500", "8001e4"], "c0ffee");
+    assert_eq!(hex::encode(&b[..13]), "ef000101000802000200090003");
+
+    let eof = EvmEofContainer::new_from_bytes(&b).unwrap();
+    assert_eq!(eof.types[1], EvmEofType { inputs: 1, outputs: 1, max_stack_height: 2 });
+    assert!(!eof.types[0].is_returning());
+    assert_eq!(eof.code[0].ops, vec![Push(1, U256::zero() + 5), Callf(1), Push(1, U256::zero()), Sstore, Stop]);
+    assert_eq!(eof.code[1].ops, vec![Dup1, Add, Retf]);
+    assert_eq!(eof.data, vec![0xc0, 0xff, 0xee]);
+    assert_eq!(eof.to_bytes(), b);
+    assert!(EvmEofContainer::is_eof(&b));
+}
+
+#[test]
+fn eof_relative_jumps() {
+    // counts down from 3: PUSH1 3, loop: PUSH1 1 SWAP1 SUB DUP1 RJUMPI loop, POP STOP
+    let b = container(&[(0, 0x80, 2)], &["60036001900380e1fff85000"], "");
+    let eof = EvmEofContainer::new_from_bytes(&b).unwrap();
+    assert_eq!(eof.code[0].ops[5], Rjumpi(-8));
+    assert_eq!(eof.to_bytes(), b);
+
+    // the loop header is a block of its own, although it is no Jumpdest
+    let cfg = eof.code[0].index().cfg();
+    assert_eq!(cfg.blocks.iter().map(|b| b.ops.clone()).collect::<Vec<_>>(), vec![0..1, 1..6, 6..8]);
+    assert_eq!(cfg.blocks[1].exit, EvmBlockExit::Jumpi(EvmJumpTarget::Static(vec![1])));
+    assert_eq!(cfg.blocks[1].successors, vec![1, 2]);
+    assert_eq!(cfg.loops().len(), 1);
+
+    // PUSH1 0 RJUMPV [0, 2], PUSH1 1, STOP: the stack height at STOP is 0 or 1
+    let b = container(&[(0, 0x80, 1)], &["6000e20100000002600100"], "");
+    let eof = EvmEofContainer::new_from_bytes(&b).unwrap();
+    assert_eq!(eof.code[0].ops, vec![Push(1, U256::zero()), Rjumpv(vec![0, 2]), Push(1, U256::one()), Stop]);
+    let cfg = eof.code[0].index().cfg();
+    assert_eq!(cfg.blocks[0].exit, EvmBlockExit::Jumpi(EvmJumpTarget::Static(vec![1, 2])));
+    assert_eq!(cfg.blocks[0].successors, vec![1, 2]);
+}
+
+#[test]
+fn eof_invalid_containers() {
+    use EvmEofError::*;
+
+    let valid = container(&[(0, 0x80, 0)], &["00"], "");
+    assert_eq!(EvmEofContainer::new_from_bytes(&valid[1..]), Err(InvalidMagic));
+    assert_eq!(EvmEofContainer::new_from_bytes(&[&valid[..2], &[0x02], &valid[3..]].concat()), Err(UnsupportedVersion(2)));
+    assert_eq!(EvmEofContainer::new_from_bytes(&valid[..13]), Err(MalformedHeader("missing data section size")));
+    assert_eq!(EvmEofContainer::new_from_bytes(&[&valid[..], &[0x00]].concat()), Err(InvalidContainerSize));
+    assert_eq!(EvmEofContainer::new_from_bytes(&container(&[(0, 0x80, 0)], &["00"], "00")[..20]), Err(InvalidContainerSize));
+
+    // instructions
+    assert_eq!(parse_err(&[(0, 0x80, 1)], &["600056"]), UndefinedInstruction(0, 2, 0x56));
+    assert_eq!(parse_err(&[(0, 0x80, 0)], &["0c"]), UndefinedInstruction(0, 0, 0x0c));
+    assert_eq!(parse_err(&[(0, 0x80, 0)], &["e000"]), TruncatedImmediate(0, 0));
+    assert_eq!(parse_err(&[(0, 0x80, 0)], &["e0fffe"]), InvalidJumpDestination(0, 0));
+    assert_eq!(parse_err(&[(0, 0x80, 0)], &["e3000100"]), InvalidSectionIndex(0, 0, 1));
+
+    // stack heights
+    assert_eq!(parse_err(&[(0, 0x80, 0)], &["0100"]), StackUnderflow(0, 0));
+    assert_eq!(parse_err(&[(0, 0x80, 1)], &["6001e0fffb"]), StackHeightMismatch(0, 2));
+    assert_eq!(parse_err(&[(0, 0x80, 3)], &["60036001900380e1fff85000"]), MaxStackHeightMismatch(0, 3, 2));
+    assert_eq!(parse_err(&[(0, 0x80, 0)], &["0000"]), UnreachableCode(0, 1));
+    assert_eq!(parse_err(&[(0, 0x80, 1)], &["600150"]), MissingTerminator(0));
+
+    // sections
+    assert_eq!(parse_err(&[(1, 0x80, 1)], &["00"]), InvalidType(0, "first section must take no inputs and not return"));
+    assert_eq!(parse_err(&[(0, 0x80, 0)], &["e4"]), ReturningMismatch(0));
+    assert_eq!(parse_err(&[(0, 0x80, 0), (0, 0, 0)], &["00", "e4"]), UnreachableSection(1));
+    assert_eq!(parse_err(&[(0, 0x80, 0), (0, 0x80, 0)], &["e3000100", "00"]), InvalidSectionCall(0, 0, 1));
+    assert_eq!(parse_err(&[(0, 0x80, 0), (0, 1, 1)], &["e50001", "6001e4"]), InvalidSectionCall(0, 0, 1));
+}
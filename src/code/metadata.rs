@@ -0,0 +1,172 @@
+use std::ops::Range;
+use crate::code::{EvmCode, EvmOp, EvmOpParserMode};
+
+#[cfg(test)]
+mod test;
+
+
+// solc appends a CBOR map to the runtime code, followed by its length as two big-endian
+// bytes, e.g. {"ipfs": <multihash>, "solc": <version>}. Older versions store a Swarm hash
+// under "bzzr0" or "bzzr1" instead of "ipfs".
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EvmMetadata {
+    pub solc: Option<String>,    // compiler version, e.g. "0.8.15", or the full version string of a prerelease
+    pub ipfs: Option<Vec<u8>>,   // multihash of the metadata JSON
+    pub bzzr0: Option<Vec<u8>>,
+    pub bzzr1: Option<Vec<u8>>,
+    pub experimental: bool,
+}
+
+impl EvmMetadata {
+    // decodes the metadata at the end of the code, if any (and if no code can reach it)
+    pub fn new_from_bytes(b: &[u8]) -> Option<Self> {
+        EvmCode::new_from_bytes(b, EvmOpParserMode::Lax).ok()?.metadata()
+    }
+
+    // CIDv0 of the metadata JSON on IPFS, e.g. "Qm..."
+    pub fn ipfs_cid(&self) -> Option<String> {
+        self.ipfs.as_ref().map(|hash| base58(hash))
+    }
+}
+
+impl EvmCode {
+    // metadata split off by the parser (see EvmCode::new_from_bytes)
+    pub fn metadata(&self) -> Option<EvmMetadata> {
+        match self.ops.last() {
+            Some(EvmOp::Data(bytes)) => match locate(bytes) {
+                Some((range, metadata)) if range.start == 0 => Some(metadata),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+
+// byte range of what decodes as metadata (including the length) at the end of the code,
+// whether code can reach it is up to the parser
+pub fn metadata_range(b: &[u8]) -> Option<Range<usize>> {
+    locate(b).map(|(range, _)| range)
+}
+
+fn locate(b: &[u8]) -> Option<(Range<usize>, EvmMetadata)> {
+    if b.len() < 2 {
+        return None;
+    }
+    let len = u16::from_be_bytes([b[b.len() - 2], b[b.len() - 1]]) as usize;
+    if len == 0 || len + 2 > b.len() {
+        return None;
+    }
+    let start = b.len() - 2 - len;
+    decode(&b[start..b.len() - 2]).map(|metadata| (start..b.len(), metadata))
+}
+
+
+// CBOR
+
+// the subset of CBOR that solc emits
+enum CborValue {
+    Uint,
+    Bytes(Vec<u8>),
+    Text(String),
+    Bool(bool),
+}
+
+// major type and argument of the next item
+fn read_head(b: &[u8], pos: &mut usize) -> Option<(u8, u64)> {
+    let initial = *b.get(*pos)?;
+    *pos += 1;
+
+    let size = match initial & 0x1f {
+        info @ 0..=23 => return Some((initial >> 5, info as u64)),
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        _ => return None,   // indefinite lengths
+    };
+    let arg = b.get(*pos..*pos + size)?.iter().fold(0u64, |acc, byte| (acc << 8) | *byte as u64);
+    *pos += size;
+    Some((initial >> 5, arg))
+}
+
+fn read_value(b: &[u8], pos: &mut usize) -> Option<CborValue> {
+    let (major, arg) = read_head(b, pos)?;
+
+    let read_bytes = |pos: &mut usize| {
+        let end = pos.checked_add(usize::try_from(arg).ok()?)?;
+        let bytes = b.get(*pos..end)?.to_vec();
+        *pos = end;
+        Some(bytes)
+    };
+
+    match (major, arg) {
+        (0, _) => Some(CborValue::Uint),
+        (2, _) => read_bytes(pos).map(CborValue::Bytes),
+        (3, _) => String::from_utf8(read_bytes(pos)?).ok().map(CborValue::Text),
+        (7, 20) => Some(CborValue::Bool(false)),
+        (7, 21) => Some(CborValue::Bool(true)),
+        _ => None,
+    }
+}
+
+// a map with text keys that spans all of b
+fn decode(b: &[u8]) -> Option<EvmMetadata> {
+    let mut pos = 0;
+    let (major, entries) = read_head(b, &mut pos)?;
+    if major != 5 || entries == 0 {
+        return None;
+    }
+
+    let mut metadata = EvmMetadata::default();
+    for _ in 0..entries {
+        let key = match read_value(b, &mut pos)? {
+            CborValue::Text(key) => key,
+            _ => return None,
+        };
+
+        match (key.as_str(), read_value(b, &mut pos)?) {
+            ("solc", CborValue::Bytes(version)) if version.len() == 3 => {
+                metadata.solc = Some(format!("{}.{}.{}", version[0], version[1], version[2]));
+            },
+            ("solc", CborValue::Text(version)) => metadata.solc = Some(version),
+            ("ipfs", CborValue::Bytes(hash)) => metadata.ipfs = Some(hash),
+            ("bzzr0", CborValue::Bytes(hash)) => metadata.bzzr0 = Some(hash),
+            ("bzzr1", CborValue::Bytes(hash)) => metadata.bzzr1 = Some(hash),
+            ("experimental", CborValue::Bool(experimental)) => metadata.experimental = experimental,
+            _ => {},   // unknown keys (other compilers) are skipped
+        }
+    }
+
+    if pos == b.len() {
+        Some(metadata)
+    } else {
+        None
+    }
+}
+
+
+fn base58(b: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+    // base-58 digits, least significant first
+    let mut digits: Vec<u8> = Vec::new();
+    for byte in b {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    // leading zero bytes are encoded as '1' each
+    let zeros = b.iter().take_while(|byte| **byte == 0).count();
+    let mut encoded = "1".repeat(zeros);
+    encoded.extend(digits.iter().rev().map(|digit| ALPHABET[*digit as usize] as char));
+    encoded
+}
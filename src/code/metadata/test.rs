@@ -0,0 +1,132 @@
+use primitive_types::U256;
+use crate::code::{EvmCode, EvmCodeError, EvmOp, EvmOpParserMode};
+use crate::code::metadata::{base58, metadata_range, EvmMetadata};
+
+
+// {"ipfs": 0x1220 ++ sha256("jitevm"), "solc": 0.8.17}
+const METADATA_IPFS: &str = "a2646970667358221220245ae287d11383cedb0c99c8e399f209833ed72625c4a222716c5d19cd609d1664736f6c63430008110033";
+// {"bzzr0": 0x00..01} as appended by solc 0.4/0.5
+const METADATA_BZZR0: &str = "a165627a7a7230582000000000000000000000000000000000000000000000000000000000000000010029";
+
+
+#[test]
+fn metadata_decode() {
+    let code = hex::decode(format!("6080604052600080fdfe{}", METADATA_IPFS)).unwrap();
+    assert_eq!(metadata_range(&code), Some(10..code.len()));
+
+    let metadata = EvmMetadata::new_from_bytes(&code).unwrap();
+    assert_eq!(metadata.solc, Some("0.8.17".to_string()));
+    assert_eq!(metadata.ipfs.as_ref().unwrap()[..2], [0x12, 0x20]);
+    assert_eq!(metadata.ipfs_cid(), Some("QmQnZmN6tZjUqmGyAXEs3MC216CVDXoZjE43qswVR5SjJm".to_string()));
+    assert_eq!(metadata.bzzr0, None);
+
+    let old = hex::decode(format!("00{}", METADATA_BZZR0)).unwrap();
+    let metadata = EvmMetadata::new_from_bytes(&old).unwrap();
+    assert_eq!(metadata.solc, None);
+    assert_eq!(metadata.bzzr0.unwrap()[31], 0x01);
+
+    // garbage after the map, and code that just happens to end in a plausible length
+    assert_eq!(metadata_range(&hex::decode(METADATA_IPFS.replace("0008110033", "000811fe0034")).unwrap()), None);
+    assert_eq!(metadata_range(&hex::decode("6000600055000003").unwrap()), None);
+
+    assert_eq!(base58(b"hello world"), "StV1DL6CwTryKyV");
+    assert_eq!(base58(&[0x00, 0x00, 0x01]), "112");
+}
+
+#[test]
+fn parse_separates_metadata() {
+    let b = hex::decode(format!("6080604052600080fdfe{}", METADATA_IPFS)).unwrap();
+    let code = EvmCode::new_from_bytes(&b, EvmOpParserMode::Strict).unwrap();
+
+    assert_eq!(code.ops, vec![
+        EvmOp::Push(1, U256::from(0x80)),
+        EvmOp::Push(1, U256::from(0x40)),
+        EvmOp::Mstore,
+        EvmOp::Push(1, U256::zero()),
+        EvmOp::Dup1,
+        EvmOp::Revert,
+        EvmOp::Data(vec![0xfe]),   // the INVALID solc puts before the metadata is unreachable, too
+        EvmOp::Data(b[10..].to_vec()),
+    ]);
+    assert_eq!(code.to_bytes(), b);
+    assert_eq!(code.metadata().unwrap().solc, Some("0.8.17".to_string()));
+
+    // without a separate op for the metadata, there is none to decode
+    assert_eq!(EvmCode { ops: vec![EvmOp::Stop] }.metadata(), None);
+}
+
+#[test]
+fn parse_data_regions() {
+    // STOP, a data table with unknown bytes and a 0x5b hidden in a PUSH2, and a conditional
+    // jump that falls through into live code
+    let b = hex::decode("600957000c0d615b005b600160115760005b00fe").unwrap();
+    let code = EvmCode::new_from_bytes(&b, EvmOpParserMode::Strict).unwrap();
+
+    assert_eq!(code.ops, vec![
+        EvmOp::Push(1, U256::from(0x09)),
+        EvmOp::Jumpi,
+        EvmOp::Stop,
+        EvmOp::Data(hex::decode("0c0d615b00").unwrap()),
+        EvmOp::Jumpdest,
+        EvmOp::Push(1, U256::one()),
+        EvmOp::Push(1, U256::from(0x11)),
+        EvmOp::Jumpi,
+        EvmOp::Push(1, U256::zero()),
+        EvmOp::Jumpdest,
+        EvmOp::Stop,
+        EvmOp::Data(vec![0xfe]),
+    ]);
+    assert_eq!(code.to_bytes(), b);
+
+    // offsets are unchanged, so are the jump targets
+    let code = code.index();
+    assert_eq!(code.opidx2target[&4], U256::from(0x09));
+    assert_eq!(code.opidx2target[&9], U256::from(0x11));
+    assert!(code.jumpdests.contains(&9));
+
+    // unknown instructions and incomplete PUSHes in live code are still rejected
+    assert!(matches!(
+        EvmCode::new_from_bytes(&hex::decode("60010c00").unwrap(), EvmOpParserMode::Strict),
+        Err(EvmCodeError::ParserErrorUnknownInstruction(2, 0x0c)),
+    ));
+    assert_eq!(EvmCode::new_from_bytes(&hex::decode("60010c00").unwrap(), EvmOpParserMode::Lax).unwrap().ops, vec![
+        EvmOp::Push(1, U256::one()),
+        EvmOp::Unknown(0x0c),
+        EvmOp::Data(vec![0x00]),
+    ]);
+    assert!(matches!(
+        EvmCode::new_from_bytes(&hex::decode("600161").unwrap(), EvmOpParserMode::Lax),
+        Err(EvmCodeError::ParserErrorIncompleteInstruction(2)),
+    ));
+    assert_eq!(EvmCode::new_from_bytes(&hex::decode("00600161").unwrap(), EvmOpParserMode::Strict).unwrap().ops, vec![
+        EvmOp::Stop,
+        EvmOp::Data(hex::decode("600161").unwrap()),
+    ]);
+}
+
+#[test]
+fn parse_keeps_reachable_metadata() {
+    // {"x": 0x5b} decodes as metadata, but the sweep finds a Jumpdest in it that the code jumps to
+    let b = hex::decode("600756a16178415b0005").unwrap();
+    assert_eq!(metadata_range(&b), Some(3..b.len()));
+
+    let code = EvmCode::new_from_bytes(&b, EvmOpParserMode::Strict).unwrap();
+    assert_eq!(code.ops, vec![
+        EvmOp::Push(1, U256::from(0x07)),
+        EvmOp::Jump,
+        EvmOp::Data(hex::decode("a1617841").unwrap()),
+        EvmOp::Jumpdest,
+        EvmOp::Stop,
+        EvmOp::Data(vec![0x05]),
+    ]);
+    assert_eq!(code.to_bytes(), b);
+    assert!(code.index().jumpdests.contains(&3));
+    assert_eq!(code.metadata(), None);
+    assert_eq!(EvmMetadata::new_from_bytes(&b), None);
+
+    // code that falls through into it
+    let b = hex::decode(format!("6001{}", METADATA_BZZR0)).unwrap();
+    assert_eq!(metadata_range(&b), Some(2..b.len()));
+    assert_eq!(EvmCode::new_from_bytes(&b, EvmOpParserMode::Lax).unwrap().metadata(), None);
+    assert_eq!(EvmMetadata::new_from_bytes(&b), None);
+}
@@ -0,0 +1,195 @@
+use primitive_types::U256;
+use crate::code::{EvmCode, EvmOp};
+use crate::operations::native as operations;
+
+#[cfg(test)]
+mod test;
+
+
+// Peephole passes that fuse short instruction sequences into superinstructions, which the
+// interpreter and the JIT execute in one go. A superinstruction keeps the bytes of the
+// instructions it replaces (see EvmOp::unfused), so byte offsets and jump targets do not
+// change, and never contains a Jumpdest (only a jump, at its end). For legacy code only: in
+// EOF code, relative jumps can target any instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EvmPass {
+    ConstantFolding,      // pure instructions on constants, e.g. Push Push Add
+    SwapPop,              // SwapN Pop, repeated
+    PushCalldataload,     // e.g. reading the selector
+    PushAdd,
+    DupIszeroPushJumpi,
+    ComparePushJumpi,     // Lt, Gt, Slt, Sgt, Eq or Iszero, then Push Jumpi
+    PushJump,             // Push Jump and Push Jumpi (see EvmCode::augment)
+}
+
+impl EvmPass {
+    // in the order they are applied: folded constants feed the other passes, and Push Jumpi
+    // is only fused on its own if it does not end a longer pattern
+    pub const ALL: [EvmPass; 7] = [
+        EvmPass::ConstantFolding,
+        EvmPass::SwapPop,
+        EvmPass::PushCalldataload,
+        EvmPass::PushAdd,
+        EvmPass::DupIszeroPushJumpi,
+        EvmPass::ComparePushJumpi,
+        EvmPass::PushJump,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EvmPass::ConstantFolding => "constant-folding",
+            EvmPass::SwapPop => "swap-pop",
+            EvmPass::PushCalldataload => "push-calldataload",
+            EvmPass::PushAdd => "push-add",
+            EvmPass::DupIszeroPushJumpi => "dup-iszero-push-jumpi",
+            EvmPass::ComparePushJumpi => "compare-push-jumpi",
+            EvmPass::PushJump => "push-jump",
+        }
+    }
+
+    pub fn new_from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|pass| pass.name() == name).copied()
+    }
+
+    fn run(&self, ops: &[EvmOp]) -> Vec<EvmOp> {
+        use EvmOp::*;
+
+        match self {
+            EvmPass::ConstantFolding => fold_constants(ops),
+            EvmPass::SwapPop => fuse(ops, |ops| {
+                let depths: Vec<usize> = ops.chunks_exact(2)
+                    .map_while(|pair| match pair {
+                        [swap, Pop] => swap.swap_depth(),
+                        _ => None,
+                    })
+                    .collect();
+                if depths.is_empty() {
+                    None
+                } else {
+                    Some((2 * depths.len(), AugmentedSwapPop(depths)))
+                }
+            }),
+            EvmPass::PushCalldataload => fuse(ops, |ops| match ops {
+                [Push(len, val), Calldataload, ..] => Some((2, AugmentedPushCalldataload(*len, *val))),
+                _ => None,
+            }),
+            EvmPass::PushAdd => fuse(ops, |ops| match ops {
+                [Push(len, val), Add, ..] => Some((2, AugmentedPushAdd(*len, *val))),
+                _ => None,
+            }),
+            EvmPass::DupIszeroPushJumpi => fuse(ops, |ops| match ops {
+                [dup, Iszero, Push(len, val), Jumpi, ..] => dup.dup_depth().map(|n| (4, AugmentedDupIszeroPushJumpi(n, *len, *val))),
+                _ => None,
+            }),
+            EvmPass::ComparePushJumpi => fuse(ops, |ops| match ops {
+                [cmp @ (Lt | Gt | Slt | Sgt | Eq | Iszero), Push(len, val), Jumpi, ..] => {
+                    Some((3, AugmentedComparePushJumpi(Box::new(cmp.clone()), *len, *val)))
+                },
+                _ => None,
+            }),
+            EvmPass::PushJump => fuse(ops, |ops| match ops {
+                [Push(len, val), Jump, ..] => Some((2, AugmentedPushJump(*len, *val))),
+                [Push(len, val), Jumpi, ..] => Some((2, AugmentedPushJumpi(*len, *val))),
+                _ => None,
+            }),
+        }
+    }
+}
+
+impl EvmCode {
+    // applies the given passes (in the order of EvmPass::ALL), the result behaves the same and
+    // has the same bytes
+    pub fn optimize(&self, passes: &[EvmPass]) -> Self {
+        let mut ops = self.ops.clone();
+        for pass in EvmPass::ALL.iter().filter(|pass| passes.contains(pass)) {
+            ops = pass.run(&ops);
+        }
+        Self { ops }
+    }
+}
+
+
+// scanning from the left, replaces each match of the pattern, which returns the number of
+// instructions it replaces and the superinstruction
+fn fuse<F: Fn(&[EvmOp]) -> Option<(usize, EvmOp)>>(ops: &[EvmOp], pattern: F) -> Vec<EvmOp> {
+    let mut fused = Vec::with_capacity(ops.len());
+    let mut idx = 0;
+
+    while idx < ops.len() {
+        match pattern(&ops[idx..]) {
+            Some((len, op)) => {
+                fused.push(op);
+                idx += len;
+            },
+            None => {
+                fused.push(ops[idx].clone());
+                idx += 1;
+            },
+        }
+    }
+
+    fused
+}
+
+fn constant(op: &EvmOp) -> Option<U256> {
+    match op {
+        EvmOp::Push(_, val) | EvmOp::AugmentedConst(_, val) => Some(*val),
+        _ => None,
+    }
+}
+
+// result of a pure instruction, operands from the top of the stack down
+fn evaluate(op: &EvmOp, operands: &[U256]) -> Option<U256> {
+    use EvmOp::*;
+
+    Some(match (op, operands) {
+        (Iszero, [a]) => operations::Iszero(*a),
+        (Not, [a]) => operations::Not(*a),
+        (Add, [a, b]) => operations::Add(*a, *b),
+        (Mul, [a, b]) => operations::Mul(*a, *b),
+        (Sub, [a, b]) => operations::Sub(*a, *b),
+        (Div, [a, b]) => operations::Div(*a, *b),
+        (Sdiv, [a, b]) => operations::Sdiv(*a, *b),
+        (Mod, [a, b]) => operations::Mod(*a, *b),
+        (Smod, [a, b]) => operations::Smod(*a, *b),
+        (Exp, [a, b]) => operations::Exp(*a, *b),
+        (Signextend, [a, b]) => operations::Signextend(*a, *b),
+        (Lt, [a, b]) => operations::Lt(*a, *b),
+        (Gt, [a, b]) => operations::Gt(*a, *b),
+        (Slt, [a, b]) => operations::Slt(*a, *b),
+        (Sgt, [a, b]) => operations::Sgt(*a, *b),
+        (Eq, [a, b]) => operations::Eq(*a, *b),
+        (And, [a, b]) => operations::And(*a, *b),
+        (Or, [a, b]) => operations::Or(*a, *b),
+        (Xor, [a, b]) => operations::Xor(*a, *b),
+        (Byte, [a, b]) => operations::Byte(*a, *b),
+        (Shl, [a, b]) => operations::Shl(*a, *b),
+        (Shr, [a, b]) => operations::Shr(*a, *b),
+        (Sar, [a, b]) => operations::Sar(*a, *b),
+        (Addmod, [a, b, c]) => operations::Addmod(*a, *b, *c),
+        (Mulmod, [a, b, c]) => operations::Mulmod(*a, *b, *c),
+        _ => return None,
+    })
+}
+
+fn fold_constants(ops: &[EvmOp]) -> Vec<EvmOp> {
+    let mut folded: Vec<EvmOp> = Vec::with_capacity(ops.len());
+
+    for op in ops {
+        // the operands are the constants pushed right before (if all of them are)
+        let (consumed, _) = op.stack_effect();
+        let start = folded.len().saturating_sub(consumed);
+        let operands: Option<Vec<U256>> = folded[start..].iter().rev().map(constant).collect();
+
+        match operands.filter(|operands| operands.len() == consumed).and_then(|operands| evaluate(op, &operands)) {
+            Some(val) => {
+                let mut replaced: Vec<EvmOp> = folded.drain(start..).flat_map(|op| op.unfused()).collect();
+                replaced.push(op.clone());
+                folded.push(EvmOp::AugmentedConst(replaced, val));
+            },
+            None => folded.push(op.clone()),
+        }
+    }
+
+    folded
+}
@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use primitive_types::{H160, U256};
+use crate::code::EvmCode;
+use crate::code::EvmOp::*;
+use crate::code::peephole::EvmPass;
+use crate::test_data;
+
+
+fn asm(source: &str) -> EvmCode {
+    EvmCode::new_from_asm(source).unwrap()
+}
+
+// final stack, and storage of the executing contract, if the code completes
+fn run_interpreter(code: &EvmCode, calldata: Vec<u8>) -> Option<(Vec<U256>, BTreeMap<U256, U256>)> {
+    use crate::interpreter::{EvmContext, EvmInnerContext, EvmOuterContext};
+    use crate::constants::EVM_STACK_SIZE;
+    use crate::state::EvmJournaledState;
+
+    let code = code.index();
+    let mut ctx = EvmContext {
+        outer: EvmOuterContext {
+            calldata,
+            state: EvmJournaledState::new(),
+            address: H160::zero(),
+            callvalue: U256::zero(),
+        },
+        inner: EvmInnerContext {
+            code: &code,
+            stack: [U256::zero(); EVM_STACK_SIZE],
+            pc: 0,
+            sp: 0,
+            memory: vec![],
+        },
+    };
+
+    while ctx.tick().ok()? {}
+
+    let storage = ctx.outer.state.accounts.get(&H160::zero())
+        .map(|a| a.storage.iter().map(|(k, v)| (*k, *v)).collect())
+        .unwrap_or_default();
+    Some((ctx.inner.stack[..ctx.inner.sp].to_vec(), storage))
+}
+
+
+#[test]
+fn peephole_fuses_patterns() {
+    let code = asm("
+        PUSH 0 CALLDATALOAD
+        PUSH 4 ADD
+        DUP2 ISZERO PUSH @a JUMPI
+        a: JUMPDEST
+        LT PUSH @b JUMPI
+        b: JUMPDEST
+        SWAP2 POP SWAP1 POP POP
+        PUSH @c JUMP
+        c: JUMPDEST
+    ");
+    let optimized = code.optimize(&EvmPass::ALL);
+
+    assert_eq!(optimized.ops, vec![
+        AugmentedPushCalldataload(1, U256::zero()),
+        AugmentedPushAdd(1, U256::zero() + 4),
+        AugmentedDupIszeroPushJumpi(2, 1, U256::zero() + 11),
+        Jumpdest,
+        AugmentedComparePushJumpi(Box::new(Lt), 1, U256::zero() + 16),
+        Jumpdest,
+        AugmentedSwapPop(vec![2, 1]),
+        Pop,
+        AugmentedPushJump(1, U256::zero() + 25),
+        Jumpdest,
+    ]);
+    assert_eq!(optimized.ops[4].name(), "LTPUSH1JUMPI");
+
+    // same bytes, hence the same offsets, jump targets and graph
+    assert_eq!(optimized.to_bytes(), code.to_bytes());
+    let (cfg, optimized_cfg) = (code.index().cfg(), optimized.index().cfg());
+    assert_eq!(optimized_cfg.blocks.len(), cfg.blocks.len());
+    for (a, b) in optimized_cfg.blocks.iter().zip(cfg.blocks.iter()) {
+        assert_eq!(a.offsets, b.offsets);
+        assert_eq!(a.exit, b.exit);
+        assert_eq!(a.successors, b.successors);
+    }
+
+    // without any pass, nothing changes
+    assert_eq!(code.optimize(&[]).ops, code.ops);
+}
+
+#[test]
+fn peephole_passes_are_independent() {
+    let code = asm("PUSH 1 PUSH 2 ADD PUSH 3 ADD PUSH @a JUMPI a: JUMPDEST");
+
+    assert_eq!(code.optimize(&[EvmPass::PushAdd]).ops, vec![
+        Push(1, U256::one()),
+        AugmentedPushAdd(1, U256::zero() + 2),
+        AugmentedPushAdd(1, U256::zero() + 3),
+        Push(1, U256::zero() + 11),
+        Jumpi,
+        Jumpdest,
+    ]);
+    assert_eq!(code.optimize(&[EvmPass::PushJump]).ops[5], AugmentedPushJumpi(1, U256::zero() + 11));
+    assert_eq!(code.optimize(&[EvmPass::PushJump]).ops, code.augment().ops);
+
+    // the order of the passes is that of EvmPass::ALL
+    assert_eq!(code.optimize(&[EvmPass::PushAdd, EvmPass::ConstantFolding]).ops, code.optimize(&[EvmPass::ConstantFolding]).ops);
+
+    for pass in EvmPass::ALL {
+        assert_eq!(EvmPass::new_from_name(pass.name()), Some(pass));
+    }
+    assert_eq!(EvmPass::new_from_name("inline"), None);
+}
+
+#[test]
+fn peephole_constant_folding() {
+    let folded = asm("PUSH 2 PUSH 3 ADD PUSH 4 MUL").optimize(&[EvmPass::ConstantFolding]);
+    assert_eq!(folded.ops, vec![
+        AugmentedConst(vec![
+            Push(1, U256::zero() + 2),
+            Push(1, U256::zero() + 3),
+            Add,
+            Push(1, U256::zero() + 4),
+            Mul,
+        ], U256::zero() + 20),
+    ]);
+    assert_eq!(folded.ops[0].stack_effect(), (0, 1));
+
+    // operands from the top of the stack down
+    let folded = asm("PUSH 1 PUSH 0 SUB PUSH 3 PUSH 10 LT").optimize(&[EvmPass::ConstantFolding]);
+    assert_eq!(folded.ops.iter().map(|op| match op {
+        AugmentedConst(_, val) => Some(*val),
+        _ => None,
+    }).collect::<Vec<_>>(), vec![Some(U256::MAX), Some(U256::zero())]);
+
+    // only pure instructions, and not across a Jumpdest
+    let code = asm("PUSH 1 SLOAD PUSH 2 ADD PUSH 1 JUMPDEST PUSH 2 ADD PUSH 5 ISZERO");
+    assert_eq!(code.optimize(&[EvmPass::ConstantFolding]).ops[..8], code.ops[..8]);
+    assert_eq!(code.optimize(&[EvmPass::ConstantFolding]).ops[8], AugmentedConst(vec![Push(1, U256::zero() + 5), Iszero], U256::zero()));
+}
+
+#[test]
+fn peephole_interpreter_equivalence() {
+    let programs = [
+        test_data::get_code_ops_fibonacci(),
+        test_data::get_code_ops_supersimple1(),
+        test_data::get_code_ops_storage1(),
+        test_data::get_code_ops_erc20_transfers(),
+        test_data::get_code_ops_uniswap_swaps(),
+        asm("PUSH 4 CALLDATALOAD PUSH 0 CALLDATALOAD PUSH 224 SHR PUSH 7 EQ PUSH @a JUMPI STOP a: JUMPDEST PUSH 0 SSTORE").ops,
+    ];
+    let calldata = hex::decode("000000070000000000000000000000000000000000000000000000000000000000000042").unwrap();
+
+    for ops in programs {
+        let code = EvmCode { ops };
+        let reference = run_interpreter(&code, calldata.clone());
+        assert!(reference.is_some());
+
+        let passes = EvmPass::ALL.iter().map(|pass| vec![*pass]).chain([EvmPass::ALL.to_vec()]);
+        for passes in passes {
+            let optimized = code.optimize(&passes);
+            assert_eq!(optimized.to_bytes(), code.to_bytes());
+            assert_eq!(run_interpreter(&optimized, calldata.clone()), reference, "{:?}", passes);
+        }
+    }
+}
+
+#[test]
+fn peephole_stack_limits() {
+    // the superinstruction underflows at its first instruction, as the unfused code would
+    let code = EvmCode { ops: vec![Push(1, U256::one()), Swap2, Pop] };
+    assert_eq!(code.optimize(&[EvmPass::SwapPop]).ops[1], AugmentedSwapPop(vec![2]));
+    assert_eq!(run_interpreter(&code.optimize(&[EvmPass::SwapPop]), vec![]), None);
+
+    // on a full stack, it overflows at the Push, as the unfused code would
+    for (pushes, expected) in [(1022, Some(vec![U256::one(); 1022])), (1023, None)] {
+        let mut ops = vec![Push(1, U256::one()); pushes];
+        ops.extend([Dup1, Iszero, Push(1, U256::zero() + 8), Jumpi]);
+        let optimized = EvmCode { ops }.optimize(&[EvmPass::DupIszeroPushJumpi]);
+        assert!(matches!(optimized.ops[pushes], AugmentedDupIszeroPushJumpi(1, 1, _)));
+        assert_eq!(run_interpreter(&optimized, vec![]).map(|(stack, _)| stack), expected);
+    }
+}
@@ -0,0 +1,39 @@
+// Hard forks that change the behavior of the EVM (only those relevant to what is implemented)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EvmFork {
+    Frontier,
+    Homestead,
+    Byzantium,
+    Constantinople,
+    Petersburg,
+    Istanbul,
+    Berlin,
+    London,
+    Merge,
+    Shanghai,
+    Cancun,
+}
+
+impl EvmFork {
+    pub const LATEST: EvmFork = EvmFork::Cancun;
+
+    // names as used by the ethereum/tests fixtures, None for forks not modeled here
+    pub fn from_name(name: &str) -> Option<Self> {
+        use EvmFork::*;
+
+        match name {
+            "Frontier" => Some(Frontier),
+            "Homestead" => Some(Homestead),
+            "Byzantium" => Some(Byzantium),
+            "Constantinople" => Some(Constantinople),
+            "ConstantinopleFix" | "Petersburg" => Some(Petersburg),
+            "Istanbul" => Some(Istanbul),
+            "Berlin" => Some(Berlin),
+            "London" => Some(London),
+            "Merge" | "Paris" => Some(Merge),
+            "Shanghai" => Some(Shanghai),
+            "Cancun" => Some(Cancun),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use primitive_types::{H160, U256};
+use proptest::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use crate::code::{EvmCode, EvmOp, DUPS, SWAPS};
+use crate::constants::EVM_STACK_SIZE;
+use crate::inspector::{EvmInspector, EvmStep};
+use crate::state::EvmJournaledState;
+
+#[cfg(test)]
+mod test;
+
+
+// Differential fuzzing of the interpreter, the JIT and revm. Programs are generated as
+// sequences of EvmFuzzInstr, which lower to code that never under- or overflows the stack
+// and only jumps (forward) to real Jumpdests, so any sequence is a valid program and
+// proptest can shrink a failing one freely. Failing cases are minimized by proptest and
+// kept in proptest-regressions/, which is replayed before new cases are generated.
+//
+// The JIT does not support memory yet, it runs programs generated without Mstore and Mload
+// (arb_program_without_memory). revm exposes neither stack nor memory, it is compared by
+// outcome, storage and gas used. Neither of our backends meters gas: the interpreter run
+// prices the executed instructions with a table of fixed costs instead, and gas is only
+// compared for programs without dynamically priced instructions (memory, storage, EXP).
+// Not compared (yet): return data, since neither backend implements RETURN.
+
+
+const FUZZ_MAX_HEIGHT: usize = 64;   // keeps stacks small, values get reused more often
+const FUZZ_CONTRACT: u64 = 0x1000;
+const FUZZ_INTRINSIC_GAS: u64 = 21_000;   // of a call without calldata
+
+
+#[derive(Debug, Clone)]
+pub enum EvmFuzzInstr {
+    Push(U256),
+    Op(EvmOp),            // operates on the stack only (arithmetic, comparison, bitwise)
+    Dup(usize),           // 1..=16
+    Swap(usize),          // 1..=16
+    Pop,
+    Mstore(u16),          // offset
+    Mload(u16),
+    Sstore(u8),           // key
+    Sload(u8),
+    Tstore(u8),
+    Tload(u8),
+    Jump(usize),          // skip this many instructions
+    Jumpi(usize),         // same, if the top of the stack is non-zero
+}
+
+
+const FUZZ_OPS: [EvmOp; 25] = {
+    use EvmOp::*;
+    [
+        Add, Mul, Sub, Div, Sdiv, Mod, Smod, Addmod, Mulmod, Exp, Signextend,
+        Lt, Gt, Slt, Sgt, Eq, Iszero, And, Or, Xor, Not, Byte, Shl, Shr, Sar,
+    ]
+};
+
+
+// GENERATION
+
+pub fn arb_u256() -> impl Strategy<Value = U256> {
+    prop_oneof![
+        Just(U256::zero()),
+        Just(U256::one()),
+        Just(U256::MAX),
+        Just(U256::one() << 255),
+        (0u64..300).prop_map(|v| U256::zero() + v),
+        any::<[u8; 32]>().prop_map(|b| U256::from_big_endian(&b)),
+    ]
+}
+
+pub fn arb_instr() -> impl Strategy<Value = EvmFuzzInstr> {
+    use EvmFuzzInstr::*;
+
+    prop_oneof![
+        4 => arb_u256().prop_map(Push),
+        6 => (0..FUZZ_OPS.len()).prop_map(|i| Op(FUZZ_OPS[i].clone())),
+        2 => (1usize..=16).prop_map(Dup),
+        2 => (1usize..=16).prop_map(Swap),
+        1 => Just(Pop),
+        1 => (0u16..256).prop_map(Mstore),
+        1 => (0u16..256).prop_map(Mload),
+        1 => (0u8..8).prop_map(Sstore),
+        1 => (0u8..8).prop_map(Sload),
+        1 => (0u8..8).prop_map(Tstore),
+        1 => (0u8..8).prop_map(Tload),
+        1 => (0usize..8).prop_map(Jump),
+        2 => (0usize..8).prop_map(Jumpi),
+    ]
+}
+
+pub fn arb_program(max_len: usize) -> impl Strategy<Value = Vec<EvmFuzzInstr>> {
+    prop::collection::vec(arb_instr(), 1..max_len)
+}
+
+// for backends without memory
+pub fn arb_program_without_memory(max_len: usize) -> impl Strategy<Value = Vec<EvmFuzzInstr>> {
+    let instr = arb_instr().prop_filter("memory", |instr| !matches!(instr, EvmFuzzInstr::Mstore(_) | EvmFuzzInstr::Mload(_)));
+    prop::collection::vec(instr, 1..max_len)
+}
+
+
+// LOWERING
+
+pub fn lower(instrs: &[EvmFuzzInstr]) -> EvmCode {
+    use EvmFuzzInstr::*;
+
+    let n = instrs.len();
+    let mut ops = Vec::new();
+    let mut height = 0usize;
+
+    let mut labels: BTreeMap<usize, Vec<(usize, usize)>> = BTreeMap::new();   // instruction -> (jump, height at jump)
+    let mut jump_pushes: Vec<usize> = Vec::new();   // jump -> Push of its target
+    let mut jump_labels: HashMap<usize, usize> = HashMap::new();   // jump -> Jumpdest
+
+    // fills the stack up to the given height with constants, or drops elements down to it
+    let adjust = |ops: &mut Vec<EvmOp>, height: &mut usize, target: usize| {
+        while *height < target {
+            ops.push(EvmOp::Push(1, U256::zero() + *height + 1));
+            *height += 1;
+        }
+        while *height > target {
+            ops.push(EvmOp::Pop);
+            *height -= 1;
+        }
+    };
+
+    for i in 0..=n {
+        // every jump gets its own Jumpdest, where the stack has the height it had at the jump
+        for (jump, jump_height) in labels.remove(&i).unwrap_or_default() {
+            adjust(&mut ops, &mut height, jump_height);
+            jump_labels.insert(jump, ops.len());
+            ops.push(EvmOp::Jumpdest);
+        }
+        if i == n {
+            break;
+        }
+
+        let (consumed, produced) = match &instrs[i] {
+            Push(_) => (0, 1),
+            Op(op) => op.stack_effect(),
+            Dup(d) => (*d, *d + 1),
+            Swap(d) => (*d + 1, *d + 1),
+            Pop | Mstore(_) | Sstore(_) | Tstore(_) | Jumpi(_) => (1, 0),
+            Mload(_) | Sload(_) | Tload(_) => (0, 1),
+            Jump(_) => (0, 0),
+        };
+        if height < consumed {
+            adjust(&mut ops, &mut height, consumed);
+        }
+        if height - consumed + produced > FUZZ_MAX_HEIGHT {
+            adjust(&mut ops, &mut height, consumed.max(FUZZ_MAX_HEIGHT - produced));
+        }
+        height = height - consumed + produced;
+
+        match &instrs[i] {
+            Push(val) => ops.push(EvmOp::Push(32, *val)),
+            Op(op) => ops.push(op.clone()),
+            Dup(d) => ops.push(DUPS[*d - 1].clone()),
+            Swap(d) => ops.push(SWAPS[*d - 1].clone()),
+            Pop => ops.push(EvmOp::Pop),
+            Mstore(offset) | Mload(offset) => {
+                ops.push(EvmOp::Push(2, U256::zero() + *offset));
+                ops.push(if matches!(instrs[i], Mstore(_)) { EvmOp::Mstore } else { EvmOp::Mload });
+            },
+            Sstore(key) | Sload(key) | Tstore(key) | Tload(key) => {
+                ops.push(EvmOp::Push(1, U256::zero() + *key));
+                ops.push(match instrs[i] {
+                    Sstore(_) => EvmOp::Sstore,
+                    Sload(_) => EvmOp::Sload,
+                    Tstore(_) => EvmOp::Tstore,
+                    _ => EvmOp::Tload,
+                });
+            },
+            Jump(skip) | Jumpi(skip) => {
+                let jump = jump_pushes.len();
+                jump_pushes.push(ops.len());
+                ops.push(EvmOp::Push(2, U256::zero()));   // target is patched below
+                ops.push(if matches!(instrs[i], Jump(_)) { EvmOp::Jump } else { EvmOp::Jumpi });
+                labels.entry((i + 1 + skip).min(n)).or_default().push((jump, height));
+            },
+        }
+    }
+
+    // Push(2, _) has the same length for every target, patching does not move any code
+    let offsets = EvmCode { ops: ops.clone() }.index().opidx2target;
+    for (jump, push) in jump_pushes.iter().enumerate() {
+        ops[*push] = EvmOp::Push(2, offsets[&jump_labels[&jump]]);
+    }
+
+    EvmCode { ops }
+}
+
+
+// EXECUTION
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvmFuzzOutcome {
+    pub success: bool,
+    pub stack: Option<Vec<U256>>,        // None if the backend does not expose it
+    pub memory: Option<Vec<u8>>,
+    pub storage: BTreeMap<U256, U256>,   // non-zero slots of the contract
+    pub gas_used: Option<u64>,           // None if not known, see fuzz_gas_cost
+}
+
+impl EvmFuzzOutcome {
+    pub fn agrees_with(&self, other: &Self) -> bool {
+        fn agree<T: PartialEq>(a: &Option<T>, b: &Option<T>) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            }
+        }
+
+        // nothing but the failure itself is observable after a failed execution
+        self.success == other.success
+            && (!self.success || (self.storage == other.storage && agree(&self.stack, &other.stack) && agree(&self.memory, &other.memory) && agree(&self.gas_used, &other.gas_used)))
+    }
+}
+
+fn nonzero_storage(state: &EvmJournaledState, address: &H160) -> BTreeMap<U256, U256> {
+    state.accounts.get(address)
+        .map(|a| a.storage.iter().filter(|(_, v)| !v.is_zero()).map(|(k, v)| (*k, *v)).collect())
+        .unwrap_or_default()
+}
+
+// fixed gas cost (London) of the instructions the generator emits, None for those whose
+// cost depends on their operands or on state
+pub fn fuzz_gas_cost(op: &EvmOp) -> Option<u64> {
+    use EvmOp::*;
+
+    if op.dup_depth().is_some() || op.swap_depth().is_some() {
+        return Some(3);
+    }
+    match op {
+        Stop => Some(0),
+        Jumpdest => Some(1),
+        Pop => Some(2),
+        Push(_, _) | Add | Sub | Lt | Gt | Slt | Sgt | Eq | Iszero | And | Or | Xor | Not | Byte | Shl | Shr | Sar => Some(3),
+        Mul | Div | Sdiv | Mod | Smod | Signextend => Some(5),
+        Addmod | Mulmod | Jump => Some(8),
+        Jumpi => Some(10),
+        _ => None,
+    }
+}
+
+// gas used by the executed instructions, None once one has no fixed cost
+struct EvmFuzzGasMeter(Option<u64>);
+
+impl EvmInspector for EvmFuzzGasMeter {
+    fn step(&mut self, step: &EvmStep) {
+        self.0 = self.0.zip(fuzz_gas_cost(step.op)).map(|(gas, cost)| gas + cost);
+    }
+}
+
+pub fn run_interpreter(code: &EvmCode) -> EvmFuzzOutcome {
+    use crate::interpreter::{EvmContext, EvmInnerContext, EvmOuterContext};
+
+    let code = code.index();
+    let mut ctx = EvmContext {
+        outer: EvmOuterContext {
+            calldata: vec![],
+            state: EvmJournaledState::new(),
+            address: H160::from_low_u64_be(FUZZ_CONTRACT),
+            callvalue: U256::zero(),
+        },
+        inner: EvmInnerContext {
+            code: &code,
+            stack: [U256::zero(); EVM_STACK_SIZE],
+            pc: 0,
+            sp: 0,
+            memory: vec![],
+        },
+    };
+
+    let mut gas = EvmFuzzGasMeter(Some(FUZZ_INTRINSIC_GAS));
+    let success = loop {
+        match ctx.tick_with_inspector(&mut gas) {
+            Ok(true) => {},
+            Ok(false) => break true,
+            Err(_) => break false,
+        }
+    };
+
+    EvmFuzzOutcome {
+        success,
+        stack: Some(ctx.inner.stack[..ctx.inner.sp].to_vec()),
+        memory: Some(ctx.inner.memory.clone()),
+        storage: nonzero_storage(&ctx.outer.state, &ctx.outer.address),
+        gas_used: gas.0,
+    }
+}
+
+
+// None if the JIT cannot compile the program (e.g., memory is not supported yet)
+pub fn run_jit(code: &EvmCode) -> Option<EvmFuzzOutcome> {
+    use crate::jit::{JitEvmEngine, JitEvmExecutionContext, JitEvmExecutionContextHolder, EVM_JIT_STATUS_OK};
+    use inkwell::context::Context;
+
+    let code = code.index();
+    let context = Context::create();
+    let engine = JitEvmEngine::new_from_context(&context).unwrap();
+    let fn_contract = engine.jit_compile_contract(&code, None, None).ok()?;
+
+    let mut holder = JitEvmExecutionContextHolder::new_from_empty();
+    holder.address = H160::from_low_u64_be(FUZZ_CONTRACT);
+    let mut ctx = JitEvmExecutionContext::new_from_holder(&mut holder);
+    let ret = unsafe { fn_contract.call(&mut ctx as *mut _ as usize) };
+    let stack = ctx.final_stack().to_vec();
+
+    Some(EvmFuzzOutcome {
+        success: ret == EVM_JIT_STATUS_OK,
+        stack: Some(stack),
+        memory: None,
+        storage: nonzero_storage(&holder.state, &holder.address),
+        gas_used: None,
+    })
+}
+
+// None if revm does not know all instructions of the program (transient storage)
+pub fn run_revm(code: &EvmCode) -> Option<EvmFuzzOutcome> {
+    use revm::{AccountInfo, Bytecode, Database, InMemoryDB, Return, SpecId, TransactTo};
+
+    if code.ops.iter().any(|op| matches!(op, EvmOp::Tload | EvmOp::Tstore)) {
+        return None;
+    }
+
+    let address = H160::from_low_u64_be(FUZZ_CONTRACT);
+    let mut db = InMemoryDB::default();
+    db.insert_account_info(address, AccountInfo::new(U256::zero(), 1, Bytecode::new_raw(code.to_bytes().into())));
+
+    let mut evm = revm::new();
+    evm.database(db);
+    evm.env.cfg.spec_id = SpecId::LONDON;
+    evm.env.tx.caller = H160::from_low_u64_be(FUZZ_CONTRACT + 1);
+    evm.env.tx.transact_to = TransactTo::Call(address);
+    evm.env.tx.gas_limit = 30_000_000;
+    let result = evm.transact_commit();
+
+    let mut db = evm.db.unwrap();
+    let storage = (0..8u64)   // the generator only uses keys below 8
+        .map(|k| (U256::zero() + k, db.storage(address, U256::zero() + k)))
+        .filter(|(_, v)| !v.is_zero())
+        .collect();
+
+    Some(EvmFuzzOutcome {
+        success: matches!(result.exit_reason, Return::Continue | Return::Stop | Return::Return | Return::SelfDestruct),
+        stack: None,
+        memory: None,
+        storage,
+        gas_used: Some(result.gas_used),
+    })
+}
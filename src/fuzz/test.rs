@@ -0,0 +1,85 @@
+use proptest::prelude::*;
+use primitive_types::U256;
+use crate::code::EvmOp;
+use crate::code::peephole::EvmPass;
+use crate::fuzz::{EvmFuzzInstr, arb_program, arb_program_without_memory, lower, run_interpreter, run_jit, run_revm};
+
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn lowered_programs_are_valid(instrs in arb_program(64)) {
+        // generated programs never fail, in particular they never underflow the stack
+        let code = lower(&instrs);
+        let outcome = run_interpreter(&code);
+        prop_assert!(outcome.success, "{:?}", code.ops);
+    }
+
+    #[test]
+    fn differential_interpreter_revm(instrs in arb_program(64)) {
+        let code = lower(&instrs);
+        let reference = run_interpreter(&code);
+
+        if let Some(revm) = run_revm(&code) {
+            prop_assert!(revm.agrees_with(&reference), "revm: {:?}\ninterpreter: {:?}\ncode: {:?}", revm, reference, code.ops);
+        }
+    }
+
+    #[test]
+    fn differential_interpreter_jit(instrs in arb_program_without_memory(64)) {
+        let code = lower(&instrs);
+        let reference = run_interpreter(&code);
+
+        let jit = run_jit(&code);
+        prop_assert!(jit.is_some(), "JIT does not compile: {:?}", code.ops);
+        let jit = jit.unwrap();
+        prop_assert!(jit.agrees_with(&reference), "JIT: {:?}\ninterpreter: {:?}\ncode: {:?}", jit, reference, code.ops);
+    }
+
+    // no pass touches memory, programs without it run on the JIT as well
+    #[test]
+    fn differential_peephole_passes(instrs in arb_program_without_memory(64)) {
+        let code = lower(&instrs);
+        let reference = run_interpreter(&code);
+
+        // each pass on its own, and all of them together
+        let passes = EvmPass::ALL.iter().map(|pass| vec![*pass]).chain([EvmPass::ALL.to_vec()]);
+        for passes in passes {
+            let optimized = code.optimize(&passes);
+            prop_assert_eq!(optimized.to_bytes(), code.to_bytes());
+
+            let interpreter = run_interpreter(&optimized);
+            prop_assert!(interpreter.agrees_with(&reference), "{:?}: {:?}\ninterpreter: {:?}\ncode: {:?}", passes, interpreter, reference, optimized.ops);
+            let jit = run_jit(&optimized);
+            prop_assert!(jit.is_some(), "{:?}: JIT does not compile: {:?}", passes, optimized.ops);
+            let jit = jit.unwrap();
+            prop_assert!(jit.agrees_with(&reference), "{:?}: JIT: {:?}\ninterpreter: {:?}\ncode: {:?}", passes, jit, reference, optimized.ops);
+        }
+    }
+}
+
+
+#[test]
+fn lowering_fills_stack_and_patches_jumps() {
+    use EvmFuzzInstr::*;
+
+    let code = lower(&[Op(EvmOp::Add), Jumpi(0), Push(U256::one())]);
+    assert_eq!(code.ops, vec![
+        EvmOp::Push(1, U256::one()),             // operands for Add
+        EvmOp::Push(1, U256::zero() + 2),
+        EvmOp::Add,
+        EvmOp::Push(2, U256::zero() + 9),        // condition is the sum
+        EvmOp::Jumpi,
+        EvmOp::Jumpdest,                         // byte 9
+        EvmOp::Push(32, U256::one()),
+    ]);
+
+    let outcome = run_interpreter(&code);
+    assert!(outcome.success);
+    assert_eq!(outcome.stack, Some(vec![U256::one()]));
+    assert_eq!(outcome.gas_used, Some(21_000 + 3 + 3 + 3 + 3 + 10 + 1 + 3));
+
+    // SSTORE has no fixed cost
+    assert_eq!(run_interpreter(&lower(&[Push(U256::one()), Sstore(0)])).gas_used, None);
+}
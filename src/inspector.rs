@@ -0,0 +1,111 @@
+use primitive_types::U256;
+use std::io::{self, Write};
+use crate::code::EvmOp;
+use crate::state::EvmStorageDiff;
+
+#[cfg(test)]
+mod test;
+
+
+// Hooks into execution, one call before and one after every instruction. Traces of
+// different backends (and of other clients, see EvmEip3155Tracer) can be diffed to find the
+// first instruction where they diverge.
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct EvmStep<'a> {
+    pub pc: usize,            // byte offset of the instruction in the code
+    pub op: &'a EvmOp,
+    pub gas: u64,             // always zero, see EvmInspector
+    pub gas_cost: u64,
+    pub stack: &'a [U256],    // bottom first
+    pub memory_size: usize,   // in bytes
+    pub depth: usize,         // call depth, starting at 1
+}
+
+
+// gas is not metered yet: the backends report gas and gas_cost as zero, and depth as that of
+// the state's checkpoints rather than of actual calls. traces diff against other clients only
+// after those fields are dropped. a superinstruction is reported as the instructions it
+// replaces (the JIT does not instrument superinstructions at all)
+pub trait EvmInspector {
+    // before the instruction is executed
+    fn step(&mut self, _step: &EvmStep) {}
+
+    // after the instruction was executed (or failed with the error), the step reflects the
+    // stack and memory it left behind
+    fn step_end(&mut self, _step: &EvmStep, _storage_diffs: &[EvmStorageDiff], _error: Option<&str>) {}
+}
+
+
+// writes one JSON object per instruction as specified by EIP-3155, as emitted by
+// `evm --json` of go-ethereum and revm's tracer
+pub struct EvmEip3155Tracer<W: Write> {
+    out: W,
+    pending: Option<String>,   // line of the current instruction, until its error is known
+    io_error: Option<io::Error>,
+}
+
+impl<W: Write> EvmEip3155Tracer<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, pending: None, io_error: None }
+    }
+
+    fn write_line(&mut self, line: &str) {
+        if self.io_error.is_none() {
+            if let Err(e) = writeln!(self.out, "{}", line) {
+                self.io_error = Some(e);
+            }
+        }
+    }
+
+    // final line of a trace
+    pub fn summary(&mut self, output: &[u8], gas_used: u64, error: Option<&str>) {
+        let mut line = format!("{{\"output\":\"{}\",\"gasUsed\":\"{:#x}\"", hex::encode(output), gas_used);
+        if let Some(error) = error {
+            line.push_str(&format!(",\"error\":\"{}\"", json_escape(error)));
+        }
+        line.push('}');
+        self.write_line(&line);
+    }
+
+    // the underlying writer, or the first error writing to it
+    pub fn into_inner(self) -> io::Result<W> {
+        match self.io_error {
+            Some(e) => Err(e),
+            None => Ok(self.out),
+        }
+    }
+}
+
+impl<W: Write> EvmInspector for EvmEip3155Tracer<W> {
+    fn step(&mut self, step: &EvmStep) {
+        let stack: Vec<String> = step.stack.iter().map(|v| format!("\"0x{:x}\"", v)).collect();
+        self.pending = Some(format!(
+            "{{\"pc\":{},\"op\":{},\"gas\":\"{:#x}\",\"gasCost\":\"{:#x}\",\"memSize\":{},\"stack\":[{}],\"depth\":{},\"refund\":0,\"opName\":\"{}\"",
+            step.pc,
+            step.op.to_bytes()[0],
+            step.gas,
+            step.gas_cost,
+            step.memory_size,
+            stack.join(","),
+            step.depth,
+            json_escape(&step.op.name()),
+        ));
+    }
+
+    fn step_end(&mut self, _step: &EvmStep, _storage_diffs: &[EvmStorageDiff], error: Option<&str>) {
+        if let Some(mut line) = self.pending.take() {
+            if let Some(error) = error {
+                line.push_str(&format!(",\"error\":\"{}\"", json_escape(error)));
+            }
+            line.push('}');
+            self.write_line(&line);
+        }
+    }
+}
+
+
+fn json_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
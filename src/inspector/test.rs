@@ -0,0 +1,127 @@
+use primitive_types::{H160, U256};
+use crate::code::{EvmCode, EvmOp, EvmOp::*};
+use crate::constants::EVM_STACK_SIZE;
+use crate::interpreter::{EvmContext, EvmInnerContext, EvmOuterContext, EvmInterpreterError};
+use crate::inspector::{EvmInspector, EvmStep, EvmEip3155Tracer};
+use crate::state::{EvmJournaledState, EvmStorageDiff};
+
+
+fn run_inspected<I: EvmInspector>(ops: Vec<EvmOp>, inspector: &mut I) -> Result<(), EvmInterpreterError> {
+    let code = EvmCode { ops }.index();
+    let mut ctx = EvmContext {
+        outer: EvmOuterContext {
+            calldata: vec![],
+            state: EvmJournaledState::new(),
+            address: H160::zero(),
+            callvalue: U256::zero(),
+        },
+        inner: EvmInnerContext {
+            code: &code,
+            stack: [U256::zero(); EVM_STACK_SIZE],
+            pc: 0,
+            sp: 0,
+            memory: vec![],
+        },
+    };
+
+    while ctx.tick_with_inspector(inspector)? {}
+    Ok(())
+}
+
+
+#[derive(Default)]
+struct RecordingInspector {
+    steps: Vec<(usize, String, usize)>,   // pc, name, stack size before
+    ends: Vec<(usize, Vec<EvmStorageDiff>, Option<String>)>,   // stack size after
+}
+
+impl EvmInspector for RecordingInspector {
+    fn step(&mut self, step: &EvmStep) {
+        self.steps.push((step.pc, step.op.name(), step.stack.len()));
+    }
+
+    fn step_end(&mut self, step: &EvmStep, storage_diffs: &[EvmStorageDiff], error: Option<&str>) {
+        self.ends.push((step.stack.len(), storage_diffs.to_vec(), error.map(|e| e.to_string())));
+    }
+}
+
+
+#[test]
+fn inspector_sees_every_step() {
+    let mut inspector = RecordingInspector::default();
+    run_inspected(vec![
+        Push(1, U256::zero() + 42),
+        Push(2, U256::zero() + 7),
+        Sstore,
+        Push(1, U256::zero() + 7),
+        Sload,
+        Stop,
+    ], &mut inspector).unwrap();
+
+    assert_eq!(inspector.steps, vec![
+        (0, "PUSH1".to_string(), 0),
+        (2, "PUSH2".to_string(), 1),
+        (5, "SSTORE".to_string(), 2),
+        (6, "PUSH1".to_string(), 0),
+        (8, "SLOAD".to_string(), 1),
+        (9, "STOP".to_string(), 1),
+    ]);
+
+    let diff = EvmStorageDiff { address: H160::zero(), key: U256::zero() + 7, previous: U256::zero(), value: U256::zero() + 42 };
+    assert_eq!(inspector.ends, vec![
+        (1, vec![], None),
+        (2, vec![], None),
+        (0, vec![diff], None),
+        (1, vec![], None),
+        (1, vec![], None),
+        (1, vec![], None),
+    ]);
+}
+
+#[test]
+fn eip3155_trace() {
+    let mut tracer = EvmEip3155Tracer::new(Vec::new());
+    let ret = run_inspected(vec![
+        Push(1, U256::zero() + 42),
+        Iszero,
+        Pop,
+        Pop,
+    ], &mut tracer);
+    assert!(matches!(ret, Err(EvmInterpreterError::StackEmpty)));
+    tracer.summary(&[], 0, Some("interpreter error: stack empty"));
+
+    let trace = String::from_utf8(tracer.into_inner().unwrap()).unwrap();
+    assert_eq!(trace.lines().collect::<Vec<_>>(), vec![
+        r#"{"pc":0,"op":96,"gas":"0x0","gasCost":"0x0","memSize":0,"stack":[],"depth":1,"refund":0,"opName":"PUSH1"}"#,
+        r#"{"pc":2,"op":21,"gas":"0x0","gasCost":"0x0","memSize":0,"stack":["0x2a"],"depth":1,"refund":0,"opName":"ISZERO"}"#,
+        r#"{"pc":3,"op":80,"gas":"0x0","gasCost":"0x0","memSize":0,"stack":["0x0"],"depth":1,"refund":0,"opName":"POP"}"#,
+        r#"{"pc":4,"op":80,"gas":"0x0","gasCost":"0x0","memSize":0,"stack":[],"depth":1,"refund":0,"opName":"POP","error":"interpreter error: stack empty"}"#,
+        r#"{"output":"","gasUsed":"0x0","error":"interpreter error: stack empty"}"#,
+    ]);
+}
+
+#[test]
+fn superinstructions_trace_as_unfused_instructions() {
+    use crate::code::peephole::EvmPass;
+
+    fn trace(ops: Vec<EvmOp>) -> Vec<String> {
+        let mut tracer = EvmEip3155Tracer::new(Vec::new());
+        let _ = run_inspected(ops, &mut tracer);
+        String::from_utf8(tracer.into_inner().unwrap()).unwrap().lines().map(|l| l.to_string()).collect()
+    }
+
+    for ops in vec![
+        crate::test_data::get_code_ops_fibonacci(),
+        crate::test_data::get_code_ops_supersimple1(),
+        vec![Push(1, U256::one()), Swap2, Pop],   // fails at the first instruction of a superinstruction
+        vec![Push(1, U256::one()), Push(1, U256::one()), Swap1, Pop, Swap1, Pop],   // and at a later one
+    ] {
+        let code = EvmCode { ops };
+        let optimized = code.optimize(&EvmPass::ALL);
+        assert_ne!(optimized.ops, code.ops);
+
+        let reference = trace(code.ops);
+        assert!(!reference.is_empty());
+        assert_eq!(trace(optimized.ops), reference);
+    }
+}
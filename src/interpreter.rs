@@ -1,34 +1,52 @@
 use thiserror::Error;
-use primitive_types::U256;
-use std::collections::HashMap;
+use primitive_types::{H160, U256};
 use crate::code::{EvmOp, IndexedEvmCode};
 use crate::constants::{EVM_STACK_SIZE, EVM_STACK_ELEMENT_SIZE};
-use crate::operations;
+use crate::operations::native as operations;
+use crate::inspector::{EvmInspector, EvmStep};
+use crate::state::{EvmHost, EvmJournaledState, EvmJournaledHost, EvmLog, EvmStateError};
 
 
+// operations consume at least as many elements as they produce, so a single
+// check up front replaces the checks of the individual pushes/pops
+
 macro_rules! op1_u256_operation {
     ($self:ident, $fname:expr) => {{
-        let a = $self.inner.pop()?;
-        $self.inner.push($fname(a))?;
+        let sp = $self.inner.sp;
+        if sp < 1 {
+            return Err(EvmInterpreterError::StackEmpty);
+        }
+        let a = $self.inner.stack[sp-1];
+        $self.inner.stack[sp-1] = $fname(a);
     }};
 }
 
 macro_rules! op2_u256_operation {
     ($self:ident, $fname:expr) => {{
-        let a = $self.inner.pop()?;
-        let b = $self.inner.pop()?;
-        $self.inner.push($fname(a, b))?;
+        let sp = $self.inner.sp;
+        if sp < 2 {
+            return Err(EvmInterpreterError::StackEmpty);
+        }
+        let a = $self.inner.stack[sp-1];
+        let b = $self.inner.stack[sp-2];
+        $self.inner.stack[sp-2] = $fname(a, b);
+        $self.inner.sp = sp-1;
     }};
 }
 
-// macro_rules! op3_u256_operation {
-//     ($self:ident, $fname:expr) => {{
-//         let a = $self.inner.pop()?;
-//         let b = $self.inner.pop()?;
-//         let c = $self.inner.pop()?;
-//         $self.inner.push($fname(a, b, c))?;
-//     }};
-// }
+macro_rules! op3_u256_operation {
+    ($self:ident, $fname:expr) => {{
+        let sp = $self.inner.sp;
+        if sp < 3 {
+            return Err(EvmInterpreterError::StackEmpty);
+        }
+        let a = $self.inner.stack[sp-1];
+        let b = $self.inner.stack[sp-2];
+        let c = $self.inner.stack[sp-3];
+        $self.inner.stack[sp-3] = $fname(a, b, c);
+        $self.inner.sp = sp-2;
+    }};
+}
 
 
 #[derive(Error, Debug)]
@@ -39,8 +57,8 @@ pub enum EvmInterpreterError {
     StackEmpty,
     #[error("interpreter error: stack too small")]
     StackTooSmall,
-    #[error("interpreter error: Sload key not found")]
-    SloadKeyNotFound,
+    #[error("interpreter error: {0}")]
+    State(#[from] EvmStateError),
     #[error("interpreter error: Jump destination invalid")]
     JumpDestinationInvalid,
     #[error("interpreter error: Jump destination not Jumpdest")]
@@ -54,10 +72,21 @@ pub enum EvmInterpreterError {
 pub struct EvmOuterContext {
     pub calldata: Vec<u8>,
     // pub returndata: Vec<u8>,
-    pub storage: HashMap<U256, U256>,
+    pub state: EvmJournaledState,
+    pub address: H160,   // account whose storage the code operates on
     pub callvalue: U256,
 }
 
+impl EvmOuterContext {
+    pub fn host(&mut self) -> EvmJournaledHost<'_> {
+        self.state.host(self.address)
+    }
+
+    pub fn end_transaction(&mut self) -> Vec<EvmLog> {
+        self.state.end_transaction()
+    }
+}
+
 
 #[derive(Debug, Clone)]
 pub struct EvmInnerContext<'a> {
@@ -118,6 +147,26 @@ impl EvmContext<'_> {
         self.inner.push(self.inner.stack[self.inner.sp - idx])?;
         Ok(())
     }
+
+    pub fn _do_jump(&mut self, target: U256) -> Result<(), EvmInterpreterError> {
+        let opidx = self.inner.code.target2opidx.get(&target).ok_or(EvmInterpreterError::JumpDestinationInvalid)?;
+        if !self.inner.code.jumpdests.contains(opidx) {
+            return Err(EvmInterpreterError::JumpDestinationNotJumpdest);
+        }
+        self.inner.pc = *opidx;
+        Ok(())
+    }
+
+    pub fn _do_calldataload(&self, offset: U256) -> U256 {
+        let offset = offset.as_usize();
+        if offset >= self.outer.calldata.len() {
+            U256::zero()
+        } else {
+            let mut read_from = self.outer.calldata.clone();
+            read_from.extend_from_slice(&[0u8; 32]);
+            U256::from_big_endian(&read_from[offset..offset+(EVM_STACK_ELEMENT_SIZE as usize)])
+        }
+    }
     
     pub fn tick(&mut self) -> Result<bool, EvmInterpreterError> {
         // use EvmOp::*;
@@ -133,6 +182,51 @@ impl EvmContext<'_> {
         self.tick_inner(op)
     }
 
+    fn step<'b>(&'b self, pc: usize, op: &'b EvmOp) -> EvmStep<'b> {
+        EvmStep {
+            pc,
+            op,
+            gas: 0,
+            gas_cost: 0,
+            stack: &self.inner.stack[..self.inner.sp],
+            memory_size: self.inner.memory.len(),
+            depth: self.outer.state.depth().max(1),   // frames checkpoint at entry, the outermost may not
+        }
+    }
+
+    // same as tick, but reports the instruction to the inspector before and after executing it.
+    // a superinstruction is reported as the instructions it replaces, one step each, so traces
+    // of optimized code are those of the unoptimized code
+    pub fn tick_with_inspector<I: EvmInspector>(&mut self, inspector: &mut I) -> Result<bool, EvmInterpreterError> {
+        if self.inner.pc == self.inner.code.code.ops.len() {
+            return Ok(false)
+        }
+
+        let code = self.inner.code;
+        let opidx = self.inner.pc;
+        let mut pc = code.opidx2target[&opidx].as_usize();
+        self.inner.pc += 1;
+
+        let mut ret = Ok(true);
+        for op in code.code.ops[opidx].unfused() {
+            inspector.step(&self.step(pc, &op));
+
+            let journal_len = self.outer.state.journal_len();
+            ret = self.tick_inner(&op);
+
+            let storage_diffs = self.outer.state.storage_diffs_since(journal_len);
+            let error = ret.as_ref().err().map(|e| e.to_string());
+            inspector.step_end(&self.step(pc, &op), &storage_diffs, error.as_deref());
+
+            // only the last instruction of a superinstruction may jump
+            if !matches!(ret, Ok(true)) {
+                break;
+            }
+            pc += op.len();
+        }
+        ret
+    }
+
     pub fn tick_inner(&mut self, op: &EvmOp) -> Result<bool, EvmInterpreterError> {
         use EvmOp::*;
         
@@ -176,35 +270,33 @@ impl EvmContext<'_> {
             // },
             Sload => {
                 let key = self.inner.pop()?;
-                let val = self.outer.storage.get(&key);//.ok_or(EvmInterpreterError::SloadKeyNotFound)?;
-                let val = match val {
-                    None => U256::zero(),
-                    Some(v) => *v,
-                };
+                let val = self.outer.host().sload(key)?;
                 self.inner.push(val)?;
             },
             Sstore => {
                 let key = self.inner.pop()?;
                 let val = self.inner.pop()?;
-                self.outer.storage.insert(key, val);
+                self.outer.host().sstore(key, val)?;
+            },
+            Tload => {
+                let key = self.inner.pop()?;
+                let val = self.outer.host().tload(key);
+                self.inner.push(val)?;
+            },
+            Tstore => {
+                let key = self.inner.pop()?;
+                let val = self.inner.pop()?;
+                self.outer.host().tstore(key, val);
             },
             Jump => {
                 let target = self.inner.pop()?;
-                let opidx = self.inner.code.target2opidx.get(&target).ok_or(EvmInterpreterError::JumpDestinationInvalid)?;
-                if !self.inner.code.jumpdests.contains(opidx) {
-                    return Err(EvmInterpreterError::JumpDestinationNotJumpdest);
-                }
-                self.inner.pc = *opidx;
+                self._do_jump(target)?;
             },
             Jumpi => {
                 let target = self.inner.pop()?;
                 let cond = self.inner.pop()?;
                 if cond != U256::zero() {
-                    let opidx = self.inner.code.target2opidx.get(&target).ok_or(EvmInterpreterError::JumpDestinationInvalid)?;
-                    if !self.inner.code.jumpdests.contains(opidx) {
-                        return Err(EvmInterpreterError::JumpDestinationNotJumpdest);
-                    }
-                    self.inner.pc = *opidx;
+                    self._do_jump(target)?;
                 }
             },
             Swap1 => { self._do_swap(1)? },
@@ -246,21 +338,21 @@ impl EvmContext<'_> {
             Div => op2_u256_operation!(self, operations::Div),
             Sdiv => op2_u256_operation!(self, operations::Sdiv),
             Mod => op2_u256_operation!(self, operations::Mod),
-            // Smod => op2_u256_operation!(self, operations::Smod),
-            // Addmod => op3_u256_operation!(self, operations::Addmod),
-            // Mulmod => op3_u256_operation!(self, operations::Mulmod),
+            Smod => op2_u256_operation!(self, operations::Smod),
+            Addmod => op3_u256_operation!(self, operations::Addmod),
+            Mulmod => op3_u256_operation!(self, operations::Mulmod),
             Slt => op2_u256_operation!(self, operations::Slt),
             Sgt => op2_u256_operation!(self, operations::Sgt),
             Iszero => op1_u256_operation!(self, operations::Iszero),
             Not => op1_u256_operation!(self, operations::Not),
-            // Byte => op2_u256_operation!(self, operations::Byte),
+            Byte => op2_u256_operation!(self, operations::Byte),
             Shl => op2_u256_operation!(self, operations::Shl),
             Shr => op2_u256_operation!(self, operations::Shr),
-            // Sar => op2_u256_operation!(self, operations::Sar),
+            Sar => op2_u256_operation!(self, operations::Sar),
             And => op2_u256_operation!(self, operations::And),
             Or => op2_u256_operation!(self, operations::Or),
-            // Xor => op2_u256_operation!(self, operations::Xor),
-            // Signextend => op2_u256_operation!(self, operations::Signextend),
+            Xor => op2_u256_operation!(self, operations::Xor),
+            Signextend => op2_u256_operation!(self, operations::Signextend),
             Lt => op2_u256_operation!(self, operations::Lt),
             Gt => op2_u256_operation!(self, operations::Gt),
             Eq => op2_u256_operation!(self, operations::Eq),
@@ -271,15 +363,61 @@ impl EvmContext<'_> {
                 self.inner.push(U256::zero() + self.outer.calldata.len())?;
             },
             Calldataload => {
-                let offset = self.inner.pop()?.as_usize();
-                if offset >= self.outer.calldata.len() {
-                    self.inner.push(U256::zero())?;
-                } else {
-                    let mut read_from = self.outer.calldata.clone();
-                    read_from.extend_from_slice(&[0u8; 32]);
-                    self.inner.push(U256::from_big_endian(&read_from[offset..offset+(EVM_STACK_ELEMENT_SIZE as usize)]))?;
+                let offset = self.inner.pop()?;
+                let val = self._do_calldataload(offset);
+                self.inner.push(val)?;
+            },
+
+            // SUPERINSTRUCTIONS (see EvmPass)
+            op if op.is_augmented() && (self.inner.sp < op.stack_effect().0 || self.inner.sp + op.len() > EVM_STACK_SIZE) => {
+                // close to the limits of the stack, the instructions it replaces fail exactly
+                // where they would have (each one pushes at most one element and has at least
+                // one byte)
+                for op in op.unfused() {
+                    if !self.tick_inner(&op)? {
+                        return Ok(false);
+                    }
                 }
             },
+            AugmentedPushJump(_, val) => {
+                self._do_jump(*val)?;
+            },
+            AugmentedPushJumpi(_, val) => {
+                let cond = self.inner.pop()?;
+                if cond != U256::zero() {
+                    self._do_jump(*val)?;
+                }
+            },
+            AugmentedPushAdd(_, val) => {
+                let a = self.inner.pop()?;
+                self.inner.push(operations::Add(*val, a))?;
+            },
+            AugmentedPushCalldataload(_, val) => {
+                let val = self._do_calldataload(*val);
+                self.inner.push(val)?;
+            },
+            AugmentedSwapPop(depths) => {
+                for n in depths {
+                    self._do_swap(*n)?;
+                    self.inner.pop()?;
+                }
+            },
+            AugmentedDupIszeroPushJumpi(n, _, val) => {
+                if self.inner.stack[self.inner.sp - n].is_zero() {
+                    self._do_jump(*val)?;
+                }
+            },
+            AugmentedComparePushJumpi(cmp, _, val) => {
+                self.tick_inner(cmp)?;
+                let cond = self.inner.pop()?;
+                if cond != U256::zero() {
+                    self._do_jump(*val)?;
+                }
+            },
+            AugmentedConst(_, val) => {
+                self.inner.push(*val)?;
+            },
+
             _ => {
                 return Err(EvmInterpreterError::UnknownInstruction(op.clone()));
             },
@@ -379,21 +517,21 @@ impl EvmContext<'_> {
             Div => op2_u256_operation!(self, operations::Div),
             Sdiv => op2_u256_operation!(self, operations::Sdiv),
             Mod => op2_u256_operation!(self, operations::Mod),
-            // Smod => op2_u256_operation!(self, operations::Smod),
-            // Addmod => op3_u256_operation!(self, operations::Addmod),
-            // Mulmod => op3_u256_operation!(self, operations::Mulmod),
+            Smod => op2_u256_operation!(self, operations::Smod),
+            Addmod => op3_u256_operation!(self, operations::Addmod),
+            Mulmod => op3_u256_operation!(self, operations::Mulmod),
             Slt => op2_u256_operation!(self, operations::Slt),
             Sgt => op2_u256_operation!(self, operations::Sgt),
             Iszero => op1_u256_operation!(self, operations::Iszero),
             Not => op1_u256_operation!(self, operations::Not),
-            // Byte => op2_u256_operation!(self, operations::Byte),
+            Byte => op2_u256_operation!(self, operations::Byte),
             Shl => op2_u256_operation!(self, operations::Shl),
             Shr => op2_u256_operation!(self, operations::Shr),
-            // Sar => op2_u256_operation!(self, operations::Sar),
+            Sar => op2_u256_operation!(self, operations::Sar),
             And => op2_u256_operation!(self, operations::And),
             Or => op2_u256_operation!(self, operations::Or),
-            // Xor => op2_u256_operation!(self, operations::Xor),
-            // Signextend => op2_u256_operation!(self, operations::Signextend),
+            Xor => op2_u256_operation!(self, operations::Xor),
+            Signextend => op2_u256_operation!(self, operations::Signextend),
             Lt => op2_u256_operation!(self, operations::Lt),
             Gt => op2_u256_operation!(self, operations::Gt),
             Eq => op2_u256_operation!(self, operations::Eq),
@@ -1,7 +1,7 @@
 use thiserror::Error;
 use std::convert::From;
-use std::collections::HashMap;
-use primitive_types::U256;
+use std::panic::AssertUnwindSafe;
+use primitive_types::{H160, U256};
 use inkwell::OptimizationLevel;
 use inkwell::AddressSpace;
 use inkwell::context::Context;
@@ -10,19 +10,65 @@ use inkwell::execution_engine::{ExecutionEngine, JitFunction};
 use inkwell::targets::{InitializationConfig, Target};
 use inkwell::IntPredicate;
 // use inkwell::values::{FunctionValue, PointerValue, PhiValue, IntValue, BasicValue};
-use inkwell::values::{IntValue, PhiValue}; //PointerValue
+use inkwell::values::{FunctionValue, IntValue, PhiValue}; //PointerValue
 use inkwell::types::{IntType};//PointerType};
 use inkwell::basic_block::BasicBlock;
 use inkwell::builder::Builder;
 use inkwell::module::Module;
 use crate::code::{EvmOp, IndexedEvmCode};
+use crate::code::cfg::{EvmCfg, EvmBlockExit, EvmJumpTarget};
+use crate::code::eof::{EvmEofContainer, EvmEofType};
 use crate::constants::{EVM_STACK_SIZE, EVM_STACK_ELEMENT_SIZE};
+use crate::state::{EvmHost, EvmJournaledState, EvmLog};
+use crate::inspector::{EvmInspector, EvmStep};
 
 #[cfg(test)]
 mod test;
 
 
 pub type JitEvmCompiledContract = unsafe extern "C" fn(usize) -> u64;
+// return values of a JIT'ed contract
+pub const EVM_JIT_STATUS_OK: u64 = 0;
+pub const EVM_JIT_STATUS_ERROR_JUMPDEST: u64 = 1;
+pub const EVM_JIT_STATUS_ERROR_INVALID_INSTRUCTION: u64 = 2;
+pub const EVM_JIT_STATUS_ERROR_STORAGE: u64 = 3;
+pub const EVM_JIT_STATUS_ERROR_HOST: u64 = 4;   // a callback panicked
+pub const EVM_JIT_STATUS_ERROR_STACK: u64 = 5;   // CALLF or JUMPF exceeds the stack or the return stack (EOF)
+// a code section returned to its caller (EOF), never returned by the contract
+const EVM_JIT_STATUS_RETF: u64 = u64::MAX;
+// a code section ended with JUMPF to section (status - EVM_JIT_STATUS_JUMPF) (EOF), never
+// returned by the contract
+const EVM_JIT_STATUS_JUMPF: u64 = 1 << 32;
+
+pub fn status_message(status: u64) -> Option<&'static str> {
+    match status {
+        EVM_JIT_STATUS_OK => None,
+        EVM_JIT_STATUS_ERROR_JUMPDEST => Some("jit error: invalid jump destination"),
+        EVM_JIT_STATUS_ERROR_INVALID_INSTRUCTION => Some("jit error: invalid instruction"),
+        EVM_JIT_STATUS_ERROR_STORAGE => Some("jit error: storage backend failed"),
+        EVM_JIT_STATUS_ERROR_STACK => Some("jit error: stack underflow or overflow"),
+        EVM_JIT_STATUS_RETF => Some("jit error: internal: RETF escaped a code section"),
+        s if s >= EVM_JIT_STATUS_JUMPF => Some("jit error: internal: JUMPF escaped a code section"),
+        _ => Some("jit error: host callback failed"),
+    }
+}
+
+
+// compiled code can report its progress to an inspector (see JitEvmInspectorHook), at the
+// cost of a callback per basic block or per instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JitEvmInstrumentation {
+    None,
+    Blocks,
+    Instructions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JitEvmEmit {
+    Ir,
+    Asm,
+    Obj,
+}
 const _EVM_JIT_STACK_ALIGN: u32 = 16;
 
 macro_rules! op1_llvmnativei256_operation {
@@ -44,31 +90,35 @@ macro_rules! op2_llvmnativei256_operation {
     }};
 }
 
+macro_rules! op2_evmi256_operation {
+    ($self:ident, $book:ident, $fname:ident) => {{
+        let (book, a) = $self.build_stack_pop($book);
+        let (book, b) = $self.build_stack_pop(book);
+        let d = $self.$fname(a, b);
+        let book = $self.build_stack_push(book, d);
+        book
+    }};
+}
+
+macro_rules! op3_evmi256_operation {
+    ($self:ident, $book:ident, $fname:ident) => {{
+        let (book, a) = $self.build_stack_pop($book);
+        let (book, b) = $self.build_stack_pop(book);
+        let (book, c) = $self.build_stack_pop(book);
+        let d = $self.$fname(a, b, c);
+        let book = $self.build_stack_push(book, d);
+        book
+    }};
+}
+
 macro_rules! op2_llvmnativei256_compare_operation {
-    ($self:ident, $book:ident, $this:expr, $next:expr, $instructions:expr, $i:expr, $op:expr, $predicate:expr) => {{
+    ($self:ident, $book:ident, $predicate:expr) => {{
         let (book, a) = $self.build_stack_pop($book);
         let (book, b) = $self.build_stack_pop(book);
         let cmp = $self.builder.build_int_compare($predicate, a, b, "");
-
-        let push_0 = JitEvmEngineSimpleBlock::new($self, $instructions[$i].block, &format!("Instruction #{}: {:?} / push 0", $i, $op), &format!("_{}_0", $i));
-        let push_1 = JitEvmEngineSimpleBlock::new($self, push_0.block, &format!("Instruction #{}: {:?} / push 1", $i, $op), &format!("_{}_1", $i));
-        
-        $self.builder.position_at_end($this.block);
-        $self.builder.build_conditional_branch(cmp, push_1.block, push_0.block);
-        push_0.add_incoming(&book, &$this);
-        push_1.add_incoming(&book, &$this);
-        
-        $self.builder.position_at_end(push_0.block);
-        let book_0 = $self.build_stack_push(book, $self.type_stackel.const_int(0, false));
-        $self.builder.build_unconditional_branch($next.block);
-        $next.add_incoming(&book_0, &push_0);
-        
-        $self.builder.position_at_end(push_1.block);
-        let book_1 = $self.build_stack_push(book, $self.type_stackel.const_int(1, false));
-        $self.builder.build_unconditional_branch($next.block);
-        $next.add_incoming(&book_1, &push_1);
-        
-        continue;   // skip auto-generated jump to next instruction
+        let d = $self.builder.build_int_z_extend(cmp, $self.type_stackel, "");
+        let book = $self.build_stack_push(book, d);
+        book
     }};
 }
 
@@ -81,6 +131,10 @@ pub enum JitEvmEngineError {
     UnknownLlvmStringError(#[from] inkwell::support::LLVMString),
     #[error("StringError: {0:?}")]
     UnknownStringError(String),
+    #[error("UnsupportedOperation: {0:?}")]
+    UnsupportedOperation(EvmOp),
+    #[error("EmptyCode")]
+    EmptyCode,
 }
 
 impl From<String> for JitEvmEngineError {
@@ -144,26 +198,90 @@ impl<'ctx> JitEvmEngineSimpleBlock<'ctx> {
 }
 
 
+// functions the compiled code calls, declared once per module (see JitEvmEngine::build_callbacks)
+#[derive(Debug, Copy, Clone)]
+pub struct JitEvmEngineCallbacks<'ctx> {
+    pub sload: FunctionValue<'ctx>,
+    pub sstore: FunctionValue<'ctx>,
+    pub tload: FunctionValue<'ctx>,
+    pub tstore: FunctionValue<'ctx>,
+    pub step: FunctionValue<'ctx>,
+    pub step_end: FunctionValue<'ctx>,
+    pub helper_exp: FunctionValue<'ctx>,
+}
+
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct JitEvmExecutionContext {
-    // WARNING: if you change anything here (adding fields is ok), then you need to change:
+    // WARNING: if you change anything here (appending fields is ok), then you need to change:
     //           - LLVM instructions in "setup" block of "executecontract" function
     //           - JitEvmEngine::callback_sload, JitEvmEngine::callback_sstore, ...
     //           - possibly other code! => try not to change this!
     // TODO: these are really all pointers
     pub stack: usize,
     pub memory: usize,
-    pub storage: usize,
+    pub state: usize,
+    pub address: usize,
+    pub host_error: usize,
+    pub inspector: usize,   // JitEvmInspectorHook, 0 if execution is not inspected
+    pub host: usize,        // JitEvmHostHook, 0 for the journaled state at `address`
+    pub sp: usize,          // set when the contract exits, the stack it left behind ends here
 }
 
+// byte offset of JitEvmExecutionContext::sp, for compiled code that stores it
+const JIT_EVM_EXECUTION_CONTEXT_SP_OFFSET: u64 = std::mem::offset_of!(JitEvmExecutionContext, sp) as u64;
+
 impl JitEvmExecutionContext {
     pub fn new_from_holder(container: &mut JitEvmExecutionContextHolder) -> Self {
         Self {
             stack: &mut container.stack as *mut _ as usize,
             memory: &mut container.memory as *mut _ as usize,
-            storage: &mut container.storage as *mut _ as usize,
+            state: &mut container.state as *mut _ as usize,
+            address: &mut container.address as *mut _ as usize,
+            host_error: &mut container.host_error as *mut _ as usize,
+            inspector: 0,
+            host: 0,
+            sp: 0,
+        }
+    }
+
+    // elements on the stack when the contract exited, bottom first
+    pub fn final_stack(&self) -> &[U256] {
+        unsafe { std::slice::from_raw_parts(self.stack as *const U256, (self.sp - self.stack) / EVM_STACK_ELEMENT_SIZE as usize) }
+    }
+
+    // only reports anything if the contract was compiled with instrumentation
+    pub fn set_inspector(&mut self, hook: &mut JitEvmInspectorHook) {
+        self.inspector = hook as *mut _ as usize;
+    }
+
+    // storage callbacks go to this host instead of the journaled state
+    pub fn set_host(&mut self, hook: &mut JitEvmHostHook) {
+        self.host = hook as *mut _ as usize;
+    }
+
+    fn with_host<T, F: FnOnce(&mut dyn EvmHost) -> T>(&mut self, f: F) -> T {
+        if self.host != 0 {
+            let hook: &mut JitEvmHostHook = unsafe { &mut *(self.host as *mut _) };
+            return f(&mut *hook.host);
         }
+        let state: &mut EvmJournaledState = unsafe { &mut *(self.state as *mut _) };
+        let address: &H160 = unsafe { &*(self.address as *const _) };
+        f(&mut state.host(*address))
+    }
+}
+
+
+// A host other than the journaled state of the execution context (e.g., a storage backend
+// that can fail), for the storage callbacks of the compiled code.
+pub struct JitEvmHostHook<'a> {
+    host: &'a mut dyn EvmHost,
+}
+
+impl<'a> JitEvmHostHook<'a> {
+    pub fn new(host: &'a mut dyn EvmHost) -> Self {
+        Self { host }
     }
 }
 
@@ -172,7 +290,9 @@ impl JitEvmExecutionContext {
 pub struct JitEvmExecutionContextHolder {
     pub stack: [U256; 1024],
     pub memory: [u8; 4096000],
-    pub storage: HashMap<U256, U256>,
+    pub state: EvmJournaledState,
+    pub address: H160,   // account whose storage the code operates on
+    pub host_error: Option<String>,   // message of the callback failure that stopped the contract
 }
 
 impl JitEvmExecutionContextHolder {
@@ -180,7 +300,56 @@ impl JitEvmExecutionContextHolder {
         Self {
             stack: [U256::zero(); 1024],
             memory: [0u8; 4096000],
-            storage: HashMap::<U256, U256>::new(),
+            state: EvmJournaledState::new(),
+            address: H160::zero(),
+            host_error: None,
+        }
+    }
+
+    pub fn end_transaction(&mut self) -> Vec<EvmLog> {
+        self.state.end_transaction()
+    }
+}
+
+
+// Adapts the callbacks of instrumented code to an EvmInspector. A step is reported when the
+// compiled code reaches an instruction (each one, or the first one of each block) and ends
+// when the next one is reached or the contract exits, so with block instrumentation the
+// stack at the end of a step is the one at the end of the block.
+pub struct JitEvmInspectorHook<'a> {
+    code: &'a IndexedEvmCode,
+    inspector: &'a mut dyn EvmInspector,
+    pending: Option<(usize, usize)>,   // instruction index and journal length of the current step
+}
+
+impl<'a> JitEvmInspectorHook<'a> {
+    pub fn new(code: &'a IndexedEvmCode, inspector: &'a mut dyn EvmInspector) -> Self {
+        Self { code, inspector, pending: None }
+    }
+
+    fn build_step<'b>(code: &'b IndexedEvmCode, opidx: usize, stack: &'b [U256], state: &EvmJournaledState) -> EvmStep<'b> {
+        EvmStep {
+            pc: code.opidx2target[&opidx].as_usize(),
+            op: &code.code.ops[opidx],
+            gas: 0,
+            gas_cost: 0,
+            stack,
+            memory_size: 0,   // memory is not supported by the JIT yet
+            depth: state.depth().max(1),   // frames checkpoint at entry, the outermost may not
+        }
+    }
+
+    fn step(&mut self, opidx: usize, stack: &[U256], state: &EvmJournaledState) {
+        self.step_end(stack, state, EVM_JIT_STATUS_OK);
+        self.inspector.step(&Self::build_step(self.code, opidx, stack, state));
+        self.pending = Some((opidx, state.journal_len()));
+    }
+
+    fn step_end(&mut self, stack: &[U256], state: &EvmJournaledState, status: u64) {
+        if let Some((opidx, journal_len)) = self.pending.take() {
+            let storage_diffs = state.storage_diffs_since(journal_len);
+            let step = Self::build_step(self.code, opidx, stack, state);
+            self.inspector.step_end(&step, &storage_diffs, status_message(status));
         }
     }
 }
@@ -194,6 +363,7 @@ pub struct JitEvmEngine<'ctx> {
     pub type_ptrint: IntType<'ctx>,
     pub type_stackel: IntType<'ctx>,
     pub type_retval: IntType<'ctx>,
+    pub instrumentation: JitEvmInstrumentation,
 }
 
 impl<'ctx> JitEvmEngine<'ctx> {
@@ -228,6 +398,7 @@ impl<'ctx> JitEvmEngine<'ctx> {
             type_ptrint,
             type_stackel,
             type_retval,
+            instrumentation: JitEvmInstrumentation::None,
         })
     }
 
@@ -332,38 +503,401 @@ impl<'ctx> JitEvmEngine<'ctx> {
         sp_int
     }
 
+    fn build_jump_switch<'a>(
+        &'a self,
+        book: JitEvmEngineBookkeeping<'a>,
+        this: JitEvmEngineSimpleBlock<'a>,
+        target: IntValue<'a>,
+        targets: &[usize],
+        code: &IndexedEvmCode,
+        cfg: &EvmCfg,
+        blocks: &[JitEvmEngineSimpleBlock<'a>],
+        error_jumpdest: JitEvmEngineSimpleBlock<'a>)
+    {
+        // dispatch on the byte offsets of the given Jumpdest blocks (in block order, so that
+        // codegen is deterministic), every other target is an invalid jump
+        let cases: Vec<_> = targets.iter().map(|t| {
+            let jmp_target = code.opidx2target[&cfg.blocks[*t].ops.start];   // Jumpdests always start a block
+            (self.type_stackel.const_int_arbitrary_precision(&jmp_target.0), blocks[*t].block)
+        }).collect();
+        self.builder.build_switch(target, error_jumpdest.block, &cases);
+        for t in targets {
+            blocks[*t].add_incoming(&book, &this);
+        }
+        error_jumpdest.add_incoming(&book, &this);
+    }
+
+    fn build_store_sp<'a>(&'a self, execution_context: IntValue<'a>, sp: IntValue<'a>) {
+        let field = self.builder.build_int_add(execution_context, self.type_ptrint.const_int(JIT_EVM_EXECUTION_CONTEXT_SP_OFFSET, false), "");
+        let field_ptr = self.builder.build_int_to_ptr(field, self.type_ptrint.ptr_type(AddressSpace::Generic), "");
+        self.builder.build_store(field_ptr, sp);
+    }
+
+    fn build_load_sp<'a>(&'a self, execution_context: IntValue<'a>) -> IntValue<'a> {
+        let field = self.builder.build_int_add(execution_context, self.type_ptrint.const_int(JIT_EVM_EXECUTION_CONTEXT_SP_OFFSET, false), "");
+        let field_ptr = self.builder.build_int_to_ptr(field, self.type_ptrint.ptr_type(AddressSpace::Generic), "");
+        self.builder.build_load(field_ptr, "").into_int_value()
+    }
+
+    fn build_return_status<'a>(
+        &'a self,
+        execution_context: IntValue<'a>,
+        sp: IntValue<'a>,
+        status: u64,
+        callback_step_end_func: FunctionValue<'a>)
+    {
+        self.build_store_sp(execution_context, sp);
+        // instrumented code reports the end of the last step before it returns
+        if self.instrumentation != JitEvmInstrumentation::None {
+            self.builder.build_call(callback_step_end_func, &[
+                execution_context.into(),
+                sp.into(),
+                self.type_retval.const_int(status, false).into(),
+            ], "");
+        }
+        self.builder.build_return(Some(&self.type_retval.const_int(status, false)));
+    }
+
+    fn build_callback_status_check<'a>(
+        &'a self,
+        book: JitEvmEngineBookkeeping<'a>,
+        this: JitEvmEngineSimpleBlock<'a>,
+        retval: IntValue<'a>,
+        label: &str,
+        suffix: &str,
+        callback_step_end_func: FunctionValue<'a>) -> (JitEvmEngineBookkeeping<'a>, JitEvmEngineSimpleBlock<'a>)
+    {
+        // a callback that does not return EVM_JIT_STATUS_OK aborts the contract with its
+        // status (see guard_callback)
+        self.build_status_check(book, this, retval, EVM_JIT_STATUS_OK, label, suffix, callback_step_end_func)
+    }
+
+    fn build_status_check<'a>(
+        &'a self,
+        book: JitEvmEngineBookkeeping<'a>,
+        this: JitEvmEngineSimpleBlock<'a>,
+        retval: IntValue<'a>,
+        expected: u64,
+        label: &str,
+        suffix: &str,
+        callback_step_end_func: FunctionValue<'a>) -> (JitEvmEngineBookkeeping<'a>, JitEvmEngineSimpleBlock<'a>)
+    {
+        // any other status than the expected one is returned, otherwise the block continues
+        // in a new LLVM basic block
+        let cont = JitEvmEngineSimpleBlock::new(self, this.block, &format!("{} / ok", label), &format!("{}_ok", suffix));
+        let failed = self.context.insert_basic_block_after(this.block, &format!("{} / failed", label));
+        self.builder.position_at_end(failed);
+        self.build_store_sp(book.execution_context, book.sp);
+        if self.instrumentation != JitEvmInstrumentation::None {
+            self.builder.build_call(callback_step_end_func, &[
+                book.execution_context.into(),
+                book.sp.into(),
+                retval.into(),
+            ], "");
+        }
+        self.builder.build_return(Some(&retval));
+
+        self.builder.position_at_end(this.block);
+        let cmp = self.builder.build_int_compare(IntPredicate::EQ, retval, self.type_retval.const_int(expected, false), "");
+        self.builder.build_conditional_branch(cmp, cont.block, failed);
+        cont.add_incoming(&book, &this);
+
+        self.builder.position_at_end(cont.block);
+        let book = JitEvmEngineBookkeeping {
+            execution_context: cont.phi_execution_context.as_basic_value().into_int_value(),
+            sp_min: cont.phi_sp_min.as_basic_value().into_int_value(),
+            sp_max: cont.phi_sp_max.as_basic_value().into_int_value(),
+            sp: cont.phi_sp.as_basic_value().into_int_value(),
+        };
+        (book, cont)
+    }
+
+    fn build_section_entry_check<'a>(
+        &'a self,
+        book: JitEvmEngineBookkeeping<'a>,
+        this: JitEvmEngineSimpleBlock<'a>,
+        section_type: &EvmEofType,
+        depth: Option<IntValue<'a>>,
+        error_stack: JitEvmEngineSimpleBlock<'a>,
+        label: &str,
+        suffix: &str) -> (JitEvmEngineBookkeeping<'a>, JitEvmEngineSimpleBlock<'a>)
+    {
+        // the stack must have room for the max stack height of the section (EIP-5450), and a
+        // CALLF for another frame on the return stack (EIP-4750)
+        let execution_context_ptr = self.builder.build_int_to_ptr(book.execution_context, self.type_ptrint.ptr_type(AddressSpace::Generic), "");
+        let stack = self.builder.build_load(execution_context_ptr, "").into_int_value();
+        let room = (EVM_STACK_SIZE + section_type.inputs as usize - section_type.max_stack_height as usize) as u64;
+        let limit = self.builder.build_int_add(stack, self.type_ptrint.const_int(room * EVM_STACK_ELEMENT_SIZE, false), "");
+        let mut overflow = self.builder.build_int_compare(IntPredicate::UGT, book.sp, limit, "");
+        if let Some(depth) = depth {
+            let too_deep = self.builder.build_int_compare(IntPredicate::UGE, depth, self.type_ptrint.const_int(EVM_STACK_SIZE as u64, false), "");
+            overflow = self.builder.build_or(overflow, too_deep, "");
+        }
+
+        let cont = JitEvmEngineSimpleBlock::new(self, this.block, label, suffix);
+        self.builder.position_at_end(this.block);
+        self.builder.build_conditional_branch(overflow, error_stack.block, cont.block);
+        error_stack.add_incoming(&book, &this);
+        cont.add_incoming(&book, &this);
+
+        self.builder.position_at_end(cont.block);
+        let book = JitEvmEngineBookkeeping {
+            execution_context: cont.phi_execution_context.as_basic_value().into_int_value(),
+            sp_min: cont.phi_sp_min.as_basic_value().into_int_value(),
+            sp_max: cont.phi_sp_max.as_basic_value().into_int_value(),
+            sp: cont.phi_sp.as_basic_value().into_int_value(),
+        };
+        (book, cont)
+    }
+
+
+    // EVM ARITHMETIC (LLVM division/shift semantics differ from the EVM's for corner cases)
+
+    fn build_select_int<'a>(&'a self, cond: IntValue<'a>, a: IntValue<'a>, b: IntValue<'a>) -> IntValue<'a> {
+        self.builder.build_select(cond, a, b, "").into_int_value()
+    }
+
+    fn build_evm_div<'a>(&'a self, a: IntValue<'a>, b: IntValue<'a>) -> IntValue<'a> {
+        // division by zero gives zero (and must not reach udiv)
+        let zero = self.type_stackel.const_zero();
+        let b_is_zero = self.builder.build_int_compare(IntPredicate::EQ, b, zero, "");
+        let b_safe = self.build_select_int(b_is_zero, self.type_stackel.const_int(1, false), b);
+        let d = self.builder.build_int_unsigned_div(a, b_safe, "");
+        self.build_select_int(b_is_zero, zero, d)
+    }
+
+    fn build_evm_sdiv<'a>(&'a self, a: IntValue<'a>, b: IntValue<'a>) -> IntValue<'a> {
+        // division by zero gives zero, INT_MIN / -1 overflows to INT_MIN (both must not reach sdiv)
+        let zero = self.type_stackel.const_zero();
+        let one = self.type_stackel.const_int(1, false);
+        let int_min = self.type_stackel.const_int_arbitrary_precision(&[0, 0, 0, 1 << 63]);
+        let b_is_zero = self.builder.build_int_compare(IntPredicate::EQ, b, zero, "");
+        let a_is_min = self.builder.build_int_compare(IntPredicate::EQ, a, int_min, "");
+        let b_is_minus_one = self.builder.build_int_compare(IntPredicate::EQ, b, self.type_stackel.const_all_ones(), "");
+        let overflow = self.builder.build_and(a_is_min, b_is_minus_one, "");
+        let b_safe = self.build_select_int(self.builder.build_or(b_is_zero, overflow, ""), one, b);
+        let d = self.builder.build_int_signed_div(a, b_safe, "");
+        self.build_select_int(b_is_zero, zero, d)
+    }
+
+    fn build_evm_mod<'a>(&'a self, a: IntValue<'a>, b: IntValue<'a>) -> IntValue<'a> {
+        // modulo zero gives zero, which is what urem by one gives as well (also used on wide integers)
+        let type_int = b.get_type();
+        let b_is_zero = self.builder.build_int_compare(IntPredicate::EQ, b, type_int.const_zero(), "");
+        let b_safe = self.build_select_int(b_is_zero, type_int.const_int(1, false), b);
+        self.builder.build_int_unsigned_rem(a, b_safe, "")
+    }
+
+    fn build_evm_smod<'a>(&'a self, a: IntValue<'a>, b: IntValue<'a>) -> IntValue<'a> {
+        // modulo zero and modulo -1 give zero, which is what srem by one gives as well
+        let b_is_zero = self.builder.build_int_compare(IntPredicate::EQ, b, self.type_stackel.const_zero(), "");
+        let b_is_minus_one = self.builder.build_int_compare(IntPredicate::EQ, b, self.type_stackel.const_all_ones(), "");
+        let b_safe = self.build_select_int(self.builder.build_or(b_is_zero, b_is_minus_one, ""), self.type_stackel.const_int(1, false), b);
+        self.builder.build_int_signed_rem(a, b_safe, "")
+    }
+
+    fn build_evm_addmod<'a>(&'a self, a: IntValue<'a>, b: IntValue<'a>, n: IntValue<'a>) -> IntValue<'a> {
+        // intermediate sum must not wrap at 256 bits
+        let type_wide = self.context.custom_width_int_type(512);
+        let a = self.builder.build_int_z_extend(a, type_wide, "");
+        let b = self.builder.build_int_z_extend(b, type_wide, "");
+        let n = self.builder.build_int_z_extend(n, type_wide, "");
+        let d = self.builder.build_int_add(a, b, "");
+        let d = self.build_evm_mod(d, n);
+        self.builder.build_int_truncate(d, self.type_stackel, "")
+    }
+
+    fn build_evm_mulmod<'a>(&'a self, a: IntValue<'a>, b: IntValue<'a>, n: IntValue<'a>) -> IntValue<'a> {
+        // intermediate product must not wrap at 256 bits
+        let type_wide = self.context.custom_width_int_type(512);
+        let a = self.builder.build_int_z_extend(a, type_wide, "");
+        let b = self.builder.build_int_z_extend(b, type_wide, "");
+        let n = self.builder.build_int_z_extend(n, type_wide, "");
+        let d = self.builder.build_int_mul(a, b, "");
+        let d = self.build_evm_mod(d, n);
+        self.builder.build_int_truncate(d, self.type_stackel, "")
+    }
+
+    fn build_evm_signextend<'a>(&'a self, b: IntValue<'a>, x: IntValue<'a>) -> IntValue<'a> {
+        // sign bit is bit 8*b+7, for b >= 31 the value is unchanged
+        let max = self.type_stackel.const_int(31, false);
+        let b_is_small = self.builder.build_int_compare(IntPredicate::ULT, b, max, "");
+        let b = self.build_select_int(b_is_small, b, max);
+        let shift = self.builder.build_int_sub(
+            self.type_stackel.const_int(248, false),
+            self.builder.build_int_mul(b, self.type_stackel.const_int(8, false), ""),
+            "");
+        let d = self.builder.build_left_shift(x, shift, "");
+        self.builder.build_right_shift(d, shift, true, "")
+    }
+
+    fn build_evm_byte<'a>(&'a self, i: IntValue<'a>, x: IntValue<'a>) -> IntValue<'a> {
+        // i-th byte counted from the most significant one, zero for i >= 32
+        let i_is_valid = self.builder.build_int_compare(IntPredicate::ULT, i, self.type_stackel.const_int(32, false), "");
+        let i = self.build_select_int(i_is_valid, i, self.type_stackel.const_zero());
+        let shift = self.builder.build_int_sub(
+            self.type_stackel.const_int(248, false),
+            self.builder.build_int_mul(i, self.type_stackel.const_int(8, false), ""),
+            "");
+        let d = self.builder.build_right_shift(x, shift, false, "");
+        let d = self.builder.build_and(d, self.type_stackel.const_int(0xff, false), "");
+        self.build_select_int(i_is_valid, d, self.type_stackel.const_zero())
+    }
+
+    fn build_evm_shl<'a>(&'a self, shift: IntValue<'a>, x: IntValue<'a>) -> IntValue<'a> {
+        // shifting by >= 256 gives zero (LLVM: poison, but not selected)
+        let shift_is_valid = self.builder.build_int_compare(IntPredicate::ULT, shift, self.type_stackel.const_int(256, false), "");
+        let d = self.builder.build_left_shift(x, shift, "");
+        self.build_select_int(shift_is_valid, d, self.type_stackel.const_zero())
+    }
+
+    fn build_evm_shr<'a>(&'a self, shift: IntValue<'a>, x: IntValue<'a>) -> IntValue<'a> {
+        // shifting by >= 256 gives zero (LLVM: poison, but not selected)
+        let shift_is_valid = self.builder.build_int_compare(IntPredicate::ULT, shift, self.type_stackel.const_int(256, false), "");
+        let d = self.builder.build_right_shift(x, shift, false, "");
+        self.build_select_int(shift_is_valid, d, self.type_stackel.const_zero())
+    }
+
+    fn build_evm_sar<'a>(&'a self, shift: IntValue<'a>, x: IntValue<'a>) -> IntValue<'a> {
+        // shifting by >= 256 fills with the sign bit, same as shifting by 255
+        let max = self.type_stackel.const_int(255, false);
+        let shift_is_small = self.builder.build_int_compare(IntPredicate::ULT, shift, max, "");
+        let shift = self.build_select_int(shift_is_small, shift, max);
+        self.builder.build_right_shift(x, shift, true, "")
+    }
+
+    fn build_helper_exp<'a>(&'a self) -> FunctionValue<'a> {
+        // square-and-multiply, as a separate function to keep contract blocks free of loops
+        let fn_type = self.type_stackel.fn_type(&[self.type_stackel.into(), self.type_stackel.into()], false);
+        let function = self.module.add_function("helper_exp", fn_type, None);
+        let base = function.get_nth_param(0).unwrap().into_int_value();
+        let exponent = function.get_nth_param(1).unwrap().into_int_value();
+
+        let block_entry = self.context.append_basic_block(function, "entry");
+        let block_head = self.context.append_basic_block(function, "head");
+        let block_body = self.context.append_basic_block(function, "body");
+        let block_exit = self.context.append_basic_block(function, "exit");
+
+        self.builder.position_at_end(block_entry);
+        self.builder.build_unconditional_branch(block_head);
+
+        self.builder.position_at_end(block_head);
+        let phi_result = self.builder.build_phi(self.type_stackel, "result");
+        let phi_base = self.builder.build_phi(self.type_stackel, "base");
+        let phi_exponent = self.builder.build_phi(self.type_stackel, "exponent");
+        let result = phi_result.as_basic_value().into_int_value();
+        let base_ = phi_base.as_basic_value().into_int_value();
+        let exponent_ = phi_exponent.as_basic_value().into_int_value();
+        let done = self.builder.build_int_compare(IntPredicate::EQ, exponent_, self.type_stackel.const_zero(), "");
+        self.builder.build_conditional_branch(done, block_exit, block_body);
+
+        self.builder.position_at_end(block_body);
+        let bit = self.builder.build_and(exponent_, self.type_stackel.const_int(1, false), "");
+        let bit = self.builder.build_int_compare(IntPredicate::NE, bit, self.type_stackel.const_zero(), "");
+        let result_next = self.build_select_int(bit, self.builder.build_int_mul(result, base_, ""), result);
+        let base_next = self.builder.build_int_mul(base_, base_, "");
+        let exponent_next = self.builder.build_right_shift(exponent_, self.type_stackel.const_int(1, false), false, "");
+        self.builder.build_unconditional_branch(block_head);
+
+        phi_result.add_incoming(&[(&self.type_stackel.const_int(1, false), block_entry), (&result_next, block_body)]);
+        phi_base.add_incoming(&[(&base, block_entry), (&base_next, block_body)]);
+        phi_exponent.add_incoming(&[(&exponent, block_entry), (&exponent_next, block_body)]);
+
+        self.builder.position_at_end(block_exit);
+        self.builder.build_return(Some(&result));
+
+        function
+    }
+
 
     // CALLBACKS FOR OPERATIONS THAT CANNOT HAPPEN PURELY WITHIN THE EVM
 
-    pub extern "C" fn callback_sload(exectx: usize, sp: usize) -> u64 {
+    // a panic must not unwind through the JIT'ed code: every callback runs in here, and a
+    // panic makes the contract exit with EVM_JIT_STATUS_ERROR_HOST, the message is kept in
+    // the execution context
+    fn guard_callback<F: FnOnce(&mut JitEvmExecutionContext) -> Result<(), (u64, String)>>(exectx: usize, f: F) -> u64 {
         let exectx: &mut JitEvmExecutionContext = unsafe { &mut *(exectx as *mut _) };
-        let storage: &mut HashMap<U256, U256> = unsafe { &mut *(exectx.storage as *mut _) };
-
-        let key: &mut U256 = unsafe { &mut *((sp - 1*EVM_STACK_ELEMENT_SIZE as usize) as *mut _) };
 
-        match storage.get(key) {
-            Some(value) => {
-                *key = *value;
+        let (status, msg) = match std::panic::catch_unwind(AssertUnwindSafe(|| f(&mut *exectx))) {
+            Ok(Ok(())) => return EVM_JIT_STATUS_OK,
+            Ok(Err((status, msg))) => (status, msg),
+            Err(payload) => {
+                let msg = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+                    (Some(msg), _) => msg.to_string(),
+                    (_, Some(msg)) => msg.clone(),
+                    _ => "callback panicked".to_string(),
+                };
+                (EVM_JIT_STATUS_ERROR_HOST, msg)
             },
-            None => {
-                // TODO: proper error handling!
-                panic!("Sload key not found: {}", *key);
-            }
-        }
+        };
+
+        let host_error: &mut Option<String> = unsafe { &mut *(exectx.host_error as *mut _) };
+        *host_error = Some(msg);
+        status
+    }
+
+    pub extern "C" fn callback_sload(exectx: usize, sp: usize) -> u64 {
+        Self::guard_callback(exectx, |exectx| {
+            let key: &mut U256 = unsafe { &mut *((sp - 1*EVM_STACK_ELEMENT_SIZE as usize) as *mut _) };
 
-        0
+            *key = exectx.with_host(|host| host.sload(*key)).map_err(|e| (EVM_JIT_STATUS_ERROR_STORAGE, e.to_string()))?;
+            Ok(())
+        })
     }
 
     pub extern "C" fn callback_sstore(exectx: usize, sp: usize) -> u64 {
-        let exectx: &mut JitEvmExecutionContext = unsafe { &mut *(exectx as *mut _) };
-        let storage: &mut HashMap<U256, U256> = unsafe { &mut *(exectx.storage as *mut _) };
+        Self::guard_callback(exectx, |exectx| {
+            let key: &mut U256 = unsafe { &mut *((sp - 1*EVM_STACK_ELEMENT_SIZE as usize) as *mut _) };
+            let value: &mut U256 = unsafe { &mut *((sp - 2*EVM_STACK_ELEMENT_SIZE as usize) as *mut _) };
+
+            exectx.with_host(|host| host.sstore(*key, *value)).map_err(|e| (EVM_JIT_STATUS_ERROR_STORAGE, e.to_string()))
+        })
+    }
+
+    pub extern "C" fn callback_tload(exectx: usize, sp: usize) -> u64 {
+        Self::guard_callback(exectx, |exectx| {
+            let key: &mut U256 = unsafe { &mut *((sp - 1*EVM_STACK_ELEMENT_SIZE as usize) as *mut _) };
+
+            // unlike storage, missing transient storage is zero by definition
+            *key = exectx.with_host(|host| host.tload(*key));
+            Ok(())
+        })
+    }
+
+    pub extern "C" fn callback_tstore(exectx: usize, sp: usize) -> u64 {
+        Self::guard_callback(exectx, |exectx| {
+            let key: &mut U256 = unsafe { &mut *((sp - 1*EVM_STACK_ELEMENT_SIZE as usize) as *mut _) };
+            let value: &mut U256 = unsafe { &mut *((sp - 2*EVM_STACK_ELEMENT_SIZE as usize) as *mut _) };
+
+            exectx.with_host(|host| host.tstore(*key, *value));
+            Ok(())
+        })
+    }
 
-        let key: &mut U256 = unsafe { &mut *((sp - 1*EVM_STACK_ELEMENT_SIZE as usize) as *mut _) };
-        let value: &mut U256 = unsafe { &mut *((sp - 2*EVM_STACK_ELEMENT_SIZE as usize) as *mut _) };
+    // INSTRUMENTATION (see JitEvmInstrumentation)
+
+    fn with_inspector<F: FnOnce(&mut JitEvmInspectorHook, &[U256], &EvmJournaledState)>(exectx: &mut JitEvmExecutionContext, sp: usize, f: F) {
+        if exectx.inspector == 0 {
+            return;
+        }
+        let hook: &mut JitEvmInspectorHook = unsafe { &mut *(exectx.inspector as *mut _) };
+        let state: &EvmJournaledState = unsafe { &*(exectx.state as *const _) };
+        let stack: &[U256] = unsafe { std::slice::from_raw_parts(exectx.stack as *const U256, (sp - exectx.stack) / EVM_STACK_ELEMENT_SIZE as usize) };
+        f(hook, stack, state);
+    }
 
-        storage.insert(*key, *value);
+    pub extern "C" fn callback_step(exectx: usize, sp: usize, opidx: u64) -> u64 {
+        Self::guard_callback(exectx, |exectx| {
+            Self::with_inspector(exectx, sp, |hook, stack, state| hook.step(opidx as usize, stack, state));
+            Ok(())
+        })
+    }
 
-        0
+    pub extern "C" fn callback_step_end(exectx: usize, sp: usize, status: u64) -> u64 {
+        Self::guard_callback(exectx, |exectx| {
+            Self::with_inspector(exectx, sp, |hook, stack, state| hook.step_end(stack, state, status));
+            Ok(())
+        })
     }
 
     // pub extern "C" fn callback_add(ptr_a: usize, ptr_b: usize) -> u64 {
@@ -375,8 +909,168 @@ impl<'ctx> JitEvmEngine<'ctx> {
     // }
 
 
+    // writes the module (with the contracts compiled so far) as LLVM IR, assembly or an object file
+    pub fn emit(&self, kind: JitEvmEmit, path: &str) -> Result<(), JitEvmEngineError> {
+        // https://github.com/TheDan64/inkwell/issues/184
+        // https://thedan64.github.io/inkwell/inkwell/targets/struct.TargetMachine.html#method.write_to_file
+        use inkwell::targets::{TargetMachine, RelocMode, CodeModel, FileType};
+
+        let file_type = match kind {
+            JitEvmEmit::Ir => {
+                self.module.print_to_file(path)?;
+                return Ok(());
+            },
+            JitEvmEmit::Asm => FileType::Assembly,
+            JitEvmEmit::Obj => FileType::Object,
+        };
+
+        let triple = TargetMachine::get_default_triple();
+        let cpu = TargetMachine::get_host_cpu_name().to_string();
+        let features = TargetMachine::get_host_cpu_features().to_string();
+
+        let target = Target::from_triple(&triple)?;
+        let machine = target
+            .create_target_machine(
+                &triple,
+                &cpu,
+                &features,
+                OptimizationLevel::Aggressive,
+                RelocMode::Default,
+                CodeModel::Default,
+            )
+            .ok_or("could not create target machine")?;
+
+        machine.write_to_file(&self.module, file_type, path.as_ref())?;
+        Ok(())
+    }
+
     pub fn jit_compile_contract(&self, code: &IndexedEvmCode, debug_ir: Option<String>, debug_asm: Option<String>) -> Result<JitFunction<JitEvmCompiledContract>, JitEvmEngineError> {
 
+        if code.code.ops.is_empty() {
+            return Err(JitEvmEngineError::EmptyCode);
+        }
+
+        // a superinstruction would be reported as one step, unlike by the interpreter
+        if self.instrumentation != JitEvmInstrumentation::None && code.code.ops.iter().any(|op| op.is_augmented()) {
+            return Err("instrumentation of superinstructions is not supported, compile unoptimized code".into());
+        }
+
+        let callbacks = self.build_callbacks();
+
+        let executecontract_fn_type = self.type_retval.fn_type(&[self.type_ptrint.into()], false);
+        let function = self.module.add_function("executecontract", executecontract_fn_type, None);
+        self.build_code_function(function, code, &callbacks, None)?;
+
+        self.finish_compile(debug_ir, debug_asm)
+    }
+
+    // compiles each code section to a function (execution context, sp, depth of the return
+    // stack) -> status, CALLF calls them through eof_dispatch (see build_eof_dispatch) and RETF
+    // returns to the caller
+    pub fn jit_compile_eof(&self, eof: &EvmEofContainer, debug_ir: Option<String>, debug_asm: Option<String>) -> Result<JitFunction<JitEvmCompiledContract>, JitEvmEngineError> {
+
+        if self.instrumentation != JitEvmInstrumentation::None {
+            return Err("instrumentation of EOF code is not supported".into());
+        }
+
+        let callbacks = self.build_callbacks();
+
+        let section_fn_type = self.type_retval.fn_type(&[self.type_ptrint.into(), self.type_ptrint.into(), self.type_ptrint.into()], false);
+        let functions: Vec<_> = (0..eof.code.len())
+            .map(|i| self.module.add_function(&format!("eof_section_{}", i), section_fn_type, None))
+            .collect();
+        let dispatch = self.build_eof_dispatch(&functions);
+        for (i, code) in eof.index().iter().enumerate() {
+            self.build_code_function(functions[i], code, &callbacks, Some((&eof.types, dispatch)))?;
+        }
+
+        // the contract runs the first section with empty stacks (it cannot return)
+        let executecontract_fn_type = self.type_retval.fn_type(&[self.type_ptrint.into()], false);
+        let function = self.module.add_function("executecontract", executecontract_fn_type, None);
+        let setup_block = self.context.append_basic_block(function, "setup");
+        self.builder.position_at_end(setup_block);
+        let execution_context = function.get_nth_param(0).unwrap().into_int_value();
+        let execution_context_ptr = self.builder.build_int_to_ptr(execution_context, self.type_ptrint.ptr_type(AddressSpace::Generic), "");
+        let sp_int = self.builder.build_load(execution_context_ptr, "").into_int_value();
+        let retval = self.builder.build_call(dispatch, &[
+            execution_context.into(),
+            sp_int.into(),
+            self.type_ptrint.const_int(0, false).into(),
+            self.type_ptrint.const_int(0, false).into(),
+        ], "").try_as_basic_value().left().unwrap().into_int_value();
+        self.builder.build_return(Some(&retval));
+
+        self.finish_compile(debug_ir, debug_asm)
+    }
+
+    // eof_dispatch (execution context, sp, depth of the return stack, section) -> status runs a
+    // section and the sections it JUMPFs to in a loop, so that JUMPF does not grow the native
+    // stack (LLVM 14 cannot force a tail call): a section ending with JUMPF leaves its sp in the
+    // execution context and returns EVM_JIT_STATUS_JUMPF + the target
+    fn build_eof_dispatch<'a>(&'a self, functions: &[FunctionValue<'a>]) -> FunctionValue<'a> {
+        let dispatch_fn_type = self.type_retval.fn_type(&[self.type_ptrint.into(), self.type_ptrint.into(), self.type_ptrint.into(), self.type_ptrint.into()], false);
+        let function = self.module.add_function("eof_dispatch", dispatch_fn_type, None);
+        let execution_context = function.get_nth_param(0).unwrap().into_int_value();
+        let sp_int = function.get_nth_param(1).unwrap().into_int_value();
+        let depth = function.get_nth_param(2).unwrap().into_int_value();
+        let section = function.get_nth_param(3).unwrap().into_int_value();
+
+        let setup_block = self.context.append_basic_block(function, "setup");
+        let dispatch_block = self.context.append_basic_block(function, "dispatch");
+        let call_blocks: Vec<_> = (0..functions.len())
+            .map(|i| self.context.append_basic_block(function, &format!("section #{}", i)))
+            .collect();
+        let returned_block = self.context.append_basic_block(function, "returned");
+        let jumpf_block = self.context.append_basic_block(function, "jumpf");
+        let done_block = self.context.append_basic_block(function, "done");
+
+        self.builder.position_at_end(setup_block);
+        self.builder.build_unconditional_branch(dispatch_block);
+
+        // section 0 is the default, the container only refers to sections it has
+        self.builder.position_at_end(dispatch_block);
+        let phi_section = self.builder.build_phi(self.type_ptrint, "section");
+        let phi_sp = self.builder.build_phi(self.type_ptrint, "sp");
+        phi_section.add_incoming(&[(&section, setup_block)]);
+        phi_sp.add_incoming(&[(&sp_int, setup_block)]);
+        let cases: Vec<_> = call_blocks.iter().enumerate().skip(1)
+            .map(|(i, block)| (self.type_ptrint.const_int(i as u64, false), *block))
+            .collect();
+        self.builder.build_switch(phi_section.as_basic_value().into_int_value(), call_blocks[0], &cases);
+
+        self.builder.position_at_end(returned_block);
+        let phi_retval = self.builder.build_phi(self.type_retval, "retval");
+        for (i, block) in call_blocks.iter().enumerate() {
+            self.builder.position_at_end(*block);
+            let retval = self.builder.build_call(functions[i], &[
+                execution_context.into(),
+                phi_sp.as_basic_value().into(),
+                depth.into(),
+            ], "").try_as_basic_value().left().unwrap().into_int_value();
+            self.builder.build_unconditional_branch(returned_block);
+            phi_retval.add_incoming(&[(&retval, *block)]);
+        }
+
+        // any other status is out of range of the targets (those below EVM_JIT_STATUS_JUMPF wrap around)
+        self.builder.position_at_end(returned_block);
+        let retval = phi_retval.as_basic_value().into_int_value();
+        let target = self.builder.build_int_sub(retval, self.type_retval.const_int(EVM_JIT_STATUS_JUMPF, false), "");
+        let is_jumpf = self.builder.build_int_compare(IntPredicate::ULT, target, self.type_retval.const_int(functions.len() as u64, false), "");
+        self.builder.build_conditional_branch(is_jumpf, jumpf_block, done_block);
+
+        self.builder.position_at_end(jumpf_block);
+        let sp_next = self.build_load_sp(execution_context);
+        self.builder.build_unconditional_branch(dispatch_block);
+        phi_section.add_incoming(&[(&target, jumpf_block)]);
+        phi_sp.add_incoming(&[(&sp_next, jumpf_block)]);
+
+        self.builder.position_at_end(done_block);
+        self.builder.build_return(Some(&retval));
+
+        function
+    }
+
+    fn build_callbacks(&self) -> JitEvmEngineCallbacks<'_> {
         // CALLBACKS
 
         let callback_sload_func = { // SLOAD
@@ -393,6 +1087,34 @@ impl<'ctx> JitEvmEngine<'ctx> {
             cb_func
         };
 
+        let callback_tload_func = { // TLOAD
+            let cb_type = self.type_retval.fn_type(&[self.type_ptrint.into(), self.type_ptrint.into()], false);
+            let cb_func = self.module.add_function("callback_tload", cb_type, None);
+            self.execution_engine.add_global_mapping(&cb_func, JitEvmEngine::callback_tload as usize);
+            cb_func
+        };
+
+        let callback_tstore_func = { // TSTORE
+            let cb_type = self.type_retval.fn_type(&[self.type_ptrint.into(), self.type_ptrint.into()], false);
+            let cb_func = self.module.add_function("callback_tstore", cb_type, None);
+            self.execution_engine.add_global_mapping(&cb_func, JitEvmEngine::callback_tstore as usize);
+            cb_func
+        };
+
+        let callback_step_func = { // INSTRUMENTATION
+            let cb_type = self.type_retval.fn_type(&[self.type_ptrint.into(), self.type_ptrint.into(), self.type_retval.into()], false);
+            let cb_func = self.module.add_function("callback_step", cb_type, None);
+            self.execution_engine.add_global_mapping(&cb_func, JitEvmEngine::callback_step as usize);
+            cb_func
+        };
+
+        let callback_step_end_func = { // INSTRUMENTATION
+            let cb_type = self.type_retval.fn_type(&[self.type_ptrint.into(), self.type_ptrint.into(), self.type_retval.into()], false);
+            let cb_func = self.module.add_function("callback_step_end", cb_type, None);
+            self.execution_engine.add_global_mapping(&cb_func, JitEvmEngine::callback_step_end as usize);
+            cb_func
+        };
+
         // let callback_add_func = { // ADD
         //     // let cb_type = self.type_stackel.fn_type(&[self.type_stackel.into(), self.type_stackel.into()], false);
         //     let cb_type = self.type_retval.fn_type(&[self.type_ptrint.into(), self.type_ptrint.into()], false);
@@ -402,12 +1124,30 @@ impl<'ctx> JitEvmEngine<'ctx> {
         // };
 
 
-        // SETUP JIT'ED CONTRACT FUNCTION
+        // HELPERS
 
-        let executecontract_fn_type = self.type_retval.fn_type(&[self.type_ptrint.into()], false);
-        let function = self.module.add_function("executecontract", executecontract_fn_type, None);
+        let helper_exp_func = self.build_helper_exp();
 
+        JitEvmEngineCallbacks {
+            sload: callback_sload_func,
+            sstore: callback_sstore_func,
+            tload: callback_tload_func,
+            tstore: callback_tstore_func,
+            step: callback_step_func,
+            step_end: callback_step_end_func,
+            helper_exp: helper_exp_func,
+        }
+    }
 
+    // renders the code into the (empty) function, either "executecontract" or a code section
+    // of an EOF container (with the types of all sections and eof_dispatch)
+    fn build_code_function<'a>(
+        &'a self,
+        function: FunctionValue<'a>,
+        code: &IndexedEvmCode,
+        callbacks: &JitEvmEngineCallbacks<'a>,
+        sections: Option<(&[EvmEofType], FunctionValue<'a>)>) -> Result<(), JitEvmEngineError>
+    {
         // SETUP HANDLER
 
         let setup_block = self.context.append_basic_block(function, "setup");
@@ -415,8 +1155,13 @@ impl<'ctx> JitEvmEngine<'ctx> {
 
         let setup_book = {
             let execution_context = function.get_nth_param(0).unwrap().into_int_value();
-            let execution_context_ptr = self.builder.build_int_to_ptr(execution_context, self.type_ptrint.ptr_type(AddressSpace::Generic), "");
-            let sp_int = self.builder.build_load(execution_context_ptr, "").into_int_value();
+            let sp_int = match sections {
+                None => {
+                    let execution_context_ptr = self.builder.build_int_to_ptr(execution_context, self.type_ptrint.ptr_type(AddressSpace::Generic), "");
+                    self.builder.build_load(execution_context_ptr, "").into_int_value()
+                },
+                Some(_) => function.get_nth_param(1).unwrap().into_int_value(),   // the inputs are on top of the stack
+            };
             let sp_max = self.builder.build_int_add(sp_int, self.type_ptrint.const_int((EVM_STACK_SIZE-1) as u64, false), "");
             // let retval = self.type_retval.const_int(0, false);
             JitEvmEngineBookkeeping {
@@ -429,51 +1174,80 @@ impl<'ctx> JitEvmEngine<'ctx> {
         };
 
 
-        // INSTRUCTIONS
+        // BASIC BLOCKS
 
-        let ops_len = code.code.ops.len();
-        assert!(ops_len > 0);
+        let cfg = code.cfg();
+        let blocks_len = cfg.blocks.len();
 
-        let mut instructions: Vec<JitEvmEngineSimpleBlock<'_>> = Vec::new();
-        for i in 0..ops_len {
-            let block_before = if i == 0 {
-                setup_block
-            } else {
-                instructions[i-1].block
-            };
-            let label = format!("Instruction #{}: {:?}", i, code.code.ops[i]);
-            instructions.push(JitEvmEngineSimpleBlock::new(self, block_before, &label, &format!("_{}", i)));
+        // blocks no jump or fallthrough can reach (code after a terminator, data, metadata) are
+        // not rendered at all, ops keep their indices so that tracing reports the right offsets
+        let reachable = cfg.reachable();
+
+        let mut reachable_blocks: Vec<Option<JitEvmEngineSimpleBlock<'_>>> = Vec::new();
+        let mut block_before = setup_block;
+        for (b, block) in cfg.blocks.iter().enumerate() {
+            if !reachable[b] {
+                reachable_blocks.push(None);
+                continue;
+            }
+            let label = format!("Block #{}: ops #{}..#{}", b, block.ops.start, block.ops.end);
+            let simple_block = JitEvmEngineSimpleBlock::new(self, block_before, &label, &format!("_{}", b));
+            block_before = simple_block.block;
+            reachable_blocks.push(Some(simple_block));
         }
+        let blocks_last = block_before;
 
+        let entry = reachable_blocks[0].unwrap();   // the first block is always reachable
         self.builder.position_at_end(setup_block);
-        self.builder.build_unconditional_branch(instructions[0].block);
-        instructions[0].phi_execution_context.add_incoming(&[(&setup_book.execution_context, setup_block)]);
-        instructions[0].phi_sp_min.add_incoming(&[(&setup_book.sp_min, setup_block)]);
-        instructions[0].phi_sp_max.add_incoming(&[(&setup_book.sp_max, setup_block)]);
-        instructions[0].phi_sp.add_incoming(&[(&setup_book.sp, setup_block)]);
+        self.builder.build_unconditional_branch(entry.block);
+        entry.phi_execution_context.add_incoming(&[(&setup_book.execution_context, setup_block)]);
+        entry.phi_sp_min.add_incoming(&[(&setup_book.sp_min, setup_block)]);
+        entry.phi_sp_max.add_incoming(&[(&setup_book.sp_max, setup_block)]);
+        entry.phi_sp.add_incoming(&[(&setup_book.sp, setup_block)]);
 
 
         // END HANDLER
 
-        let end = JitEvmEngineSimpleBlock::new(self, instructions[ops_len-1].block, &"end", &"-end");
-        self.builder.build_return(Some(&self.type_retval.const_int(0, false)));
+        let end = JitEvmEngineSimpleBlock::new(self, blocks_last, &"end", &"-end");
+        self.build_return_status(end.phi_execution_context.as_basic_value().into_int_value(), end.phi_sp.as_basic_value().into_int_value(), EVM_JIT_STATUS_OK, callbacks.step_end);
 
 
         // ERROR-JUMPDEST HANDLER
 
         let error_jumpdest = JitEvmEngineSimpleBlock::new(self, end.block, &"error-jumpdest", &"-error-jumpdest");
-        self.builder.build_return(Some(&self.type_retval.const_int(1, false)));
+        self.build_return_status(error_jumpdest.phi_execution_context.as_basic_value().into_int_value(), error_jumpdest.phi_sp.as_basic_value().into_int_value(), EVM_JIT_STATUS_ERROR_JUMPDEST, callbacks.step_end);
+
+
+        // ERROR-INVALID-INSTRUCTION HANDLER
+
+        let error_invalid_instruction = JitEvmEngineSimpleBlock::new(self, error_jumpdest.block, &"error-invalid-instruction", &"-error-invalid-instruction");
+        self.build_return_status(error_invalid_instruction.phi_execution_context.as_basic_value().into_int_value(), error_invalid_instruction.phi_sp.as_basic_value().into_int_value(), EVM_JIT_STATUS_ERROR_INVALID_INSTRUCTION, callbacks.step_end);
+
 
+        // ERROR-STACK HANDLER (EOF)
 
-        // RENDER INSTRUCTIONS
+        let error_stack = sections.map(|_| {
+            let error_stack = JitEvmEngineSimpleBlock::new(self, error_invalid_instruction.block, &"error-stack", &"-error-stack");
+            self.build_return_status(error_stack.phi_execution_context.as_basic_value().into_int_value(), error_stack.phi_sp.as_basic_value().into_int_value(), EVM_JIT_STATUS_ERROR_STACK, callbacks.step_end);
+            error_stack
+        });
 
-        for (i, op) in code.code.ops.iter().enumerate() {
-            use EvmOp::*;
+        // unreachable blocks are never branched to, if they were, they would fail
+        let blocks: Vec<JitEvmEngineSimpleBlock<'_>> = reachable_blocks.iter()
+            .map(|simple_block| simple_block.unwrap_or(error_invalid_instruction))
+            .collect();
 
-            let this = instructions[i];
+
+        // RENDER BLOCKS
+
+        'blocks: for (b, block) in cfg.blocks.iter().enumerate() {
+            if !reachable[b] {
+                continue;
+            }
+            let mut this = blocks[b];
 
             self.builder.position_at_end(this.block);
-            let book = JitEvmEngineBookkeeping {
+            let mut book = JitEvmEngineBookkeeping {
                 execution_context: this.phi_execution_context.as_basic_value().into_int_value(),
                 sp_min: this.phi_sp_min.as_basic_value().into_int_value(),
                 sp_max: this.phi_sp_max.as_basic_value().into_int_value(),
@@ -481,281 +1255,433 @@ impl<'ctx> JitEvmEngine<'ctx> {
                 // retval: this.phi_retval.as_basic_value().into_int_value(),
             };
 
-            let next = if i+1 == ops_len { end } else { instructions[i+1] };
+            let next = if b+1 == blocks_len { end } else { blocks[b+1] };
 
-            let book = match op {
-                Stop => {
-                    let val = self.type_retval.const_int(0, false);
-                    self.builder.build_return(Some(&val));
-                    continue;   // skip auto-generated jump to next instruction
-                },
-                Push(_, val) => {
-                    let val = self.type_stackel.const_int_arbitrary_precision(&val.0);
-                    let book = self.build_stack_push(book, val);
-                    book
-                },
-                Pop => {
-                    let (book, _) = self.build_stack_pop(book);
-                    book
-                },
-                Jumpdest => {
-                    book
-                },
-                Sload => {
-                    let _retval = self.builder.build_call(callback_sload_func, &[
-                        book.execution_context.into(),
-                        book.sp.into(),
-                    ], "").try_as_basic_value().left().unwrap().into_int_value();
-                    // TODO: proper error handling, based on return value?
-                    book
-                },
-                Sstore => {
-                    let _retval = self.builder.build_call(callback_sstore_func, &[
+            // all instructions of a block are rendered as straight-line code into the same
+            // LLVM basic block, only the last one (see EvmOp::is_block_terminator) may branch
+            // (except for callbacks that can fail, after which `this` continues in a new block)
+            for i in block.ops.clone() {
+                use EvmOp::*;
+
+                let op = &code.code.ops[i];
+
+                let instrument = match self.instrumentation {
+                    JitEvmInstrumentation::None => false,
+                    JitEvmInstrumentation::Blocks => i == block.ops.start,
+                    JitEvmInstrumentation::Instructions => true,
+                };
+                if instrument {
+                    let retval = self.builder.build_call(callbacks.step, &[
                         book.execution_context.into(),
                         book.sp.into(),
+                        self.type_retval.const_int(i as u64, false).into(),
                     ], "").try_as_basic_value().left().unwrap().into_int_value();
-                    // TODO: proper error handling, based on return value?
-                    let (book, _) = self.build_stack_pop(book);
-                    let (book, _) = self.build_stack_pop(book);
-                    book
-                },
-                Jump => {
-                    let (book, target) = self.build_stack_pop(book);
-
-                    if code.jumpdests.is_empty() {
-                        // there are no valid jump targets, this Jump has to fail!
-                        self.builder.build_unconditional_branch(end.block);
-                        end.add_incoming(&book, &this);
-
-                    } else {
-                        let mut jump_table: Vec<JitEvmEngineSimpleBlock<'_>> = Vec::new();
-                        for (j, jmp_i) in code.jumpdests.iter().enumerate() {
-                            let jmp_target = code.opidx2target[jmp_i];
-                            jump_table.push(JitEvmEngineSimpleBlock::new(
-                                self,
-                                if j == 0 { this.block } else { jump_table[j-1].block },
-                                &format!("instruction #{}: {:?} / to Jumpdest #{} at op #{} to byte #{}", i, op, j, jmp_i, jmp_target),
-                                &format!("_{}_{}", i, j),
-                            ));
+                    (book, this) = self.build_callback_status_check(book, this, retval, &format!("instruction #{}: {:?} / step", i, op), &format!("_{}_step", i), callbacks.step_end);
+                }
+
+                book = match op {
+                    Stop => {
+                        self.build_return_status(book.execution_context, book.sp, EVM_JIT_STATUS_OK, callbacks.step_end);
+                        continue 'blocks;   // skip auto-generated jump to next block
+                    },
+                    Push(_, val) => {
+                        let val = self.type_stackel.const_int_arbitrary_precision(&val.0);
+                        let book = self.build_stack_push(book, val);
+                        book
+                    },
+                    Pop => {
+                        let (book, _) = self.build_stack_pop(book);
+                        book
+                    },
+                    Jumpdest => {
+                        book
+                    },
+                    Sload => {
+                        let retval = self.builder.build_call(callbacks.sload, &[
+                            book.execution_context.into(),
+                            book.sp.into(),
+                        ], "").try_as_basic_value().left().unwrap().into_int_value();
+                        let (book, cont) = self.build_callback_status_check(book, this, retval, &format!("instruction #{}: {:?}", i, op), &format!("_{}", i), callbacks.step_end);
+                        this = cont;
+                        book
+                    },
+                    Sstore => {
+                        let retval = self.builder.build_call(callbacks.sstore, &[
+                            book.execution_context.into(),
+                            book.sp.into(),
+                        ], "").try_as_basic_value().left().unwrap().into_int_value();
+                        let (book, cont) = self.build_callback_status_check(book, this, retval, &format!("instruction #{}: {:?}", i, op), &format!("_{}", i), callbacks.step_end);
+                        this = cont;
+                        let (book, _) = self.build_stack_pop(book);
+                        let (book, _) = self.build_stack_pop(book);
+                        book
+                    },
+                    Tload => {
+                        let retval = self.builder.build_call(callbacks.tload, &[
+                            book.execution_context.into(),
+                            book.sp.into(),
+                        ], "").try_as_basic_value().left().unwrap().into_int_value();
+                        let (book, cont) = self.build_callback_status_check(book, this, retval, &format!("instruction #{}: {:?}", i, op), &format!("_{}", i), callbacks.step_end);
+                        this = cont;
+                        book
+                    },
+                    Tstore => {
+                        let retval = self.builder.build_call(callbacks.tstore, &[
+                            book.execution_context.into(),
+                            book.sp.into(),
+                        ], "").try_as_basic_value().left().unwrap().into_int_value();
+                        let (book, cont) = self.build_callback_status_check(book, this, retval, &format!("instruction #{}: {:?}", i, op), &format!("_{}", i), callbacks.step_end);
+                        this = cont;
+                        let (book, _) = self.build_stack_pop(book);
+                        let (book, _) = self.build_stack_pop(book);
+                        book
+                    },
+                    Jump => {
+                        let (book, target) = self.build_stack_pop(book);
+
+                        match &block.exit {
+                            EvmBlockExit::Jump(EvmJumpTarget::Static(targets)) if targets.len() == 1 => {
+                                // the only possible target, branch directly
+                                let jmp_block = blocks[targets[0]];
+                                self.builder.build_unconditional_branch(jmp_block.block);
+                                jmp_block.add_incoming(&book, &this);
+                            },
+                            EvmBlockExit::Jump(EvmJumpTarget::Invalid) => {
+                                // no possible target is a Jumpdest, this Jump has to fail!
+                                self.builder.build_unconditional_branch(error_jumpdest.block);
+                                error_jumpdest.add_incoming(&book, &this);
+                            },
+                            exit => {
+                                // dispatch on the candidates (Static) or on all Jumpdests (Dynamic, possibly none)
+                                let targets = match exit {
+                                    EvmBlockExit::Jump(EvmJumpTarget::Static(targets)) => targets,
+                                    _ => &cfg.jumpdest_blocks,
+                                };
+                                self.build_jump_switch(book, this, target, targets, code, &cfg, &blocks, error_jumpdest);
+                            },
                         }
 
-                        self.builder.position_at_end(this.block);
-                        self.builder.build_unconditional_branch(jump_table[0].block);
-                        jump_table[0].add_incoming(&book, &this);
-
-                        for (j, jmp_i) in code.jumpdests.iter().enumerate() {
-                            let jmp_target = code.opidx2target[jmp_i];
-                            let jmp_target = jmp_target.as_u64();   // REMARK: assumes that code cannot exceed 2^64 instructions, probably ok ;)
-                            self.builder.position_at_end(jump_table[j].block);
-                            let cmp = self.builder.build_int_compare(IntPredicate::EQ, self.type_stackel.const_int(jmp_target, false), target, "");
-                            if j+1 == code.jumpdests.len() {
-                                self.builder.build_conditional_branch(cmp, instructions[*jmp_i].block, error_jumpdest.block);
-                                instructions[*jmp_i].add_incoming(&book, &jump_table[j]);
-                                error_jumpdest.add_incoming(&book, &jump_table[j]);
-                            } else {
-                                self.builder.build_conditional_branch(cmp, instructions[*jmp_i].block, jump_table[j+1].block);
-                                instructions[*jmp_i].add_incoming(&book, &jump_table[j]);
-                                jump_table[j+1].add_incoming(&book, &jump_table[j]);
-                            }
-                        }
-                    }
+                        continue 'blocks;   // skip auto-generated jump to next block
+                    },
+                    Jumpi => {
+                        let (book, target) = self.build_stack_pop(book);
+                        let (book, val) = self.build_stack_pop(book);
 
-                    continue;   // skip auto-generated jump to next instruction
-                },
-                Jumpi => {
-                    let (book, target) = self.build_stack_pop(book);
-                    let (book, val) = self.build_stack_pop(book);
-
-                    if code.jumpdests.is_empty() {
-                        // there are no valid jump targets, this Jumpi has to fail!
-                        self.builder.build_unconditional_branch(end.block);
-                        end.add_incoming(&book, &this);
-
-                    } else {
-                        let mut jump_table: Vec<JitEvmEngineSimpleBlock<'_>> = Vec::new();
-                        for (j, jmp_i) in code.jumpdests.iter().enumerate() {
-                            let jmp_target = code.opidx2target[jmp_i];
-                            jump_table.push(JitEvmEngineSimpleBlock::new(
-                                self,
-                                if j == 0 { this.block } else { jump_table[j-1].block },
-                                &format!("instruction #{}: {:?} / to Jumpdest #{} at op #{} to byte #{}", i, op, j, jmp_i, jmp_target),
-                                &format!("_{}_{}", i, j),
-                            ));
+                        let cmp = self.builder.build_int_compare(IntPredicate::EQ, self.type_stackel.const_int(0, false), val, "");
+                        match &block.exit {
+                            EvmBlockExit::Jumpi(EvmJumpTarget::Static(targets)) if targets.len() == 1 => {
+                                // the only possible target, branch directly
+                                let jmp_block = blocks[targets[0]];
+                                self.builder.build_conditional_branch(cmp, next.block, jmp_block.block);
+                                next.add_incoming(&book, &this);
+                                jmp_block.add_incoming(&book, &this);
+                            },
+                            EvmBlockExit::Jumpi(EvmJumpTarget::Invalid) => {
+                                // no possible target is a Jumpdest, this Jumpi fails unless it falls through
+                                self.builder.build_conditional_branch(cmp, next.block, error_jumpdest.block);
+                                next.add_incoming(&book, &this);
+                                error_jumpdest.add_incoming(&book, &this);
+                            },
+                            exit => {
+                                // dispatch on the candidates (Static) or on all Jumpdests (Dynamic, possibly none)
+                                let targets = match exit {
+                                    EvmBlockExit::Jumpi(EvmJumpTarget::Static(targets)) => targets,
+                                    _ => &cfg.jumpdest_blocks,
+                                };
+
+                                let taken = JitEvmEngineSimpleBlock::new(
+                                    self,
+                                    this.block,
+                                    &format!("instruction #{}: {:?} / taken", i, op),
+                                    &format!("_{}_taken", i),
+                                );
+                                self.builder.position_at_end(this.block);
+                                self.builder.build_conditional_branch(cmp, next.block, taken.block);
+                                next.add_incoming(&book, &this);
+                                taken.add_incoming(&book, &this);
+
+                                self.builder.position_at_end(taken.block);
+                                let book = JitEvmEngineBookkeeping {
+                                    execution_context: taken.phi_execution_context.as_basic_value().into_int_value(),
+                                    sp_min: taken.phi_sp_min.as_basic_value().into_int_value(),
+                                    sp_max: taken.phi_sp_max.as_basic_value().into_int_value(),
+                                    sp: taken.phi_sp.as_basic_value().into_int_value(),
+                                };
+                                self.build_jump_switch(book, taken, target, targets, code, &cfg, &blocks, error_jumpdest);
+                            },
                         }
 
-                        self.builder.position_at_end(this.block);
+                        continue 'blocks;   // skip auto-generated jump to next block
+                    },
+                    Swap1 => { self.build_swap(book, 1+1) },
+                    Swap2 => { self.build_swap(book, 2+1) },
+                    Swap3 => { self.build_swap(book, 3+1) },
+                    Swap4 => { self.build_swap(book, 4+1) },
+                    Swap5 => { self.build_swap(book, 5+1) },
+                    Swap6 => { self.build_swap(book, 6+1) },
+                    Swap7 => { self.build_swap(book, 7+1) },
+                    Swap8 => { self.build_swap(book, 8+1) },
+                    Swap9 => { self.build_swap(book, 9+1) },
+                    Swap10 => { self.build_swap(book, 10+1) },
+                    Swap11 => { self.build_swap(book, 11+1) },
+                    Swap12 => { self.build_swap(book, 12+1) },
+                    Swap13 => { self.build_swap(book, 13+1) },
+                    Swap14 => { self.build_swap(book, 14+1) },
+                    Swap15 => { self.build_swap(book, 15+1) },
+                    Swap16 => { self.build_swap(book, 16+1) },
+                    Dup1 => { self.build_dup(book, 1)? },
+                    Dup2 => { self.build_dup(book, 2)? },
+                    Dup3 => { self.build_dup(book, 3)? },
+                    Dup4 => { self.build_dup(book, 4)? },
+                    Dup5 => { self.build_dup(book, 5)? },
+                    Dup6 => { self.build_dup(book, 6)? },
+                    Dup7 => { self.build_dup(book, 7)? },
+                    Dup8 => { self.build_dup(book, 8)? },
+                    Dup9 => { self.build_dup(book, 9)? },
+                    Dup10 => { self.build_dup(book, 10)? },
+                    Dup11 => { self.build_dup(book, 11)? },
+                    Dup12 => { self.build_dup(book, 12)? },
+                    Dup13 => { self.build_dup(book, 13)? },
+                    Dup14 => { self.build_dup(book, 14)? },
+                    Dup15 => { self.build_dup(book, 15)? },
+                    Dup16 => { self.build_dup(book, 16)? },
+                    Iszero => {
+                        let (book, val) = self.build_stack_pop(book);
                         let cmp = self.builder.build_int_compare(IntPredicate::EQ, self.type_stackel.const_int(0, false), val, "");
-                        self.builder.build_conditional_branch(cmp, next.block, jump_table[0].block);
+                        let d = self.builder.build_int_z_extend(cmp, self.type_stackel, "");
+                        let book = self.build_stack_push(book, d);
+                        book
+                    },
+                    Add => { op2_llvmnativei256_operation!(self, book, build_int_add) },
+                    Sub => { op2_llvmnativei256_operation!(self, book, build_int_sub) },
+                    Mul => { op2_llvmnativei256_operation!(self, book, build_int_mul) },
+                    Div => { op2_evmi256_operation!(self, book, build_evm_div) },
+                    Sdiv => { op2_evmi256_operation!(self, book, build_evm_sdiv) },
+                    Mod => { op2_evmi256_operation!(self, book, build_evm_mod) },
+                    Smod => { op2_evmi256_operation!(self, book, build_evm_smod) },
+                    Addmod => { op3_evmi256_operation!(self, book, build_evm_addmod) },
+                    Mulmod => { op3_evmi256_operation!(self, book, build_evm_mulmod) },
+                    Exp => {
+                        let (book, a) = self.build_stack_pop(book);
+                        let (book, b) = self.build_stack_pop(book);
+                        let d = self.builder.build_call(callbacks.helper_exp, &[a.into(), b.into()], "").try_as_basic_value().left().unwrap().into_int_value();
+                        let book = self.build_stack_push(book, d);
+                        book
+                    },
+                    Signextend => { op2_evmi256_operation!(self, book, build_evm_signextend) },
+                    Eq => { op2_llvmnativei256_compare_operation!(self, book, IntPredicate::EQ) },
+                    Lt => { op2_llvmnativei256_compare_operation!(self, book, IntPredicate::ULT) },
+                    Gt => { op2_llvmnativei256_compare_operation!(self, book, IntPredicate::UGT) },
+                    Slt => { op2_llvmnativei256_compare_operation!(self, book, IntPredicate::SLT) },
+                    Sgt => { op2_llvmnativei256_compare_operation!(self, book, IntPredicate::SGT) },
+                    And => { op2_llvmnativei256_operation!(self, book, build_and) },
+                    Or => { op2_llvmnativei256_operation!(self, book, build_or) },
+                    Xor => { op2_llvmnativei256_operation!(self, book, build_xor) },
+                    Not => { op1_llvmnativei256_operation!(self, book, build_not) },
+                    Byte => { op2_evmi256_operation!(self, book, build_evm_byte) },
+                    Shl => { op2_evmi256_operation!(self, book, build_evm_shl) },
+                    Shr => { op2_evmi256_operation!(self, book, build_evm_shr) },
+                    Sar => { op2_evmi256_operation!(self, book, build_evm_sar) },
+                    AugmentedPushJump(_, val) => {
+                        // retrieve the corresponding jump target ...
+                        let jmp_block = match code.target2opidx.get(val) {
+                            Some(jmp_i) if code.jumpdests.contains(jmp_i) => blocks[cfg.opidx2block[*jmp_i]],
+                            _ => error_jumpdest,
+                        };
+                        // ... and jump to there (only Jumpdests start a block)!
+                        self.builder.build_unconditional_branch(jmp_block.block);
+                        jmp_block.add_incoming(&book, &this);
+
+                        continue 'blocks;   // skip auto-generated jump to next block
+                    },
+                    AugmentedPushJumpi(_, val) => {
+                        let (book, condition) = self.build_stack_pop(book);
+
+                        // retrieve the corresponding jump target ...
+                        let jmp_block = match code.target2opidx.get(val) {
+                            Some(jmp_i) if code.jumpdests.contains(jmp_i) => blocks[cfg.opidx2block[*jmp_i]],
+                            _ => error_jumpdest,
+                        };
+                        // ... and jump to there (conditionally, only Jumpdests start a block)!
+                        let cmp = self.builder.build_int_compare(IntPredicate::EQ, self.type_stackel.const_int(0, false), condition, "");
+                        self.builder.build_conditional_branch(cmp, next.block, jmp_block.block);
                         next.add_incoming(&book, &this);
-                        jump_table[0].add_incoming(&book, &this);
-
-                        for (j, jmp_i) in code.jumpdests.iter().enumerate() {
-                            let jmp_target = code.opidx2target[jmp_i];
-                            let jmp_target = jmp_target.as_u64();   // REMARK: assumes that code cannot exceed 2^64 instructions, probably ok ;)
-                            self.builder.position_at_end(jump_table[j].block);
-                            let cmp = self.builder.build_int_compare(IntPredicate::EQ, self.type_stackel.const_int(jmp_target, false), target, "");
-                            if j+1 == code.jumpdests.len() {
-                                self.builder.build_conditional_branch(cmp, instructions[*jmp_i].block, error_jumpdest.block);
-                                instructions[*jmp_i].add_incoming(&book, &jump_table[j]);
-                                error_jumpdest.add_incoming(&book, &jump_table[j]);
-                            } else {
-                                self.builder.build_conditional_branch(cmp, instructions[*jmp_i].block, jump_table[j+1].block);
-                                instructions[*jmp_i].add_incoming(&book, &jump_table[j]);
-                                jump_table[j+1].add_incoming(&book, &jump_table[j]);
-                            }
+                        jmp_block.add_incoming(&book, &this);
+
+                        continue 'blocks;   // skip auto-generated jump to next block
+                    },
+                    AugmentedPushAdd(_, val) => {
+                        let (book, a) = self.build_stack_pop(book);
+                        let d = self.builder.build_int_add(a, self.type_stackel.const_int_arbitrary_precision(&val.0), "");
+                        let book = self.build_stack_push(book, d);
+                        book
+                    },
+                    AugmentedSwapPop(depths) => {
+                        let mut book = book;
+                        for n in depths {
+                            book = self.build_swap(book, *n as u64 + 1);
+                            (book, _) = self.build_stack_pop(book);
                         }
-                    }
-
-                    continue;   // skip auto-generated jump to next instruction
-                },
-                Swap1 => { self.build_swap(book, 1+1) },
-                Swap2 => { self.build_swap(book, 2+1) },
-                Swap3 => { self.build_swap(book, 3+1) },
-                Swap4 => { self.build_swap(book, 4+1) },
-                Swap5 => { self.build_swap(book, 5+1) },
-                Swap6 => { self.build_swap(book, 6+1) },
-                Swap7 => { self.build_swap(book, 7+1) },
-                Swap8 => { self.build_swap(book, 8+1) },
-                Swap9 => { self.build_swap(book, 9+1) },
-                Swap10 => { self.build_swap(book, 10+1) },
-                Swap11 => { self.build_swap(book, 11+1) },
-                Swap12 => { self.build_swap(book, 12+1) },
-                Swap13 => { self.build_swap(book, 13+1) },
-                Swap14 => { self.build_swap(book, 14+1) },
-                Swap15 => { self.build_swap(book, 15+1) },
-                Swap16 => { self.build_swap(book, 16+1) },
-                Dup1 => { self.build_dup(book, 1)? },
-                Dup2 => { self.build_dup(book, 2)? },
-                Dup3 => { self.build_dup(book, 3)? },
-                Dup4 => { self.build_dup(book, 4)? },
-                Dup5 => { self.build_dup(book, 5)? },
-                Dup6 => { self.build_dup(book, 6)? },
-                Dup7 => { self.build_dup(book, 7)? },
-                Dup8 => { self.build_dup(book, 8)? },
-                Dup9 => { self.build_dup(book, 9)? },
-                Dup10 => { self.build_dup(book, 10)? },
-                Dup11 => { self.build_dup(book, 11)? },
-                Dup12 => { self.build_dup(book, 12)? },
-                Dup13 => { self.build_dup(book, 13)? },
-                Dup14 => { self.build_dup(book, 14)? },
-                Dup15 => { self.build_dup(book, 15)? },
-                Dup16 => { self.build_dup(book, 16)? },
-                Iszero => {
-                    let (book, val) = self.build_stack_pop(book);
-                    let cmp = self.builder.build_int_compare(IntPredicate::EQ, self.type_stackel.const_int(0, false), val, "");
-
-                    let push_0 = JitEvmEngineSimpleBlock::new(self, instructions[i].block, &format!("Instruction #{}: {:?} / push 0", i, op), &format!("_{}_0", i));
-                    let push_1 = JitEvmEngineSimpleBlock::new(self, push_0.block, &format!("Instruction #{}: {:?} / push 1", i, op), &format!("_{}_1", i));
-                    
-                    self.builder.position_at_end(this.block);
-                    self.builder.build_conditional_branch(cmp, push_1.block, push_0.block);
-                    push_0.add_incoming(&book, &this);
-                    push_1.add_incoming(&book, &this);
-
-                    self.builder.position_at_end(push_0.block);
-                    let book_0 = self.build_stack_push(book, self.type_stackel.const_int(0, false));
-                    self.builder.build_unconditional_branch(next.block);
-                    next.add_incoming(&book_0, &push_0);
-
-                    self.builder.position_at_end(push_1.block);
-                    let book_1 = self.build_stack_push(book, self.type_stackel.const_int(1, false));
-                    self.builder.build_unconditional_branch(next.block);
-                    next.add_incoming(&book_1, &push_1);
-
-                    continue;   // skip auto-generated jump to next instruction
-                },
-                Add => { op2_llvmnativei256_operation!(self, book, build_int_add) },
-                Sub => { op2_llvmnativei256_operation!(self, book, build_int_sub) },
-                Mul => { op2_llvmnativei256_operation!(self, book, build_int_mul) },
-                Div => { op2_llvmnativei256_operation!(self, book, build_int_unsigned_div) },
-                Sdiv => { op2_llvmnativei256_operation!(self, book, build_int_signed_div) },
-                Mod => { op2_llvmnativei256_operation!(self, book, build_int_unsigned_rem) },
-                // Smod => { op2_llvmnativei256_operation!(self, book, build_int_signed_rem) },
-                Eq => { op2_llvmnativei256_compare_operation!(self, book, this, next, instructions, i, op, IntPredicate::EQ) },
-                Lt => { op2_llvmnativei256_compare_operation!(self, book, this, next, instructions, i, op, IntPredicate::ULT) },
-                Gt => { op2_llvmnativei256_compare_operation!(self, book, this, next, instructions, i, op, IntPredicate::UGT) },
-                Slt => { op2_llvmnativei256_compare_operation!(self, book, this, next, instructions, i, op, IntPredicate::SLT) },
-                Sgt => { op2_llvmnativei256_compare_operation!(self, book, this, next, instructions, i, op, IntPredicate::SGT) },
-                And => { op2_llvmnativei256_operation!(self, book, build_and) },
-                Or => { op2_llvmnativei256_operation!(self, book, build_or) },
-                // Xor => { op2_llvmnativei256_operation!(self, book, build_xor) },
-                Not => { op1_llvmnativei256_operation!(self, book, build_not) },
-                AugmentedPushJump(_, val) => {
-                    if code.jumpdests.is_empty() {
-                        // there are no valid jump targets, this Jump has to fail!
-                        self.builder.build_unconditional_branch(end.block);
-                        end.add_incoming(&book, &this);
-                    } else {
-                        // retrieve the corresponding jump target (panic if not a valid jump target) ...
-                        let jmp_i = code.target2opidx[val];
-                        // ... and jump to there!
-                        self.builder.build_unconditional_branch(instructions[jmp_i].block);
-                        instructions[jmp_i].add_incoming(&book, &this);
-                    }
-                    
-                    continue;   // skip auto-generated jump to next instruction
-                },
-                AugmentedPushJumpi(_, val) => {
-                    let (book, condition) = self.build_stack_pop(book);
-
-                    if code.jumpdests.is_empty() {
-                        // there are no valid jump targets, this Jumpi has to fail!
-                        self.builder.build_unconditional_branch(end.block);
-                        end.add_incoming(&book, &this);
-
-                    } else {
-                        // retrieve the corresponding jump target (panic if not a valid jump target) ...
-                        let jmp_i = code.target2opidx[val];
-                        // ... and jump to there (conditionally)!
+                        book
+                    },
+                    AugmentedDupIszeroPushJumpi(n, _, val) => {
+                        // retrieve the corresponding jump target ...
+                        let jmp_block = match code.target2opidx.get(val) {
+                            Some(jmp_i) if code.jumpdests.contains(jmp_i) => blocks[cfg.opidx2block[*jmp_i]],
+                            _ => error_jumpdest,
+                        };
+                        // ... and jump to there if the Nth element is zero (it stays on the stack)
+                        let (book, a) = self.build_stack_read(book, *n as u64);
+                        let cmp = self.builder.build_int_compare(IntPredicate::EQ, self.type_stackel.const_int(0, false), a, "");
+                        self.builder.build_conditional_branch(cmp, jmp_block.block, next.block);
+                        next.add_incoming(&book, &this);
+                        jmp_block.add_incoming(&book, &this);
+
+                        continue 'blocks;   // skip auto-generated jump to next block
+                    },
+                    AugmentedComparePushJumpi(cmp, _, val) => {
+                        // retrieve the corresponding jump target ...
+                        let jmp_block = match code.target2opidx.get(val) {
+                            Some(jmp_i) if code.jumpdests.contains(jmp_i) => blocks[cfg.opidx2block[*jmp_i]],
+                            _ => error_jumpdest,
+                        };
+                        // ... and branch on the comparison itself, without materializing it
+                        let (book, a) = self.build_stack_pop(book);
+                        let (book, cond) = match &**cmp {
+                            Iszero => (book, self.builder.build_int_compare(IntPredicate::EQ, self.type_stackel.const_int(0, false), a, "")),
+                            cmp => {
+                                let predicate = match cmp {
+                                    Lt => IntPredicate::ULT,
+                                    Gt => IntPredicate::UGT,
+                                    Slt => IntPredicate::SLT,
+                                    Sgt => IntPredicate::SGT,
+                                    Eq => IntPredicate::EQ,
+                                    _ => return Err(JitEvmEngineError::UnsupportedOperation(op.clone())),
+                                };
+                                let (book, b) = self.build_stack_pop(book);
+                                (book, self.builder.build_int_compare(predicate, a, b, ""))
+                            },
+                        };
+                        self.builder.build_conditional_branch(cond, jmp_block.block, next.block);
+                        next.add_incoming(&book, &this);
+                        jmp_block.add_incoming(&book, &this);
+
+                        continue 'blocks;   // skip auto-generated jump to next block
+                    },
+                    AugmentedConst(_, val) => {
+                        let val = self.type_stackel.const_int_arbitrary_precision(&val.0);
+                        let book = self.build_stack_push(book, val);
+                        book
+                    },
+                    Rjump(_) => {
+                        let jmp_block = match &block.exit {
+                            EvmBlockExit::Jump(EvmJumpTarget::Static(targets)) => blocks[targets[0]],
+                            _ => error_jumpdest,
+                        };
+                        self.builder.build_unconditional_branch(jmp_block.block);
+                        jmp_block.add_incoming(&book, &this);
+
+                        continue 'blocks;   // skip auto-generated jump to next block
+                    },
+                    Rjumpi(_) => {
+                        let (book, condition) = self.build_stack_pop(book);
+
+                        let jmp_block = match &block.exit {
+                            EvmBlockExit::Jumpi(EvmJumpTarget::Static(targets)) => blocks[targets[0]],
+                            _ => error_jumpdest,
+                        };
                         let cmp = self.builder.build_int_compare(IntPredicate::EQ, self.type_stackel.const_int(0, false), condition, "");
-                        self.builder.build_conditional_branch(cmp, next.block, instructions[jmp_i].block);
+                        self.builder.build_conditional_branch(cmp, next.block, jmp_block.block);
+                        next.add_incoming(&book, &this);
+                        jmp_block.add_incoming(&book, &this);
+
+                        continue 'blocks;   // skip auto-generated jump to next block
+                    },
+                    Rjumpv(_) => {
+                        let (book, index) = self.build_stack_pop(book);
+
+                        // the jump table in order (possibly with repeated targets), an index out
+                        // of range falls through
+                        let targets: &[usize] = match &block.exit {
+                            EvmBlockExit::Jumpi(EvmJumpTarget::Static(targets)) => targets,
+                            _ => &[],
+                        };
+                        let cases: Vec<_> = targets.iter().enumerate()
+                            .map(|(k, t)| (self.type_stackel.const_int(k as u64, false), blocks[*t].block))
+                            .collect();
+                        self.builder.build_switch(index, next.block, &cases);
+                        for t in targets {
+                            blocks[*t].add_incoming(&book, &this);
+                        }
                         next.add_incoming(&book, &this);
-                        instructions[jmp_i].add_incoming(&book, &this);
-                    }
-
-                    continue;   // skip auto-generated jump to next instruction
-                },
 
-                _ => {
-                    panic!("Op not implemented: {:?}", op);
-                },
-            };
+                        continue 'blocks;   // skip auto-generated jump to next block
+                    },
+                    Callf(idx) => {
+                        let (types, dispatch) = sections.ok_or_else(|| JitEvmEngineError::UnsupportedOperation(op.clone()))?;
+                        let section_type = &types[*idx as usize];
+                        let depth = function.get_nth_param(2).unwrap().into_int_value();
+
+                        let (book, cont) = self.build_section_entry_check(book, this, section_type, Some(depth), error_stack.unwrap(), &format!("instruction #{}: {:?} / entry", i, op), &format!("_{}_entry", i));
+                        this = cont;
+                        let depth = self.builder.build_int_add(depth, self.type_ptrint.const_int(1, false), "");
+                        let retval = self.builder.build_call(dispatch, &[
+                            book.execution_context.into(),
+                            book.sp.into(),
+                            depth.into(),
+                            self.type_ptrint.const_int(*idx as u64, false).into(),
+                        ], "").try_as_basic_value().left().unwrap().into_int_value();
+
+                        // anything but RETF (e.g. STOP or an error in the callee) ends the contract
+                        let (book, cont) = self.build_status_check(book, this, retval, EVM_JIT_STATUS_RETF, &format!("instruction #{}: {:?}", i, op), &format!("_{}", i), callbacks.step_end);
+                        this = cont;
+
+                        // the callee replaced its inputs with its outputs
+                        let delta = (section_type.outputs as i64 - section_type.inputs as i64) * EVM_STACK_ELEMENT_SIZE as i64;
+                        let sp = self.builder.build_int_add(book.sp, self.type_ptrint.const_int(delta as u64, true), "");
+                        book.update_sp(sp)
+                    },
+                    Retf => {
+                        self.build_return_status(book.execution_context, book.sp, EVM_JIT_STATUS_RETF, callbacks.step_end);
+                        continue 'blocks;   // skip auto-generated jump to next block
+                    },
+                    Jumpf(idx) => {
+                        let (types, _) = sections.ok_or_else(|| JitEvmEngineError::UnsupportedOperation(op.clone()))?;
+
+                        // eof_dispatch of our caller runs the target next, which returns to that caller (if at all)
+                        let (book, _) = self.build_section_entry_check(book, this, &types[*idx as usize], None, error_stack.unwrap(), &format!("instruction #{}: {:?} / entry", i, op), &format!("_{}_entry", i));
+                        self.build_return_status(book.execution_context, book.sp, EVM_JIT_STATUS_JUMPF + *idx as u64, callbacks.step_end);
+
+                        continue 'blocks;   // skip auto-generated jump to next block
+                    },
+                    Invalid | Unknown(_) | Data(_) => {
+                        self.builder.build_unconditional_branch(error_invalid_instruction.block);
+                        error_invalid_instruction.add_incoming(&book, &this);
+                        continue 'blocks;   // skip auto-generated jump to next block
+                    },
+
+                    _ => {
+                        return Err(JitEvmEngineError::UnsupportedOperation(op.clone()));
+                    },
+                };
+            }
 
             self.builder.build_unconditional_branch(next.block);
             next.add_incoming(&book, &this);
         }
 
+        Ok(())
+    }
 
+    fn finish_compile(&self, debug_ir: Option<String>, debug_asm: Option<String>) -> Result<JitFunction<JitEvmCompiledContract>, JitEvmEngineError> {
         // OUTPUT LLVM
         if let Some(path) = debug_ir {
-            self.module.print_to_file(path)?;
+            self.emit(JitEvmEmit::Ir, &path)?;
         }
 
         // OUTPUT ASM
         if let Some(path) = debug_asm {
-            // https://github.com/TheDan64/inkwell/issues/184
-            // https://thedan64.github.io/inkwell/inkwell/targets/struct.TargetMachine.html#method.write_to_file
-            use inkwell::targets::{TargetMachine, RelocMode, CodeModel, FileType};
-
-            let triple = TargetMachine::get_default_triple();
-            let cpu = TargetMachine::get_host_cpu_name().to_string();
-            let features = TargetMachine::get_host_cpu_features().to_string();
-            
-            let target = Target::from_triple(&triple).unwrap();
-            let machine = target
-                .create_target_machine(
-                    &triple,
-                    &cpu,
-                    &features,
-                    OptimizationLevel::Aggressive,
-                    RelocMode::Default,
-                    CodeModel::Default,
-                )
-                .unwrap();
-            
-                // create a module and do JIT stuff
-            
-            machine.write_to_file(&self.module, FileType::Assembly, path.as_ref())?;
+            self.emit(JitEvmEmit::Asm, &path)?;
         }
 
 
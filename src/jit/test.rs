@@ -80,6 +80,8 @@ macro_rules! test_op2 {
                 _test(U256::zero(), U256::one());
                 _test(U256::one(), U256::zero());
                 _test(U256::one(), U256::one());
+                _test(U256::one() << 255, U256::MAX);   // signed overflow
+                _test(U256::MAX, U256::one() << 255);
 
                 for _i in 0..1000 {
                     let a = rand::thread_rng().gen::<[u8; 32]>();
@@ -88,6 +90,56 @@ macro_rules! test_op2 {
                     let b = U256::from_big_endian(&b);
                     _test(a, b);
                 }
+
+                // small first operand (shift amounts, byte indices, exponents, ...)
+                for _i in 0..1000 {
+                    let a = U256::from(rand::thread_rng().gen_range(0u64..300));
+                    let b = rand::thread_rng().gen::<[u8; 32]>();
+                    let b = U256::from_big_endian(&b);
+                    _test(a, b);
+                }
+            }
+        }
+    };
+}
+
+macro_rules! test_op3 {
+    ($fname:ident, $evmop:expr, $opname:expr) => {
+        paste! {
+            #[test]
+            fn [<operations_jit_equivalence_ $fname>]() {
+                use crate::code::EvmOp::*;
+                use crate::operations;
+
+                fn _test(a: U256, b: U256, c: U256) {
+                    let d = run_jit_ops(1, vec![
+                        Push(32, c),
+                        Push(32, b),
+                        Push(32, a),
+                        $evmop,
+                    ]);
+                    let d = d[0];
+                    let d_ = $opname(a, b, c);
+                    if d != d_ {
+                        println!("a = {:?} / b = {:?} / c = {:?} / d = {:?} / d' = {:?}", a, b, c, d, d_);
+                    }
+                    assert_eq!(d, d_);
+                }
+
+                _test(U256::zero(), U256::zero(), U256::zero());
+                _test(U256::one(), U256::one(), U256::zero());
+                _test(U256::MAX, U256::MAX, U256::one());
+                _test(U256::MAX, U256::MAX, U256::MAX - 1);
+
+                for _i in 0..1000 {
+                    let a = rand::thread_rng().gen::<[u8; 32]>();
+                    let b = rand::thread_rng().gen::<[u8; 32]>();
+                    let c = rand::thread_rng().gen::<[u8; 32]>();
+                    let a = U256::from_big_endian(&a);
+                    let b = U256::from_big_endian(&b);
+                    let c = U256::from_big_endian(&c);
+                    _test(a, b, c);
+                }
             }
         }
     };
@@ -101,6 +153,11 @@ test_op2!(mul, EvmOp::Mul, operations::Mul);
 test_op2!(div, EvmOp::Div, operations::Div);
 test_op2!(sdiv, EvmOp::Sdiv, operations::Sdiv);
 test_op2!(mod, EvmOp::Mod, operations::Mod);
+test_op2!(smod, EvmOp::Smod, operations::Smod);
+test_op3!(addmod, EvmOp::Addmod, operations::Addmod);
+test_op3!(mulmod, EvmOp::Mulmod, operations::Mulmod);
+test_op2!(exp, EvmOp::Exp, operations::Exp);
+test_op2!(signextend, EvmOp::Signextend, operations::Signextend);
 test_op2!(eq, EvmOp::Eq, operations::Eq);
 test_op2!(lt, EvmOp::Lt, operations::Lt);
 test_op2!(gt, EvmOp::Gt, operations::Gt);
@@ -108,5 +165,576 @@ test_op2!(slt, EvmOp::Slt, operations::Slt);
 test_op2!(sgt, EvmOp::Sgt, operations::Sgt);
 test_op2!(and, EvmOp::And, operations::And);
 test_op2!(or, EvmOp::Or, operations::Or);
-// test_op2!(xor, EvmOp::Xor, operations::Xor);
+test_op2!(xor, EvmOp::Xor, operations::Xor);
 test_op1!(not, EvmOp::Not, operations::Not);
+test_op2!(byte, EvmOp::Byte, operations::Byte);
+test_op2!(shl, EvmOp::Shl, operations::Shl);
+test_op2!(shr, EvmOp::Shr, operations::Shr);
+test_op2!(sar, EvmOp::Sar, operations::Sar);
+
+
+fn run_interpreter_ops(ops: Vec<EvmOp>) -> Vec<U256> {
+    use crate::interpreter::{EvmContext, EvmInnerContext, EvmOuterContext};
+    use crate::code::EvmCode;
+    use crate::constants::EVM_STACK_SIZE;
+    use crate::state::EvmJournaledState;
+    use primitive_types::H160;
+
+    let code = EvmCode { ops: ops.clone() }.index();
+    let mut ctx = EvmContext {
+        outer: EvmOuterContext {
+            calldata: vec![],
+            state: EvmJournaledState::new(),
+            address: H160::zero(),
+            callvalue: U256::zero(),
+        },
+        inner: EvmInnerContext {
+            code: &code,
+            stack: [U256::zero(); EVM_STACK_SIZE],
+            pc: 0,
+            sp: 0,
+            memory: vec![],
+        },
+    };
+
+    while ctx.tick().unwrap() {}
+
+    ctx.inner.stack[..ctx.inner.sp].to_vec()
+}
+
+macro_rules! test_program {
+    ($fname:ident, $ops:expr) => {
+        paste! {
+            #[test]
+            fn [<program_jit_interpreter_equivalence_ $fname>]() {
+                let ops = $ops;
+                let d_ = run_interpreter_ops(ops.clone());
+                let d = run_jit_ops(d_.len(), ops.clone());
+                assert_eq!(d, d_);
+
+                // same for augmented code (constant jumps)
+                let augmented = crate::code::EvmCode { ops: ops.clone() }.augment().ops;
+                let d = run_jit_ops(d_.len(), augmented);
+                assert_eq!(d, d_);
+
+                // same for code with all peephole passes, in both engines
+                let optimized = crate::code::EvmCode { ops }.optimize(&crate::code::peephole::EvmPass::ALL).ops;
+                assert_eq!(run_interpreter_ops(optimized.clone()), d_);
+                let d = run_jit_ops(d_.len(), optimized);
+                assert_eq!(d, d_);
+            }
+        }
+    };
+}
+
+
+test_program!(fibonacci, crate::test_data::get_code_ops_fibonacci());
+test_program!(fibonacci_repetitions, crate::test_data::get_code_ops_fibonacci_repetitions());
+test_program!(supersimple1, crate::test_data::get_code_ops_supersimple1());
+test_program!(transient_storage, vec![
+    EvmOp::Push(1, U256::zero() + 42),   // value
+    EvmOp::Push(1, U256::zero() + 7),    // key
+    EvmOp::Tstore,
+    EvmOp::Push(1, U256::zero() + 7),
+    EvmOp::Tload,                        // written before
+    EvmOp::Push(1, U256::zero() + 8),
+    EvmOp::Tload,                        // never written, zero
+]);
+test_program!(storage, vec![
+    EvmOp::Push(1, U256::zero() + 7),    // never written, zero
+    EvmOp::Sload,
+    EvmOp::Push(1, U256::zero() + 42),   // value
+    EvmOp::Push(1, U256::zero() + 7),    // key
+    EvmOp::Sstore,
+    EvmOp::Push(1, U256::zero() + 7),
+    EvmOp::Sload,                        // written before
+    EvmOp::Push(1, U256::zero() + 8),
+    EvmOp::Sload,
+]);
+test_program!(internal_function, vec![
+    EvmOp::Push(1, U256::zero() + 5),    // return address
+    EvmOp::Push(1, U256::zero() + 13),   // call FUNC
+    EvmOp::Jump,
+    EvmOp::Jumpdest,                     // byte 5
+    EvmOp::Push(1, U256::zero() + 11),   // return address
+    EvmOp::Push(1, U256::zero() + 13),   // call FUNC
+    EvmOp::Jump,
+    EvmOp::Jumpdest,                     // byte 11
+    EvmOp::Stop,
+    EvmOp::Jumpdest,                     // FUNC: byte 13
+    EvmOp::Push(1, U256::one()),
+    EvmOp::Swap1,
+    EvmOp::Jump,                         // return to either call site
+]);
+test_program!(dynamic_jumps, vec![
+    EvmOp::Push(1, U256::one()),
+    EvmOp::Push(1, U256::zero() + 6),
+    EvmOp::Div,                          // target is not folded, dispatch at runtime
+    EvmOp::Jump,
+    EvmOp::Jumpdest,                     // byte 6
+    EvmOp::Push(1, U256::zero() + 42),
+    EvmOp::Push(1, U256::one()),         // condition
+    EvmOp::Push(1, U256::one()),
+    EvmOp::Push(1, U256::zero() + 17),
+    EvmOp::Div,
+    EvmOp::Jumpi,
+    EvmOp::Jumpdest,                     // byte 17
+    EvmOp::Stop,
+]);
+test_program!(dynamic_jumpi_fallthrough_without_jumpdests, vec![
+    EvmOp::Push(1, U256::zero()),        // condition
+    EvmOp::Push(1, U256::one()),
+    EvmOp::Push(1, U256::zero() + 5),
+    EvmOp::Div,
+    EvmOp::Jumpi,                        // not taken, falls through
+    EvmOp::Push(1, U256::zero() + 42),
+    EvmOp::Stop,
+]);
+
+
+fn run_jit_ops_retval(ops: Vec<EvmOp>) -> u64 {
+    use crate::jit::{JitEvmExecutionContextHolder, JitEvmEngine};
+    use crate::code::EvmCode;
+    use inkwell::context::Context;
+
+    let context = Context::create();
+    let engine = JitEvmEngine::new_from_context(&context).unwrap();
+
+    let mut holder = JitEvmExecutionContextHolder::new_from_empty();
+    let mut ctx = JitEvmExecutionContext::new_from_holder(&mut holder);
+    let fn_contract = engine.jit_compile_contract(&EvmCode { ops }.index(), None, None).unwrap();
+    unsafe { fn_contract.call(&mut ctx as *mut _ as usize) }
+}
+
+#[test]
+fn final_stack_after_exit() {
+    use crate::jit::{JitEvmExecutionContextHolder, JitEvmEngine, EVM_JIT_STATUS_OK, EVM_JIT_STATUS_ERROR_INVALID_INSTRUCTION};
+    use crate::code::{EvmCode, EvmOp::*};
+    use inkwell::context::Context;
+
+    for (ops, status, stack) in [
+        (crate::test_data::get_code_ops_supersimple1(), EVM_JIT_STATUS_OK, vec![U256::zero() + 23, U256::zero() + 42, U256::zero() + 5, U256::zero()]),
+        (vec![Push(1, U256::zero() + 7), Stop, Push(1, U256::one())], EVM_JIT_STATUS_OK, vec![U256::zero() + 7]),
+        (vec![Push(1, U256::zero() + 7), Invalid], EVM_JIT_STATUS_ERROR_INVALID_INSTRUCTION, vec![U256::zero() + 7]),
+    ] {
+        let context = Context::create();
+        let engine = JitEvmEngine::new_from_context(&context).unwrap();
+        let fn_contract = engine.jit_compile_contract(&EvmCode { ops }.index(), None, None).unwrap();
+
+        let mut holder = JitEvmExecutionContextHolder::new_from_empty();
+        let mut ctx = JitEvmExecutionContext::new_from_holder(&mut holder);
+        assert_eq!(unsafe { fn_contract.call(&mut ctx as *mut _ as usize) }, status);
+        assert_eq!(ctx.final_stack(), &stack[..]);
+    }
+}
+
+#[test]
+fn dynamic_jumpi_invalid_target() {
+    use crate::code::EvmOp::*;
+
+    let cond_target = |cond: u64, target: u64| vec![
+        Push(1, U256::zero() + cond),
+        Push(1, U256::one()),
+        Push(1, U256::zero() + target),
+        Div,                             // target is not folded, dispatch at runtime
+        Jumpi,
+        Push(1, U256::zero() + 42),      // byte 8
+        Jumpdest,                        // byte 10
+        Stop,
+    ];
+
+    assert_eq!(run_jit_ops_retval(cond_target(0, 8)), 0);
+    assert_eq!(run_jit_ops_retval(cond_target(1, 10)), 0);
+    assert_eq!(run_jit_ops_retval(cond_target(1, 8)), 1);
+    assert_eq!(run_jit_ops_retval(cond_target(1, 1000)), 1);
+}
+
+#[test]
+fn compile_errors_and_runtime_failures() {
+    use crate::jit::{JitEvmEngine, JitEvmEngineError, EVM_JIT_STATUS_OK, EVM_JIT_STATUS_ERROR_JUMPDEST, EVM_JIT_STATUS_ERROR_INVALID_INSTRUCTION};
+    use crate::code::{EvmCode, EvmOp::*};
+    use inkwell::context::Context;
+
+    let context = Context::create();
+    let engine = JitEvmEngine::new_from_context(&context).unwrap();
+    assert!(matches!(engine.jit_compile_contract(&EvmCode { ops: vec![] }.index(), None, None), Err(JitEvmEngineError::EmptyCode)));
+
+    let context = Context::create();
+    let engine = JitEvmEngine::new_from_context(&context).unwrap();
+    assert!(matches!(engine.jit_compile_contract(&EvmCode { ops: vec![Callvalue] }.index(), None, None), Err(JitEvmEngineError::UnsupportedOperation(Callvalue))));
+
+    assert_eq!(run_jit_ops_retval(vec![Push(1, U256::one()), Invalid]), EVM_JIT_STATUS_ERROR_INVALID_INSTRUCTION);
+    assert_eq!(run_jit_ops_retval(vec![Push(1, U256::one()), Pop, Unknown(0x0c)]), EVM_JIT_STATUS_ERROR_INVALID_INSTRUCTION);
+    assert_eq!(run_jit_ops_retval(vec![AugmentedPushJump(2, U256::zero() + 1000)]), EVM_JIT_STATUS_ERROR_JUMPDEST);
+    assert_eq!(run_jit_ops_retval(vec![Push(1, U256::zero()), AugmentedPushJumpi(2, U256::zero() + 1000), Stop]), EVM_JIT_STATUS_OK);
+}
+
+#[test]
+fn failing_run_reverts_storage() {
+    use crate::jit::{JitEvmExecutionContextHolder, JitEvmEngine, EVM_JIT_STATUS_OK};
+    use crate::code::{EvmCode, EvmOp::*};
+    use crate::state::EvmHost;
+    use inkwell::context::Context;
+
+    let program = |last: EvmOp| vec![
+        Push(1, U256::zero() + 42),      // value
+        Push(1, U256::zero() + 7),       // key
+        Sstore,
+        Push(1, U256::zero() + 43),
+        Push(1, U256::zero() + 7),
+        Tstore,
+        last,
+    ];
+    let context_ok = Context::create();
+    let engine_ok = JitEvmEngine::new_from_context(&context_ok).unwrap();
+    let fn_ok = engine_ok.jit_compile_contract(&EvmCode { ops: program(Stop) }.index(), None, None).unwrap();
+    let context_err = Context::create();
+    let engine_err = JitEvmEngine::new_from_context(&context_err).unwrap();
+    let fn_err = engine_err.jit_compile_contract(&EvmCode { ops: program(Invalid) }.index(), None, None).unwrap();
+
+    let mut holder = JitEvmExecutionContextHolder::new_from_empty();
+    let mut ctx = JitEvmExecutionContext::new_from_holder(&mut holder);
+
+    let checkpoint = holder.state.checkpoint();
+    let ret = unsafe { fn_err.call(&mut ctx as *mut _ as usize) };
+    assert_ne!(ret, EVM_JIT_STATUS_OK);
+    holder.state.revert(checkpoint).unwrap();
+    assert_eq!(holder.state.host(holder.address).sload(U256::zero() + 7), Ok(U256::zero()));
+    assert_eq!(holder.state.host(holder.address).tload(U256::zero() + 7), U256::zero());
+
+    let checkpoint = holder.state.checkpoint();
+    let ret = unsafe { fn_ok.call(&mut ctx as *mut _ as usize) };
+    assert_eq!(ret, EVM_JIT_STATUS_OK);
+    holder.state.commit(checkpoint).unwrap();
+    assert_eq!(holder.state.host(holder.address).sload(U256::zero() + 7), Ok(U256::zero() + 42));
+    assert_eq!(holder.state.host(holder.address).tload(U256::zero() + 7), U256::zero() + 43);
+}
+
+// storage backend that is down, for the error path of the storage callbacks
+struct EvmFailingHost;
+
+impl crate::state::EvmHost for EvmFailingHost {
+    fn sload(&mut self, _key: U256) -> Result<U256, crate::state::EvmStateError> {
+        Err(crate::state::EvmStateError::StorageBackend("connection lost".to_string()))
+    }
+
+    fn sstore(&mut self, _key: U256, _value: U256) -> Result<(), crate::state::EvmStateError> {
+        Err(crate::state::EvmStateError::StorageBackend("read-only".to_string()))
+    }
+
+    fn tload(&mut self, _key: U256) -> U256 {
+        U256::zero()
+    }
+
+    fn tstore(&mut self, _key: U256, _value: U256) {}
+}
+
+#[test]
+fn failing_storage_backend_sets_storage_error() {
+    use crate::jit::{JitEvmExecutionContextHolder, JitEvmEngine, JitEvmHostHook, EVM_JIT_STATUS_ERROR_STORAGE};
+    use crate::code::{EvmCode, EvmOp::*};
+    use inkwell::context::Context;
+
+    for (ops, msg) in [
+        (vec![Push(1, U256::zero() + 7), Sload, Stop], "state error: storage backend: connection lost"),
+        (vec![Push(1, U256::one()), Push(1, U256::zero() + 7), Sstore, Stop], "state error: storage backend: read-only"),
+    ] {
+        let context = Context::create();
+        let engine = JitEvmEngine::new_from_context(&context).unwrap();
+        let fn_contract = engine.jit_compile_contract(&EvmCode { ops }.index(), None, None).unwrap();
+
+        let mut holder = JitEvmExecutionContextHolder::new_from_empty();
+        let mut ctx = JitEvmExecutionContext::new_from_holder(&mut holder);
+        let mut backend = EvmFailingHost;
+        let mut hook = JitEvmHostHook::new(&mut backend);
+        ctx.set_host(&mut hook);
+        let ret = unsafe { fn_contract.call(&mut ctx as *mut _ as usize) };
+
+        assert_eq!(ret, EVM_JIT_STATUS_ERROR_STORAGE);
+        assert_eq!(holder.host_error, Some(msg.to_string()));
+    }
+}
+
+#[test]
+fn panicking_callback_sets_host_error() {
+    use crate::jit::{JitEvmExecutionContextHolder, JitEvmEngine, EVM_JIT_STATUS_OK, EVM_JIT_STATUS_ERROR_HOST};
+
+    let mut holder = JitEvmExecutionContextHolder::new_from_empty();
+    let mut ctx = JitEvmExecutionContext::new_from_holder(&mut holder);
+    let ctx_ptr = &mut ctx as *mut _ as usize;
+
+    assert_eq!(JitEvmEngine::guard_callback(ctx_ptr, |_| Ok(())), EVM_JIT_STATUS_OK);
+    assert_eq!(holder.host_error, None);
+
+    assert_eq!(JitEvmEngine::guard_callback(ctx_ptr, |_| panic!("host exploded: {}", 42)), EVM_JIT_STATUS_ERROR_HOST);
+    assert_eq!(holder.host_error, Some("host exploded: 42".to_string()));
+
+    assert_eq!(JitEvmEngine::guard_callback(ctx_ptr, |_| Err((17, "failed".to_string()))), 17);
+    assert_eq!(holder.host_error, Some("failed".to_string()));
+}
+
+#[test]
+fn status_messages() {
+    use crate::jit::{status_message, EVM_JIT_STATUS_OK, EVM_JIT_STATUS_ERROR_HOST, EVM_JIT_STATUS_ERROR_STACK, EVM_JIT_STATUS_RETF, EVM_JIT_STATUS_JUMPF};
+
+    assert_eq!(status_message(EVM_JIT_STATUS_OK), None);
+    assert_eq!(status_message(EVM_JIT_STATUS_ERROR_STACK), Some("jit error: stack underflow or overflow"));
+    assert_eq!(status_message(EVM_JIT_STATUS_ERROR_HOST), Some("jit error: host callback failed"));
+    assert_eq!(status_message(EVM_JIT_STATUS_RETF), Some("jit error: internal: RETF escaped a code section"));
+    assert_eq!(status_message(EVM_JIT_STATUS_JUMPF + 1), Some("jit error: internal: JUMPF escaped a code section"));
+}
+
+// inspector that fails when the given instruction is reached
+struct EvmPanickingInspector(usize);
+
+impl crate::inspector::EvmInspector for EvmPanickingInspector {
+    fn step(&mut self, step: &crate::inspector::EvmStep) {
+        if step.pc == self.0 {
+            panic!("inspector exploded at pc {}", step.pc);
+        }
+    }
+}
+
+#[test]
+fn panicking_inspector_stops_compiled_code() {
+    use crate::jit::{JitEvmExecutionContextHolder, JitEvmEngine, JitEvmInspectorHook, JitEvmInstrumentation, EVM_JIT_STATUS_ERROR_HOST};
+    use crate::code::{EvmCode, EvmOp::*};
+    use crate::state::EvmHost;
+    use inkwell::context::Context;
+
+    let code = EvmCode { ops: vec![
+        Push(1, U256::zero() + 42),
+        Push(1, U256::zero() + 7),
+        Sstore,
+        Jumpdest,                        // byte 5, starts the second block
+        Push(1, U256::zero() + 43),
+        Push(1, U256::zero() + 8),
+        Sstore,
+        Stop,
+    ] }.index();
+
+    let context = Context::create();
+    let mut engine = JitEvmEngine::new_from_context(&context).unwrap();
+    engine.instrumentation = JitEvmInstrumentation::Blocks;
+    let fn_contract = engine.jit_compile_contract(&code, None, None).unwrap();
+
+    let mut holder = JitEvmExecutionContextHolder::new_from_empty();
+    let mut ctx = JitEvmExecutionContext::new_from_holder(&mut holder);
+    let mut inspector = EvmPanickingInspector(5);
+    let ret = {
+        let mut hook = JitEvmInspectorHook::new(&code, &mut inspector);
+        ctx.set_inspector(&mut hook);
+        unsafe { fn_contract.call(&mut ctx as *mut _ as usize) }
+    };
+
+    // the generated code checks the status of the callback and exits right away
+    assert_eq!(ret, EVM_JIT_STATUS_ERROR_HOST);
+    assert_eq!(holder.host_error, Some("inspector exploded at pc 5".to_string()));
+    assert_eq!(holder.state.host(holder.address).sload(U256::zero() + 7), Ok(U256::zero() + 42));
+    assert_eq!(holder.state.host(holder.address).sload(U256::zero() + 8), Ok(U256::zero()));
+}
+
+
+fn trace_interpreter(ops: Vec<EvmOp>) -> Vec<String> {
+    use crate::interpreter::{EvmContext, EvmInnerContext, EvmOuterContext};
+    use crate::inspector::EvmEip3155Tracer;
+    use crate::code::EvmCode;
+    use crate::constants::EVM_STACK_SIZE;
+    use crate::state::EvmJournaledState;
+    use primitive_types::H160;
+
+    let code = EvmCode { ops }.index();
+    let mut ctx = EvmContext {
+        outer: EvmOuterContext {
+            calldata: vec![],
+            state: EvmJournaledState::new(),
+            address: H160::zero(),
+            callvalue: U256::zero(),
+        },
+        inner: EvmInnerContext {
+            code: &code,
+            stack: [U256::zero(); EVM_STACK_SIZE],
+            pc: 0,
+            sp: 0,
+            memory: vec![],
+        },
+    };
+
+    let mut tracer = EvmEip3155Tracer::new(Vec::new());
+    while ctx.tick_with_inspector(&mut tracer).unwrap() {}
+    String::from_utf8(tracer.into_inner().unwrap()).unwrap().lines().map(|l| l.to_string()).collect()
+}
+
+fn trace_jit(ops: Vec<EvmOp>, instrumentation: crate::jit::JitEvmInstrumentation) -> Vec<String> {
+    use crate::jit::{JitEvmExecutionContextHolder, JitEvmEngine, JitEvmInspectorHook, EVM_JIT_STATUS_OK};
+    use crate::inspector::EvmEip3155Tracer;
+    use crate::code::EvmCode;
+    use inkwell::context::Context;
+
+    let context = Context::create();
+    let mut engine = JitEvmEngine::new_from_context(&context).unwrap();
+    engine.instrumentation = instrumentation;
+    let code = EvmCode { ops }.index();
+    let fn_contract = engine.jit_compile_contract(&code, None, None).unwrap();
+
+    let mut holder = JitEvmExecutionContextHolder::new_from_empty();
+    let mut ctx = JitEvmExecutionContext::new_from_holder(&mut holder);
+    let mut tracer = EvmEip3155Tracer::new(Vec::new());
+    {
+        let mut hook = JitEvmInspectorHook::new(&code, &mut tracer);
+        ctx.set_inspector(&mut hook);
+        let ret = unsafe { fn_contract.call(&mut ctx as *mut _ as usize) };
+        assert_eq!(ret, EVM_JIT_STATUS_OK);
+    }
+    String::from_utf8(tracer.into_inner().unwrap()).unwrap().lines().map(|l| l.to_string()).collect()
+}
+
+#[test]
+fn instrumented_jit_traces_like_interpreter() {
+    use crate::jit::JitEvmInstrumentation;
+
+    for ops in vec![
+        crate::test_data::get_code_ops_fibonacci(),
+        crate::test_data::get_code_ops_supersimple1(),
+        vec![
+            EvmOp::Push(1, U256::zero() + 42),
+            EvmOp::Push(1, U256::zero() + 7),
+            EvmOp::Sstore,
+            EvmOp::Push(1, U256::zero() + 7),
+            EvmOp::Sload,
+        ],
+    ] {
+        let trace = trace_interpreter(ops.clone());
+        assert_eq!(trace_jit(ops.clone(), JitEvmInstrumentation::Instructions), trace);
+        assert!(trace_jit(ops.clone(), JitEvmInstrumentation::None).is_empty());
+
+        // block instrumentation reports the first instruction of every executed block
+        let blocks = trace_jit(ops.clone(), JitEvmInstrumentation::Blocks);
+        assert!(!blocks.is_empty() && blocks.len() <= trace.len());
+        assert_eq!(blocks[0], trace[0]);
+        let lines: std::collections::HashSet<_> = trace.iter().collect();
+        assert!(blocks.iter().all(|l| lines.contains(l)));
+    }
+}
+
+#[test]
+fn instrumented_superinstructions_are_rejected() {
+    use crate::jit::{JitEvmEngine, JitEvmInstrumentation};
+    use crate::code::{EvmCode, peephole::EvmPass};
+    use inkwell::context::Context;
+
+    let code = EvmCode { ops: crate::test_data::get_code_ops_fibonacci() }.optimize(&EvmPass::ALL).index();
+    for (instrumentation, ok) in [
+        (JitEvmInstrumentation::None, true),
+        (JitEvmInstrumentation::Blocks, false),
+        (JitEvmInstrumentation::Instructions, false),
+    ] {
+        let context = Context::create();
+        let mut engine = JitEvmEngine::new_from_context(&context).unwrap();
+        engine.instrumentation = instrumentation;
+        assert_eq!(engine.jit_compile_contract(&code, None, None).is_ok(), ok);
+    }
+}
+
+#[test]
+fn unreachable_blocks_are_not_compiled() {
+    use crate::jit::{JitEvmEngine, JitEvmInstrumentation, EVM_JIT_STATUS_OK};
+    use crate::code::{EvmCode, EvmOp::*};
+    use inkwell::context::Context;
+
+    // after Stop, neither the unsupported Callvalue nor the never jumped to Jumpdest and data
+    let ops = vec![
+        Push(1, U256::zero() + 42),
+        Push(1, U256::zero() + 7),
+        Sstore,
+        Stop,
+        Callvalue,
+        Jumpdest,
+        Calldataload,
+        Data(vec![0xa2, 0x64]),
+    ];
+    assert_eq!(run_jit_ops_retval(ops.clone()), EVM_JIT_STATUS_OK);
+
+    let context = Context::create();
+    let engine = JitEvmEngine::new_from_context(&context).unwrap();
+    let path = std::env::temp_dir().join(format!("jitevm-unreachable-{}.ll", std::process::id()));
+    engine.jit_compile_contract(&EvmCode { ops }.index(), Some(path.to_str().unwrap().to_string()), None).unwrap();
+    let ir = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(ir.contains("Block #0: "));
+    assert!(!ir.contains("Block #1: ") && !ir.contains("Block #2: "));
+
+    // skipped code in between keeps the offsets of the code after it
+    let ops = vec![
+        Push(1, U256::zero() + 4),
+        Jump,
+        Callvalue,
+        Jumpdest,                        // byte 4
+        Push(1, U256::zero() + 42),
+        Push(1, U256::zero() + 7),
+        Sstore,
+        Stop,
+    ];
+    let trace = trace_interpreter(ops.clone());
+    assert!(trace[2].starts_with(r#"{"pc":4,"#));
+    assert_eq!(trace_jit(ops, JitEvmInstrumentation::Instructions), trace);
+}
+
+
+// status and the given storage slots after running the container
+fn run_jit_eof(container: &str, keys: &[u64]) -> (u64, Vec<U256>) {
+    use crate::jit::{JitEvmExecutionContextHolder, JitEvmEngine};
+    use crate::code::eof::EvmEofContainer;
+    use crate::state::EvmHost;
+    use inkwell::context::Context;
+
+    let eof = EvmEofContainer::new_from_bytes(&hex::decode(container).unwrap()).unwrap();
+    let context = Context::create();
+    let engine = JitEvmEngine::new_from_context(&context).unwrap();
+    let fn_contract = engine.jit_compile_eof(&eof, None, None).unwrap();
+
+    let mut holder = JitEvmExecutionContextHolder::new_from_empty();
+    let mut ctx = JitEvmExecutionContext::new_from_holder(&mut holder);
+    let ret = unsafe { fn_contract.call(&mut ctx as *mut _ as usize) };
+    (ret, keys.iter().map(|key| holder.state.host(holder.address).sload(U256::zero() + *key).unwrap()).collect())
+}
+
+#[test]
+fn eof_sections_compile_to_functions() {
+    use crate::jit::{EVM_JIT_STATUS_OK, EVM_JIT_STATUS_ERROR_STACK};
+
+    // PUSH1 5 CALLF 1 PUSH1 0 SSTORE STOP, and DUP1 ADD RETF
+    assert_eq!(run_jit_eof("ef000101000802000200090003040000000080000201010002\
+        6005e3000160005500\
+        8001e4", &[0]), (EVM_JIT_STATUS_OK, vec![U256::zero() + 10]));
+
+    // counts down from 3 (RJUMPI), then JUMPF 1: PUSH1 7 ADD PUSH1 1 SSTORE STOP
+    assert_eq!(run_jit_eof("ef0001010008020002000d0007040000000080000201800002\
+        60036001900380e1fff8e50001\
+        60070160015500", &[1]), (EVM_JIT_STATUS_OK, vec![U256::zero() + 7]));
+
+    // PUSH1 idx RJUMPV [0, 5], PUSH1 42 PUSH1 0 SSTORE, STOP
+    for (idx, expected) in [(0, 42), (1, 0), (2, 42)] {
+        let container = format!("ef0001010004020001000e040000000080000260{:02x}e20100000005602a60005500", idx);
+        assert_eq!(run_jit_eof(&container, &[0]), (EVM_JIT_STATUS_OK, vec![U256::zero() + expected]));
+    }
+
+    // a section that calls itself until the return stack is full
+    assert_eq!(run_jit_eof("ef000101000802000200040004040000000080000000000000e3000100e30001e4", &[]).0, EVM_JIT_STATUS_ERROR_STACK);
+}
+
+#[test]
+fn eof_jumpf_does_not_grow_the_native_stack() {
+    use crate::jit::EVM_JIT_STATUS_OK;
+
+    // PUSH3 1000000 JUMPF 1, and PUSH1 1 SWAP1 SUB DUP1 RJUMPI +6 (PUSH1 42 PUSH1 0 SSTORE STOP) JUMPF 1
+    assert_eq!(run_jit_eof("ef000101000802000200070011040000000080000101800003\
+        620f4240e50001\
+        6001900380e10006602a60005500e50001", &[0]), (EVM_JIT_STATUS_OK, vec![U256::zero() + 42]));
+
+    // PUSH1 5 CALLF 1 PUSH1 0 SSTORE STOP, JUMPF 2, and DUP1 ADD RETF (to the caller of section 1)
+    assert_eq!(run_jit_eof("ef000101000c02000300090003000304000000008000020101000101010002\
+        6005e3000160005500\
+        e50002\
+        8001e4", &[0]), (EVM_JIT_STATUS_OK, vec![U256::zero() + 10]));
+}
@@ -1,6 +1,12 @@
 pub mod constants;
+pub mod fork;
 pub mod code;
 pub mod operations;
+pub mod precompiles;
+pub mod state;
+pub mod inspector;
 pub mod interpreter;
 pub mod jit;
+pub mod fuzz;
+pub mod statetest;
 pub mod test_data;
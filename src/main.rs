@@ -1,277 +1,417 @@
-use eyre::Result;
+use inkwell::context::Context;
+use inkwell::execution_engine::JitFunction;
 use jitevm::code::{EvmCode, EvmOpParserMode, IndexedEvmCode};
+use jitevm::code::disasm::{EvmDisassembly, EvmDisasmStyle};
+use jitevm::code::peephole::EvmPass;
 use jitevm::constants::EVM_STACK_SIZE;
 use jitevm::interpreter::{EvmContext, EvmInnerContext, EvmOuterContext};
-use jitevm::jit::{JitEvmEngine, JitEvmExecutionContext};
-use jitevm::test_data;
-use primitive_types::U256;
-use std::collections::HashMap;
+use jitevm::jit::{status_message, JitEvmCompiledContract, JitEvmEmit, JitEvmEngine, JitEvmExecutionContext, JitEvmExecutionContextHolder};
+use jitevm::state::{EvmJournaledState, EvmLog};
+use jitevm::statetest::parse_pre;
+use primitive_types::{H160, U256};
+use serde_json::{json, Value};
 use std::error::Error;
-use std::time::Instant;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+
+const USAGE: &str = "usage: jitevm <command> [options] <code>
+
+<code> is hex bytecode, or a file containing hex or binary bytecode
+
+commands:
+    run        execute the code and print the result as JSON
+    compile    compile the code and write LLVM IR, assembly or an object file
+    disasm     print an annotated listing of the instructions
+    cfg        print the basic blocks as JSON, or as graphviz with --dot
+    bench      time repeated executions and print the timings as JSON
+
+options:
+    --backend interp|jit      backend for run and bench (default: interp)
+    --calldata <hex>          (interp only)
+    --callvalue <value>       decimal or 0x-prefixed hex (interp only)
+    --gas-limit <value>       gas is not metered yet, reported as gas left
+    --pre <file>              pre-state as JSON, in the format of the state tests' \"pre\"
+    --address <hex>           address of the executed account (default: zero)
+    --emit ir|asm|obj         output of compile (default: ir)
+    -o <file>                 output file of compile (default: contract.ll/.s/.o)
+    --format <style>          listing, evmasm or json output of disasm (default: listing)
+    --dot                     graphviz output for cfg
+    --iterations <n>          executions for bench (default: 10)
+    --passes <passes>         peephole passes, all, none or a comma-separated list of
+                              constant-folding, swap-pop, push-calldataload, push-add,
+                              dup-iszero-push-jumpi, compare-push-jumpi, push-jump (default: all)";
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Interpreter,
+    Jit,
+}
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let ops = test_data::get_code_ops_fibonacci();
-    // let ops = test_data::get_code_ops_fibonacci_repetitions();
-    // let ops = test_data::get_code_ops_supersimple1();
-    // let ops = test_data::get_code_ops_supersimple2();
-    // let ops = test_data::get_code_ops_storage1();
-    // let ops = test_data::get_code_bin_revm_test1();
-
-    // TESTING BASIC OPERATIONS WITH EVMOP AND EVMCODE
-
-    let code = EvmCode { ops: ops.clone() };
-    let augmented_code = code.augment();
-    let _indexed_code = IndexedEvmCode::new_from_evmcode(augmented_code.clone());
-
-    // println!("Code: {:?}", code);
-    // println!("Augmented code: {:?}", augmented_code);
-    // println!("Indexed code: {:?}", indexed_code);
-    println!("Serialized code: {:?}", code.to_bytes());
-    println!("Serialized code (hex): {:?}", hex::encode(code.to_bytes()));
-
-    assert!(code.to_bytes() == augmented_code.to_bytes());
-    assert!(code == EvmCode::new_from_bytes(&augmented_code.to_bytes(), EvmOpParserMode::Strict)?);
-
-    let bcode = test_data::get_code_bin_revm_test1();
-    let code = EvmCode::new_from_bytes(&bcode, EvmOpParserMode::Lax)?;
-    // println!("Deserialized code: {:?}", code);
-    // let ops = code.clone().ops;
-
-    use itertools::Itertools;
-    println!(
-        "Unique instructions: {:?}",
-        code.ops
-            .iter()
-            .unique()
-            .sorted()
-            .collect::<Vec<&jitevm::code::EvmOp>>()
-    );
-
-    // TESTING EVMINTERPRETER
+#[derive(Debug)]
+struct Args {
+    command: String,
+    code: Vec<u8>,
+    calldata: Vec<u8>,
+    callvalue: U256,
+    gas_limit: u64,
+    pre: Option<String>,
+    address: H160,
+    backend: Backend,
+    emit: JitEvmEmit,
+    output: Option<String>,
+    format: Option<EvmDisasmStyle>,   // None for JSON
+    dot: bool,
+    iterations: usize,
+    passes: Vec<EvmPass>,
+}
 
-    let mut ctx = EvmContext {
-        outer: EvmOuterContext {
-            calldata: hex::decode("30627b7c").unwrap().into(),
-            // returndata: vec![],
-            storage: HashMap::new(),
-            callvalue: U256::zero(),
-        },
-        inner: EvmInnerContext {
-            code: &EvmCode { ops: ops.clone() }.index(),
-            stack: [0.into(); EVM_STACK_SIZE],
-            pc: 0,
-            sp: 0,
-            // gas: 0,
-            memory: vec![],
-        },
-    };
 
-    println!("Benchmarking interpreted execution ...");
-    let mut t = 0;
-    println!("t={}: Context: {:?}", t, ctx);
-    let measurement_now = Instant::now();
-    loop {
-        // let ctx_pre = ctx.clone();
-        match ctx.tick() {
-            Ok(false) => {
-                break;
-            }
-            Ok(true) => {}
-            Err(e) => {
-                println!("Interpreter error at t={}: {:?}: {}", t, e, e);
-                // println!("Pre-context: {:?}", ctx_pre);
-                println!("Context: {:?}", ctx);
-                break;
-            }
-        }
-        // if !ctx.tick()? {
-        //     break;
-        // };
-        t += 1;
-        // println!("t={}: Context: {:?}", t, ctx);
+// ARGUMENTS
+
+fn parse_passes(s: &str) -> Result<Vec<EvmPass>, Box<dyn Error>> {
+    Ok(match s {
+        "all" => EvmPass::ALL.to_vec(),
+        "none" => vec![],
+        _ => s.split(',')
+            .map(|name| EvmPass::new_from_name(name).ok_or_else(|| format!("unknown pass {}", name)))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    Ok(hex::decode(s.trim().trim_start_matches("0x"))?)
+}
+
+fn parse_value(s: &str) -> Result<U256, Box<dyn Error>> {
+    Ok(match s.strip_prefix("0x") {
+        Some(digits) => U256::from_str_radix(digits, 16).map_err(|e| format!("invalid value {}: {:?}", s, e))?,
+        None => U256::from_dec_str(s).map_err(|e| format!("invalid value {}: {:?}", s, e))?,
+    })
+}
+
+fn parse_address(s: &str) -> Result<H160, Box<dyn Error>> {
+    let bytes = parse_hex(s)?;
+    if bytes.len() != 20 {
+        return Err(format!("invalid address {}: expected 20 bytes, got {}", s, bytes.len()).into());
     }
-    let measurement_runtime = measurement_now.elapsed();
-    println!("t={}: Context: {:?}", t, ctx);
-    println!("Runtime: {:.2?}", measurement_runtime);
+    Ok(H160::from_slice(&bytes))
+}
 
-    // TESTING JIT
+// hex on the command line, or a file with hex or raw bytecode
+fn read_code(arg: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    if !Path::new(arg).is_file() {
+        return parse_hex(arg);
+    }
+    let content = std::fs::read(arg)?;
+    match std::str::from_utf8(&content).ok().and_then(|s| parse_hex(s).ok()) {
+        Some(code) => Ok(code),
+        None => Ok(content),
+    }
+}
 
-    use inkwell::context::Context;
-    let context = Context::create();
-    let engine = JitEvmEngine::new_from_context(&context)?;
-    // let fn_contract = engine.jit_compile_contract(&EvmCode { ops: ops.clone() }.augment().index())?;
-    let fn_contract = engine.jit_compile_contract(&EvmCode { ops: ops.clone() }.augment().index(), Some("jit_main.ll".to_string()), Some("jit_main.asm".to_string()))?;
-
-    println!("Benchmark compiled execution ...");
-    for _i in 0..10 {
-        let mut execution_context_stack = [U256::zero(); 1024];
-        // TODO: at maximum block size of 30M gas, max memory size is 123169 words = ~128000 words = 4096000 bytes
-        let mut execution_context_memory = [0u8; 4096000];
-        let mut execution_context_storage = HashMap::<U256, U256>::new();
-
-        let mut execution_context = JitEvmExecutionContext {
-            stack: &mut execution_context_stack as *mut _ as usize,
-            memory: &mut execution_context_memory as *mut _ as usize,
-            storage: &mut execution_context_storage as *mut _ as usize,
-        };
-        println!("INPUT: {:?}", execution_context.clone());
-
-        let measurement_now = Instant::now();
-        let ret = unsafe { fn_contract.call(&mut execution_context as *mut _ as usize) };
-        let measurement_runtime = measurement_now.elapsed();
-
-        println!("Ret: {:?}", ret);
-        println!("Stack: {:?}", execution_context_stack);
-        println!("Storage: {:?}", execution_context_storage);
-        println!("Runtime: {:.2?}", measurement_runtime);
+fn parse_args() -> Result<Args, Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let command = args.next().ok_or(USAGE)?;
+    if command == "--help" || command == "-h" {
+        println!("{}", USAGE);
+        std::process::exit(0);
     }
 
+    let mut code = None;
+    let mut calldata = None;
+    let mut callvalue = None;
+    let mut gas_limit = 30_000_000;
+    let mut pre = None;
+    let mut address = H160::zero();
+    let mut backend = Backend::Interpreter;
+    let mut emit = JitEvmEmit::Ir;
+    let mut output = None;
+    let mut format = Some(EvmDisasmStyle::Listing);
+    let mut dot = false;
+    let mut iterations = 10;
+    let mut passes = EvmPass::ALL.to_vec();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(USAGE);
+        match arg.as_str() {
+            "--backend" => backend = match value()?.as_str() {
+                "interp" => Backend::Interpreter,
+                "jit" => Backend::Jit,
+                _ => return Err(USAGE.into()),
+            },
+            "--calldata" => calldata = Some(parse_hex(&value()?)?),
+            "--callvalue" => callvalue = Some(parse_value(&value()?)?),
+            "--gas-limit" => gas_limit = parse_value(&value()?)?.low_u64(),
+            "--pre" => pre = Some(value()?),
+            "--address" => address = parse_address(&value()?)?,
+            "--emit" => emit = match value()?.as_str() {
+                "ir" => JitEvmEmit::Ir,
+                "asm" => JitEvmEmit::Asm,
+                "obj" => JitEvmEmit::Obj,
+                _ => return Err(USAGE.into()),
+            },
+            "-o" => output = Some(value()?),
+            "--format" => format = match value()?.as_str() {
+                "listing" => Some(EvmDisasmStyle::Listing),
+                "evmasm" => Some(EvmDisasmStyle::Evmasm),
+                "json" => None,
+                _ => return Err(USAGE.into()),
+            },
+            "--dot" => dot = true,
+            "--iterations" => iterations = value()?.parse()?,
+            "--passes" => passes = parse_passes(&value()?)?,
+            _ if code.is_none() => code = Some(read_code(&arg)?),
+            _ => return Err(USAGE.into()),
+        }
+    }
 
-    // TESTING AOT-COMPILED EVM
+    // the JIT does not support CALLDATALOAD and CALLVALUE yet
+    if backend == Backend::Jit && (calldata.is_some() || callvalue.is_some()) {
+        return Err("--calldata and --callvalue are not supported with --backend jit".into());
+    }
+
+    Ok(Args {
+        command,
+        code: code.ok_or(USAGE)?,
+        calldata: calldata.unwrap_or_default(),
+        callvalue: callvalue.unwrap_or_default(),
+        gas_limit,
+        pre,
+        address,
+        backend,
+        emit,
+        output,
+        format,
+        dot,
+        iterations,
+        passes,
+    })
+}
+
+
+// EXECUTION
+
+fn pre_state(args: &Args) -> Result<EvmJournaledState, Box<dyn Error>> {
+    match &args.pre {
+        Some(path) => {
+            let (accounts, _) = parse_pre(&serde_json::from_str(&std::fs::read_to_string(path)?)?)?;
+            Ok(EvmJournaledState::new_from_accounts(accounts))
+        },
+        None => Ok(EvmJournaledState::new()),
+    }
+}
 
-    let ctx_raw = EvmContext {
+// error message if execution failed (state changes are reverted then), and the post-state
+fn run_interpreter(args: &Args, code: &IndexedEvmCode, state: EvmJournaledState) -> (Option<String>, EvmJournaledState) {
+    let mut ctx = EvmContext {
         outer: EvmOuterContext {
-            calldata: hex::decode("30627b7c").unwrap().into(),
-            // returndata: vec![],
-            storage: HashMap::new(),
-            callvalue: U256::zero(),
+            calldata: args.calldata.clone(),
+            state,
+            address: args.address,
+            callvalue: args.callvalue,
         },
         inner: EvmInnerContext {
-            code: &EvmCode { ops: ops.clone() }.index(),
-            stack: [0.into(); EVM_STACK_SIZE],
+            code,
+            stack: [U256::zero(); EVM_STACK_SIZE],
             pc: 0,
             sp: 0,
-            // gas: 0,
             memory: vec![],
         },
     };
 
-    // println!("Benchmarking interpreted execution ...");
-    // let mut t = 0;
-    // println!("t={}: Context: {:?}", t, ctx);
-
-    macro_rules! exec_aot_evmop {
-        ($ctx:expr, $_aot_ret:ident, $pc:expr, $op:expr) => {
-            $ctx.inner.pc = $pc;
-            match $ctx.tick_inner_simplified($op) {
-                Ok(true) => {},
-                Ok(false) => { $_aot_ret = 0; break; },
-                Err(_) => { $_aot_ret = 1; break; },
-            }
-        };
+    let checkpoint = ctx.outer.state.checkpoint();
+    let error = loop {
+        match ctx.tick() {
+            Ok(true) => {},
+            Ok(false) => break None,
+            Err(e) => break Some(e.to_string()),
+        }
+    };
+    let mut state = ctx.outer.state;
+    if error.is_none() {
+        state.commit(checkpoint).unwrap();
+    } else {
+        state.revert(checkpoint).unwrap();
     }
+    (error, state)
+}
 
-    for i in 0..3 {
-        let mut ctx = ctx_raw.clone();
+fn run_jit(args: &Args, holder: &mut JitEvmExecutionContextHolder, fn_contract: &JitFunction<JitEvmCompiledContract>) -> Option<String> {
+    holder.address = args.address;
+    holder.host_error = None;
+    let checkpoint = holder.state.checkpoint();
+    let mut ctx = JitEvmExecutionContext::new_from_holder(holder);
+    let status = unsafe { fn_contract.call(&mut ctx as *mut _ as usize) };
+
+    let error = status_message(status).map(|msg| match &holder.host_error {
+        Some(e) => format!("{}: {}", msg, e),
+        None => msg.to_string(),
+    });
+    if error.is_none() {
+        holder.state.commit(checkpoint).unwrap();
+    } else {
+        holder.state.revert(checkpoint).unwrap();
+    }
+    error
+}
 
-        enum AotJumpDest {
-            JumpDest0,
-            JumpDest1,
-            JumpDest2,
-        }
-        let mut _jumpdest = AotJumpDest::JumpDest0;
-        let mut _aot_ret: usize = 0;
-
-        let measurement_now = Instant::now();
-        loop {
-            use jitevm::code::EvmOp::*;
-            match _jumpdest {
-                AotJumpDest::JumpDest0 => {
-                    exec_aot_evmop!(ctx, _aot_ret, 0, Push(2, U256::zero() + 6000 - 2));
-                    exec_aot_evmop!(ctx, _aot_ret, 3, Push(1, U256::zero()));
-                    exec_aot_evmop!(ctx, _aot_ret, 5, Push(1, U256::one()));
-                    _jumpdest = AotJumpDest::JumpDest1;
-                    continue;
-                },
-                AotJumpDest::JumpDest1 => {
-                    // exec_aot_evmop!(ctx, _aot_ret, 7, Jumpdest);   // op 3 code 7
-                    exec_aot_evmop!(ctx, _aot_ret, 8, Dup3);
-                    exec_aot_evmop!(ctx, _aot_ret, 9, Iszero);
-                    exec_aot_evmop!(ctx, _aot_ret, 10, Push(1, U256::zero() + 28));
-                    exec_aot_evmop!(ctx, _aot_ret, 12, Jumpi);
-                    if ctx.inner.pc == 7 {
-                        _jumpdest = AotJumpDest::JumpDest1;
-                        continue;
-                    } else if ctx.inner.pc == 28 {
-                        _jumpdest = AotJumpDest::JumpDest2;
-                        continue;
-                    } else if ctx.inner.pc == 12 {
-                        // no jump occurred - TODO: this needs fixing
-                    } else {
-                        _aot_ret = 2;
-                        break;
-                    }
-                    exec_aot_evmop!(ctx, _aot_ret, 13, Dup2);
-                    exec_aot_evmop!(ctx, _aot_ret, 14, Dup2);
-                    exec_aot_evmop!(ctx, _aot_ret, 15, Add);
-                    exec_aot_evmop!(ctx, _aot_ret, 16, Swap2);
-                    exec_aot_evmop!(ctx, _aot_ret, 17, Pop);
-                    exec_aot_evmop!(ctx, _aot_ret, 18, Swap1);
-                    exec_aot_evmop!(ctx, _aot_ret, 19, Swap2);
-                    exec_aot_evmop!(ctx, _aot_ret, 20, Push(1, U256::one()));
-                    exec_aot_evmop!(ctx, _aot_ret, 22, Swap1);
-                    exec_aot_evmop!(ctx, _aot_ret, 23, Sub);
-                    exec_aot_evmop!(ctx, _aot_ret, 24, Swap2);
-                    exec_aot_evmop!(ctx, _aot_ret, 25, Push(1, U256::zero() + 7));
-                    exec_aot_evmop!(ctx, _aot_ret, 27, Jump);
-                    if ctx.inner.pc == 7 {
-                        _jumpdest = AotJumpDest::JumpDest1;
-                        continue;
-                    } else if ctx.inner.pc == 28 {
-                        _jumpdest = AotJumpDest::JumpDest2;
-                        continue;
-                    } else {
-                        _aot_ret = 2;
-                        break;
-                    }
-                },
-                AotJumpDest::JumpDest2 => {
-                    // exec_aot_evmop!(ctx, _aot_ret, 28, Jumpdest);   // op 21 code 28
-                    exec_aot_evmop!(ctx, _aot_ret, 29, Swap2);
-                    exec_aot_evmop!(ctx, _aot_ret, 30, Pop);
-                    exec_aot_evmop!(ctx, _aot_ret, 31, Pop);
-                    exec_aot_evmop!(ctx, _aot_ret, 32, Stop);
-                },
-            }
-        }
+fn result_json(args: &Args, error: Option<String>, state: &EvmJournaledState, logs: &[EvmLog]) -> Value {
+    let hex_u256 = |v: &U256| format!("{:#x}", v);
+
+    let storage: serde_json::Map<String, Value> = state.accounts.iter()
+        .filter(|(_, account)| !account.storage.is_empty())
+        .map(|(address, account)| {
+            let slots: serde_json::Map<String, Value> = account.storage.iter()
+                .map(|(k, v)| (hex_u256(k), Value::String(hex_u256(v))))
+                .collect();
+            (format!("{:?}", address), Value::Object(slots))
+        })
+        .collect();
+
+    let logs: Vec<Value> = logs.iter().map(|log| json!({
+        "address": format!("{:?}", log.address),
+        "topics": log.topics.iter().map(hex_u256).collect::<Vec<_>>(),
+        "data": format!("0x{}", hex::encode(&log.data)),
+    })).collect();
+
+    json!({
+        "status": if error.is_none() { "success" } else { "error" },
+        "error": error,
+        "returnData": "0x",
+        "gasUsed": "0x0",
+        "gasLeft": format!("{:#x}", args.gas_limit),
+        "storage": storage,
+        "logs": logs,
+    })
+}
 
-        let measurement_runtime = measurement_now.elapsed();
-        println!("{} -> Context: {:?}", _aot_ret, ctx);
-        println!("Runtime: {:.2?}", measurement_runtime);
-    }
 
+// COMMANDS
 
+fn cmd_run(args: &Args, code: &IndexedEvmCode) -> Result<Value, Box<dyn Error>> {
+    let (error, mut state) = match args.backend {
+        Backend::Interpreter => run_interpreter(args, code, pre_state(args)?),
+        Backend::Jit => {
+            let context = Context::create();
+            let engine = JitEvmEngine::new_from_context(&context)?;
+            let fn_contract = engine.jit_compile_contract(code, None, None)?;
 
+            let mut holder = Box::new(JitEvmExecutionContextHolder::new_from_empty());
+            holder.state = pre_state(args)?;
+            let error = run_jit(args, &mut holder, &fn_contract);
+            (error, std::mem::take(&mut holder.state))
+        },
+    };
+    let logs = state.end_transaction();
+    Ok(result_json(args, error, &state, &logs))
+}
 
-    // fn jit_compile_sum(&self) -> Option<JitFunction<SumFunc>> {
-    //     let i64_type = self.context.i64_type();
-    //     let fn_type = i64_type.fn_type(&[i64_type.into(), i64_type.into(), i64_type.into()], false);
-    //     let function = self.module.add_function("sum", fn_type, None);
-    //     let basic_block = self.context.append_basic_block(function, "entry");
+fn cmd_compile(args: &Args, code: &IndexedEvmCode) -> Result<(), Box<dyn Error>> {
+    let output = args.output.clone().unwrap_or_else(|| match args.emit {
+        JitEvmEmit::Ir => "contract.ll",
+        JitEvmEmit::Asm => "contract.s",
+        JitEvmEmit::Obj => "contract.o",
+    }.to_string());
 
-    //     self.builder.position_at_end(basic_block);
+    let context = Context::create();
+    let engine = JitEvmEngine::new_from_context(&context)?;
+    engine.jit_compile_contract(code, None, None)?;
+    engine.emit(args.emit, &output)?;
+    Ok(())
+}
 
-    //     let x = function.get_nth_param(0)?.into_int_value();
-    //     let y = function.get_nth_param(1)?.into_int_value();
-    //     let z = function.get_nth_param(2)?.into_int_value();
+fn cmd_disasm(args: &Args) -> Result<(), Box<dyn Error>> {
+    let disasm = EvmDisassembly::new_from_bytes(&args.code);
+    match args.format {
+        Some(style) => print!("{}", disasm.format(style)),
+        None => println!("{}", serde_json::to_string_pretty(&disasm.to_json())?),
+    }
+    Ok(())
+}
 
-    //     let sum = self.builder.build_int_add(x, y, "sum");
-    //     let sum = self.builder.build_int_add(sum, z, "sum");
+fn cmd_cfg(args: &Args, code: &IndexedEvmCode) -> Result<(), Box<dyn Error>> {
+    let cfg = code.cfg();
+    if args.dot {
+        print!("{}", cfg.to_dot(code));
+        return Ok(());
+    }
 
-    //     self.builder.build_return(Some(&sum));
+    let reachable = cfg.reachable();
+    let blocks: Vec<Value> = cfg.blocks.iter().enumerate().map(|(b, block)| json!({
+        "block": b,
+        "start": block.offsets.start,
+        "end": block.offsets.end,
+        "exit": format!("{:?}", block.exit),
+        "successors": block.successors,
+        "predecessors": block.predecessors,
+        "reachable": reachable[b],
+    })).collect();
+    println!("{}", serde_json::to_string_pretty(&blocks)?);
+    Ok(())
+}
 
-    //     unsafe { self.execution_engine.get_function("sum").ok() }
-    // }
+fn timings_json(times: &[Duration]) -> Value {
+    let total: Duration = times.iter().sum();
+    json!({
+        "meanNs": total.as_nanos() / times.len().max(1) as u128,
+        "minNs": times.iter().min().map(|t| t.as_nanos()),
+        "maxNs": times.iter().max().map(|t| t.as_nanos()),
+    })
+}
 
-    // // // BenchmarkDB is dummy state that implements Database trait.
-    // // let mut evm = revm::new();
-    // // evm.database(BenchmarkDB(contract_data));
+fn cmd_bench(args: &Args, code: &IndexedEvmCode) -> Result<Value, Box<dyn Error>> {
+    let mut times = Vec::with_capacity(args.iterations);
+    match args.backend {
+        Backend::Interpreter => {
+            for _ in 0..args.iterations {
+                let state = pre_state(args)?;
+                let t = Instant::now();
+                run_interpreter(args, code, state);
+                times.push(t.elapsed());
+            }
+            Ok(json!({ "backend": "interp", "iterations": args.iterations, "execution": timings_json(&times) }))
+        },
+        Backend::Jit => {
+            let context = Context::create();
+            let engine = JitEvmEngine::new_from_context(&context)?;
+            let t = Instant::now();
+            let fn_contract = engine.jit_compile_contract(code, None, None)?;
+            let compile_time = t.elapsed();
+
+            let mut holder = Box::new(JitEvmExecutionContextHolder::new_from_empty());
+            for _ in 0..args.iterations {
+                holder.state = pre_state(args)?;
+                let t = Instant::now();
+                run_jit(args, &mut holder, &fn_contract);
+                times.push(t.elapsed());
+            }
+            Ok(json!({
+                "backend": "jit",
+                "iterations": args.iterations,
+                "compileNs": compile_time.as_nanos(),
+                "execution": timings_json(&times),
+            }))
+        },
+    }
+}
 
-    // // // execution globals block hash/gas_limit/coinbase/timestamp..
-    // // evm.env.tx.caller = H160::from_str("0x1000000000000000000000000000000000000000").unwrap();
-    // // evm.env.tx.transact_to =
-    // //     TransactTo::Call(H160::from_str("0x0000000000000000000000000000000000000000").unwrap());
-    // // evm.env.tx.data = Bytes::from(hex::decode("30627b7c").unwrap());
 
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = parse_args()?;
+    if args.command == "disasm" {
+        // also lists code the parser rejects (e.g., a PUSH cut off by the end)
+        return cmd_disasm(&args);
+    }
+    let code = EvmCode::new_from_bytes(&args.code, EvmOpParserMode::Lax)?.optimize(&args.passes).index();
+
+    match args.command.as_str() {
+        "run" => println!("{}", serde_json::to_string_pretty(&cmd_run(&args, &code)?)?),
+        "compile" => cmd_compile(&args, &code)?,
+        "cfg" => cmd_cfg(&args, &code)?,
+        "bench" => println!("{}", serde_json::to_string_pretty(&cmd_bench(&args, &code)?)?),
+        _ => return Err(USAGE.into()),
+    }
     Ok(())
 }
@@ -2,6 +2,8 @@ use primitive_types::U256;
 use revm::instructions::{arithmetic, bitwise};
 use std::ops::{BitAnd, BitOr, BitXor};
 
+pub mod native;
+
 macro_rules! op1_u256_fn {
     ($fname:ident, $fn:expr) => {
         #[allow(non_snake_case)]
@@ -0,0 +1,240 @@
+#![allow(non_snake_case)]
+
+// Arithmetic core working directly on the (little-endian) 64-bit limbs of U256.
+// Same signatures and semantics as the wrappers in `operations`, so the interpreter
+// can switch between both freely.
+
+use primitive_types::U256;
+
+#[cfg(test)]
+mod test;
+
+
+// operations not (yet) worth a native implementation
+pub use super::{Sdiv, Smod, Addmod, Mulmod, Signextend, Byte, Sar};
+
+
+#[inline(always)]
+fn limbs_lt(a: &[u64; 4], b: &[u64; 4]) -> bool {
+    // borrow out of a - b, no data-dependent branches
+    let mut borrow = false;
+    for i in 0..4 {
+        let (d, b1) = a[i].overflowing_sub(b[i]);
+        let (_, b2) = d.overflowing_sub(borrow as u64);
+        borrow = b1 | b2;
+    }
+    borrow
+}
+
+#[inline(always)]
+fn bool_to_u256(b: bool) -> U256 {
+    U256([b as u64, 0, 0, 0])
+}
+
+#[inline(always)]
+fn fits_u64(a: &U256) -> bool {
+    (a.0[1] | a.0[2] | a.0[3]) == 0
+}
+
+#[inline(always)]
+fn div_mod_u64(a: U256, b: u64) -> (U256, u64) {
+    // schoolbook long division by a single limb
+    let mut q = [0u64; 4];
+    let mut r = 0u128;
+    for i in (0..4).rev() {
+        let cur = (r << 64) | a.0[i] as u128;
+        q[i] = (cur / b as u128) as u64;
+        r = cur % b as u128;
+    }
+    (U256(q), r as u64)
+}
+
+
+#[inline(always)]
+pub fn Add(a: U256, b: U256) -> U256 {
+    let mut r = [0u64; 4];
+    let mut carry = false;
+    for i in 0..4 {
+        let (s, c1) = a.0[i].overflowing_add(b.0[i]);
+        let (s, c2) = s.overflowing_add(carry as u64);
+        r[i] = s;
+        carry = c1 | c2;
+    }
+    U256(r)
+}
+
+#[inline(always)]
+pub fn Sub(a: U256, b: U256) -> U256 {
+    let mut r = [0u64; 4];
+    let mut borrow = false;
+    for i in 0..4 {
+        let (d, b1) = a.0[i].overflowing_sub(b.0[i]);
+        let (d, b2) = d.overflowing_sub(borrow as u64);
+        r[i] = d;
+        borrow = b1 | b2;
+    }
+    U256(r)
+}
+
+#[inline(always)]
+pub fn Mul(a: U256, b: U256) -> U256 {
+    // schoolbook, only the limbs of the lower 256 bits are computed
+    let mut r = [0u64; 4];
+    for i in 0..4 {
+        let mut carry = 0u64;
+        for j in 0..(4 - i) {
+            let t = (a.0[i] as u128) * (b.0[j] as u128) + r[i + j] as u128 + carry as u128;
+            r[i + j] = t as u64;
+            carry = (t >> 64) as u64;
+        }
+    }
+    U256(r)
+}
+
+#[inline(always)]
+pub fn Div(a: U256, b: U256) -> U256 {
+    if fits_u64(&b) {
+        match b.0[0] {
+            0 => U256::zero(),
+            1 => a,
+            b if fits_u64(&a) => U256([a.0[0] / b, 0, 0, 0]),
+            b => div_mod_u64(a, b).0,
+        }
+    } else if limbs_lt(&a.0, &b.0) {
+        U256::zero()
+    } else {
+        a / b
+    }
+}
+
+#[inline(always)]
+pub fn Mod(a: U256, b: U256) -> U256 {
+    if fits_u64(&b) {
+        match b.0[0] {
+            0 | 1 => U256::zero(),
+            b if fits_u64(&a) => U256([a.0[0] % b, 0, 0, 0]),
+            b => U256([div_mod_u64(a, b).1, 0, 0, 0]),
+        }
+    } else if limbs_lt(&a.0, &b.0) {
+        a
+    } else {
+        a % b
+    }
+}
+
+#[inline(always)]
+pub fn Exp(a: U256, b: U256) -> U256 {
+    if b.is_zero() {
+        return U256::one();
+    }
+    if fits_u64(&a) {
+        match a.0[0] {
+            0 | 1 => return a,
+            2 => return Shl(b, U256::one()),
+            _ => {},
+        }
+    }
+
+    // square-and-multiply over the significant bits of the exponent
+    let mut result = U256::one();
+    let mut base = a;
+    for i in 0..b.bits() {
+        if b.bit(i) {
+            result = Mul(result, base);
+        }
+        base = Mul(base, base);
+    }
+    result
+}
+
+#[inline(always)]
+pub fn Lt(a: U256, b: U256) -> U256 {
+    bool_to_u256(limbs_lt(&a.0, &b.0))
+}
+
+#[inline(always)]
+pub fn Gt(a: U256, b: U256) -> U256 {
+    bool_to_u256(limbs_lt(&b.0, &a.0))
+}
+
+#[inline(always)]
+pub fn Slt(a: U256, b: U256) -> U256 {
+    // flipping the sign bits maps signed order onto unsigned order
+    let mut a = a;
+    let mut b = b;
+    a.0[3] ^= 1 << 63;
+    b.0[3] ^= 1 << 63;
+    bool_to_u256(limbs_lt(&a.0, &b.0))
+}
+
+#[inline(always)]
+pub fn Sgt(a: U256, b: U256) -> U256 {
+    Slt(b, a)
+}
+
+#[inline(always)]
+pub fn Eq(a: U256, b: U256) -> U256 {
+    let d = (a.0[0] ^ b.0[0]) | (a.0[1] ^ b.0[1]) | (a.0[2] ^ b.0[2]) | (a.0[3] ^ b.0[3]);
+    bool_to_u256(d == 0)
+}
+
+#[inline(always)]
+pub fn Iszero(a: U256) -> U256 {
+    bool_to_u256((a.0[0] | a.0[1] | a.0[2] | a.0[3]) == 0)
+}
+
+#[inline(always)]
+pub fn And(a: U256, b: U256) -> U256 {
+    U256([a.0[0] & b.0[0], a.0[1] & b.0[1], a.0[2] & b.0[2], a.0[3] & b.0[3]])
+}
+
+#[inline(always)]
+pub fn Or(a: U256, b: U256) -> U256 {
+    U256([a.0[0] | b.0[0], a.0[1] | b.0[1], a.0[2] | b.0[2], a.0[3] | b.0[3]])
+}
+
+#[inline(always)]
+pub fn Xor(a: U256, b: U256) -> U256 {
+    U256([a.0[0] ^ b.0[0], a.0[1] ^ b.0[1], a.0[2] ^ b.0[2], a.0[3] ^ b.0[3]])
+}
+
+#[inline(always)]
+pub fn Not(a: U256) -> U256 {
+    U256([!a.0[0], !a.0[1], !a.0[2], !a.0[3]])
+}
+
+#[inline(always)]
+pub fn Shl(a: U256, b: U256) -> U256 {
+    // shift b left by a
+    if !fits_u64(&a) || a.0[0] >= 256 {
+        return U256::zero();
+    }
+    let limbs = (a.0[0] / 64) as usize;
+    let bits = (a.0[0] % 64) as u32;
+    let mut r = [0u64; 4];
+    for i in limbs..4 {
+        r[i] = b.0[i - limbs] << bits;
+        if bits > 0 && i > limbs {
+            r[i] |= b.0[i - limbs - 1] >> (64 - bits);
+        }
+    }
+    U256(r)
+}
+
+#[inline(always)]
+pub fn Shr(a: U256, b: U256) -> U256 {
+    // shift b right by a
+    if !fits_u64(&a) || a.0[0] >= 256 {
+        return U256::zero();
+    }
+    let limbs = (a.0[0] / 64) as usize;
+    let bits = (a.0[0] % 64) as u32;
+    let mut r = [0u64; 4];
+    for i in 0..(4 - limbs) {
+        r[i] = b.0[i + limbs] >> bits;
+        if bits > 0 && i + limbs + 1 < 4 {
+            r[i] |= b.0[i + limbs + 1] << (64 - bits);
+        }
+    }
+    U256(r)
+}
@@ -0,0 +1,79 @@
+use paste::paste;
+use rand::Rng;
+use primitive_types::U256;
+
+
+fn random_operand() -> U256 {
+    // mix of full-width, single-limb and tiny operands, to hit the fast paths
+    let mut rng = rand::thread_rng();
+    match rng.gen_range(0..4) {
+        0 => U256::from_big_endian(&rng.gen::<[u8; 32]>()),
+        1 => U256::from(rng.gen::<u64>()),
+        2 => U256::from(rng.gen_range(0u64..300)),
+        _ => U256::MAX - rng.gen_range(0u64..3),
+    }
+}
+
+macro_rules! test_native_op1 {
+    ($fname:ident) => {
+        paste! {
+            #[test]
+            fn [<operations_native_equivalence_ $fname:lower>]() {
+                use crate::operations;
+
+                for a in [U256::zero(), U256::one(), U256::MAX] {
+                    assert_eq!(operations::native::$fname(a), operations::$fname(a), "a = {:?}", a);
+                }
+
+                for _i in 0..10000 {
+                    let a = random_operand();
+                    assert_eq!(operations::native::$fname(a), operations::$fname(a), "a = {:?}", a);
+                }
+            }
+        }
+    };
+}
+
+macro_rules! test_native_op2 {
+    ($fname:ident) => {
+        paste! {
+            #[test]
+            fn [<operations_native_equivalence_ $fname:lower>]() {
+                use crate::operations;
+
+                let special = [U256::zero(), U256::one(), U256::from(2), U256::one() << 255, U256::MAX];
+                for a in special {
+                    for b in special {
+                        assert_eq!(operations::native::$fname(a, b), operations::$fname(a, b), "a = {:?} / b = {:?}", a, b);
+                    }
+                }
+
+                for _i in 0..10000 {
+                    let a = random_operand();
+                    let b = random_operand();
+                    assert_eq!(operations::native::$fname(a, b), operations::$fname(a, b), "a = {:?} / b = {:?}", a, b);
+                }
+            }
+        }
+    };
+}
+
+
+test_native_op2!(Add);
+test_native_op2!(Sub);
+test_native_op2!(Mul);
+test_native_op2!(Div);
+test_native_op2!(Mod);
+test_native_op2!(Exp);
+test_native_op2!(Lt);
+test_native_op2!(Gt);
+test_native_op2!(Slt);
+test_native_op2!(Sgt);
+test_native_op2!(Eq);
+test_native_op1!(Iszero);
+test_native_op2!(And);
+test_native_op2!(Or);
+test_native_op2!(Xor);
+test_native_op1!(Not);
+test_native_op2!(Shl);
+test_native_op2!(Shr);
@@ -0,0 +1,81 @@
+use thiserror::Error;
+use primitive_types::H160;
+use revm_precompile::{Precompiles, PrecompileSpecId, PrecompileError, Address, Bytes};
+use revm_precompile::primitives::Env;
+use crate::fork::EvmFork;
+
+#[cfg(test)]
+mod test;
+
+
+// Precompiled contracts live at the lowest addresses (0x01 = ecrecover, ..., 0x0a = point
+// evaluation). A call has to look up its target here before it is dispatched to the
+// interpreter or the JIT, but neither implements CALL yet, so nothing consults the registry
+// outside its tests. Gas pricing and outputs come from the revm reference implementation.
+
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EvmPrecompileError {
+    #[error("precompile error: out of gas")]
+    OutOfGas,
+    #[error("precompile error: {0}")]
+    Failed(String),
+}
+
+impl From<PrecompileError> for EvmPrecompileError {
+    fn from(e: PrecompileError) -> Self {
+        match e {
+            PrecompileError::OutOfGas => Self::OutOfGas,
+            e => Self::Failed(format!("{:?}", e)),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvmPrecompileOutput {
+    pub gas_used: u64,
+    pub output: Vec<u8>,
+}
+
+
+pub struct EvmPrecompiles {
+    pub fork: EvmFork,
+    precompiles: &'static Precompiles,
+    env: Env,   // only consulted for the KZG trusted setup (point evaluation)
+}
+
+impl EvmPrecompiles {
+    pub fn new(fork: EvmFork) -> Self {
+        use EvmFork::*;
+
+        let spec = match fork {
+            Frontier | Homestead => PrecompileSpecId::HOMESTEAD,
+            Byzantium | Constantinople | Petersburg => PrecompileSpecId::BYZANTIUM,
+            Istanbul => PrecompileSpecId::ISTANBUL,
+            Berlin | London | Merge | Shanghai => PrecompileSpecId::BERLIN,
+            Cancun => PrecompileSpecId::CANCUN,
+        };
+
+        Self { fork, precompiles: Precompiles::new(spec), env: Env::default() }
+    }
+
+    pub fn contains(&self, address: &H160) -> bool {
+        self.precompiles.contains(&Address::new(address.0))
+    }
+
+    pub fn addresses(&self) -> Vec<H160> {
+        let mut addresses: Vec<H160> = self.precompiles.addresses().map(|a| H160(a.0.0)).collect();
+        addresses.sort();
+        addresses
+    }
+
+    // None if there is no precompile at the address (i.e., regular code has to run)
+    pub fn call(&self, address: &H160, input: &[u8], gas_limit: u64) -> Option<Result<EvmPrecompileOutput, EvmPrecompileError>> {
+        let mut precompile = self.precompiles.get(&Address::new(address.0))?.clone();
+        let ret = precompile.call(&Bytes::copy_from_slice(input), gas_limit, &self.env);
+        Some(ret
+            .map(|(gas_used, output)| EvmPrecompileOutput { gas_used, output: output.to_vec() })
+            .map_err(EvmPrecompileError::from))
+    }
+}
@@ -0,0 +1,99 @@
+use paste::paste;
+use primitive_types::H160;
+use crate::fork::EvmFork;
+use crate::precompiles::{EvmPrecompiles, EvmPrecompileError};
+
+
+// local test vectors (testdata/precompiles-local, not go-ethereum's) in the go-ethereum
+// format, successful calls carry "Expected" output and "Gas", failing calls carry
+// "ExpectedError" (revm's error name)
+
+fn run_vectors(fork: EvmFork, address: u64, vectors: &str, failing: bool) {
+    let precompiles = EvmPrecompiles::new(fork);
+    let address = H160::from_low_u64_be(address);
+    let vectors: serde_json::Value = serde_json::from_str(vectors).unwrap();
+
+    for v in vectors.as_array().unwrap() {
+        let name = v["Name"].as_str().unwrap();
+        let input = hex::decode(v["Input"].as_str().unwrap()).unwrap();
+
+        if failing {
+            let ret = precompiles.call(&address, &input, u64::MAX).unwrap();
+            assert_eq!(ret, Err(EvmPrecompileError::Failed(v["ExpectedError"].as_str().unwrap().to_string())), "{}", name);
+            continue;
+        }
+
+        let expected = hex::decode(v["Expected"].as_str().unwrap()).unwrap();
+        let gas = v["Gas"].as_u64().unwrap();
+
+        let ret = precompiles.call(&address, &input, gas).unwrap().unwrap();
+        assert_eq!(ret.output, expected, "{}", name);
+        assert_eq!(ret.gas_used, gas, "{}", name);
+
+        if gas > 0 {
+            let ret = precompiles.call(&address, &input, gas - 1).unwrap();
+            assert_eq!(ret, Err(EvmPrecompileError::OutOfGas), "{}", name);
+        }
+    }
+}
+
+// one test per file, named after it
+macro_rules! test_vectors {
+    ($fname:ident, $fork:expr, $address:expr) => {
+        paste! {
+            #[test]
+            fn [<precompile_ $fname>]() {
+                run_vectors($fork, $address, include_str!(concat!("../../testdata/precompiles-local/", stringify!($fname), ".json")), false);
+            }
+        }
+    };
+    ($fname:ident, $fork:expr, $address:expr, failing) => {
+        paste! {
+            #[test]
+            fn [<precompile_ $fname>]() {
+                run_vectors($fork, $address, include_str!(concat!("../../testdata/precompiles-local/", stringify!($fname), ".json")), true);
+            }
+        }
+    };
+}
+
+
+test_vectors!(ecrecover, EvmFork::LATEST, 0x01);
+test_vectors!(sha256, EvmFork::LATEST, 0x02);
+test_vectors!(ripemd160, EvmFork::LATEST, 0x03);
+test_vectors!(identity, EvmFork::LATEST, 0x04);
+test_vectors!(modexp_byzantium, EvmFork::Byzantium, 0x05);
+test_vectors!(modexp_berlin, EvmFork::Berlin, 0x05);
+test_vectors!(bn256add_byzantium, EvmFork::Byzantium, 0x06);
+test_vectors!(bn256add_byzantium_fail, EvmFork::Byzantium, 0x06, failing);
+test_vectors!(bn256add_istanbul, EvmFork::Istanbul, 0x06);
+test_vectors!(bn256add_fail, EvmFork::Istanbul, 0x06, failing);
+test_vectors!(bn256scalarmul_byzantium, EvmFork::Byzantium, 0x07);
+test_vectors!(bn256scalarmul_byzantium_fail, EvmFork::Byzantium, 0x07, failing);
+test_vectors!(bn256scalarmul_istanbul, EvmFork::Istanbul, 0x07);
+test_vectors!(bn256scalarmul_fail, EvmFork::Istanbul, 0x07, failing);
+test_vectors!(bn256pairing_byzantium, EvmFork::Byzantium, 0x08);
+test_vectors!(bn256pairing_byzantium_fail, EvmFork::Byzantium, 0x08, failing);
+test_vectors!(bn256pairing_istanbul, EvmFork::Istanbul, 0x08);
+test_vectors!(bn256pairing_fail, EvmFork::Istanbul, 0x08, failing);
+test_vectors!(blake2f, EvmFork::Istanbul, 0x09);
+test_vectors!(blake2f_fail, EvmFork::Istanbul, 0x09, failing);
+test_vectors!(point_evaluation, EvmFork::Cancun, 0x0a);
+test_vectors!(point_evaluation_fail, EvmFork::Cancun, 0x0a, failing);
+
+
+#[test]
+fn precompile_addresses_per_fork() {
+    let addresses = |fork| EvmPrecompiles::new(fork).addresses().iter().map(|a| a.to_low_u64_be()).collect::<Vec<_>>();
+
+    assert_eq!(addresses(EvmFork::Frontier), (1..=4).collect::<Vec<_>>());
+    assert_eq!(addresses(EvmFork::Byzantium), (1..=8).collect::<Vec<_>>());
+    assert_eq!(addresses(EvmFork::Istanbul), (1..=9).collect::<Vec<_>>());
+    assert_eq!(addresses(EvmFork::London), (1..=9).collect::<Vec<_>>());
+    assert_eq!(addresses(EvmFork::Cancun), (1..=10).collect::<Vec<_>>());
+
+    let precompiles = EvmPrecompiles::new(EvmFork::Istanbul);
+    assert!(precompiles.contains(&H160::from_low_u64_be(0x09)));
+    assert!(!precompiles.contains(&H160::from_low_u64_be(0x0a)));
+    assert!(precompiles.call(&H160::from_low_u64_be(0x0a), &[], u64::MAX).is_none());
+}
@@ -0,0 +1,349 @@
+use thiserror::Error;
+use primitive_types::{H160, U256};
+use std::collections::{HashMap, HashSet};
+
+#[cfg(test)]
+mod test;
+
+
+// State that outlives a single execution frame: accounts with their balances and storage,
+// EIP-1153 transient storage, EIP-2929 access sets and logs. Every mutation is recorded in
+// a journal, so that a frame can take a checkpoint at entry and, if it fails, revert
+// everything it (and the frames it called) did. Committing a checkpoint keeps the changes,
+// but they can still be reverted together with an enclosing checkpoint.
+
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EvmStateError {
+    #[error("state error: insufficient balance")]
+    InsufficientBalance,
+    #[error("state error: checkpoint out of order")]
+    CheckpointOutOfOrder,
+    #[error("state error: storage backend: {0}")]
+    StorageBackend(String),
+}
+
+
+// storage access as seen by the interpreter and the JIT, both backends go through this
+// (slots that were never written read as zero, persistent storage may live in a backend
+// that can fail, transient storage is always in memory)
+pub trait EvmHost {
+    fn sload(&mut self, key: U256) -> Result<U256, EvmStateError>;
+    fn sstore(&mut self, key: U256, value: U256) -> Result<(), EvmStateError>;
+    fn tload(&mut self, key: U256) -> U256;
+    fn tstore(&mut self, key: U256, value: U256);
+}
+
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EvmAccount {
+    pub balance: U256,
+    pub nonce: u64,
+    pub storage: HashMap<U256, U256>,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvmLog {
+    pub address: H160,
+    pub topics: Vec<U256>,
+    pub data: Vec<u8>,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvmJournalEntry {
+    AccountCreated { address: H160 },
+    AccountWarmed { address: H160 },
+    BalanceChanged { address: H160, previous: U256 },
+    StorageChanged { address: H160, key: U256, previous: Option<U256> },
+    StorageWarmed { address: H160, key: U256 },
+    TransientStorageChanged { address: H160, key: U256, previous: U256 },
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvmCheckpoint {
+    depth: usize,
+    journal_len: usize,
+    logs_len: usize,
+}
+
+
+// a storage write as reported to an inspector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvmStorageDiff {
+    pub address: H160,
+    pub key: U256,
+    pub previous: U256,
+    pub value: U256,
+}
+
+
+// everything the gas schedule needs to price an SLOAD
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvmSload {
+    pub value: Option<U256>,
+    pub is_cold: bool,
+}
+
+// everything the gas schedule needs to price an SSTORE (EIP-2200, EIP-2929)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvmSstore {
+    pub original: U256,   // value at the beginning of the transaction
+    pub current: U256,    // value before this write
+    pub new: U256,
+    pub is_cold: bool,
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct EvmJournaledState {
+    pub accounts: HashMap<H160, EvmAccount>,
+    transient_storage: HashMap<(H160, U256), U256>,   // EIP-1153, lives for one transaction
+    original_storage: HashMap<(H160, U256), U256>,    // first value seen in this transaction
+    warm_addresses: HashSet<H160>,
+    warm_slots: HashSet<(H160, U256)>,
+    logs: Vec<EvmLog>,
+    journal: Vec<EvmJournalEntry>,
+    depth: usize,
+}
+
+impl EvmJournaledState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn new_from_accounts(accounts: HashMap<H160, EvmAccount>) -> Self {
+        Self { accounts, ..Self::default() }
+    }
+
+    pub fn host(&mut self, address: H160) -> EvmJournaledHost<'_> {
+        EvmJournaledHost { state: self, address }
+    }
+
+
+    // CHECKPOINTS
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn checkpoint(&mut self) -> EvmCheckpoint {
+        self.depth += 1;
+        EvmCheckpoint {
+            depth: self.depth,
+            journal_len: self.journal.len(),
+            logs_len: self.logs.len(),
+        }
+    }
+
+    pub fn commit(&mut self, checkpoint: EvmCheckpoint) -> Result<(), EvmStateError> {
+        if checkpoint.depth != self.depth {
+            return Err(EvmStateError::CheckpointOutOfOrder);
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+
+    pub fn revert(&mut self, checkpoint: EvmCheckpoint) -> Result<(), EvmStateError> {
+        use EvmJournalEntry::*;
+
+        if checkpoint.depth != self.depth {
+            return Err(EvmStateError::CheckpointOutOfOrder);
+        }
+        self.depth -= 1;
+
+        while self.journal.len() > checkpoint.journal_len {
+            match self.journal.pop().unwrap() {
+                AccountCreated { address } => {
+                    self.accounts.remove(&address);
+                },
+                AccountWarmed { address } => {
+                    self.warm_addresses.remove(&address);
+                },
+                BalanceChanged { address, previous } => {
+                    self.accounts.get_mut(&address).unwrap().balance = previous;
+                },
+                StorageChanged { address, key, previous } => {
+                    let storage = &mut self.accounts.get_mut(&address).unwrap().storage;
+                    match previous {
+                        Some(previous) => { storage.insert(key, previous); },
+                        None => { storage.remove(&key); },
+                    }
+                },
+                StorageWarmed { address, key } => {
+                    self.warm_slots.remove(&(address, key));
+                },
+                TransientStorageChanged { address, key, previous } => {
+                    self.transient_storage.insert((address, key), previous);
+                },
+            }
+        }
+        self.logs.truncate(checkpoint.logs_len);
+
+        Ok(())
+    }
+
+    pub fn journal_len(&self) -> usize {
+        self.journal.len()
+    }
+
+    // storage writes since the journal had the given length (e.g., those of one instruction)
+    pub fn storage_diffs_since(&self, journal_len: usize) -> Vec<EvmStorageDiff> {
+        self.journal.get(journal_len..).unwrap_or(&[]).iter().filter_map(|e| match e {
+            EvmJournalEntry::StorageChanged { address, key, previous } => Some(EvmStorageDiff {
+                address: *address,
+                key: *key,
+                previous: previous.unwrap_or(U256::zero()),
+                value: self.storage_get(address, key).unwrap_or(U256::zero()),
+            }),
+            _ => None,
+        }).collect()
+    }
+
+    // drops everything that is scoped to a transaction and returns the logs it emitted
+    pub fn end_transaction(&mut self) -> Vec<EvmLog> {
+        self.transient_storage.clear();
+        self.original_storage.clear();
+        self.warm_addresses.clear();
+        self.warm_slots.clear();
+        self.journal.clear();
+        self.depth = 0;
+        std::mem::take(&mut self.logs)
+    }
+
+
+    // ACCOUNTS
+
+    fn account_mut(&mut self, address: H160) -> &mut EvmAccount {
+        if !self.accounts.contains_key(&address) {
+            self.journal.push(EvmJournalEntry::AccountCreated { address });
+        }
+        self.accounts.entry(address).or_default()
+    }
+
+    pub fn exists(&self, address: &H160) -> bool {
+        self.accounts.contains_key(address)
+    }
+
+    // false if the account exists already
+    pub fn create_account(&mut self, address: H160) -> bool {
+        if self.exists(&address) {
+            return false;
+        }
+        self.account_mut(address);
+        true
+    }
+
+    // returns true if the address was cold
+    pub fn warm_address(&mut self, address: H160) -> bool {
+        let is_cold = self.warm_addresses.insert(address);
+        if is_cold {
+            self.journal.push(EvmJournalEntry::AccountWarmed { address });
+        }
+        is_cold
+    }
+
+    pub fn balance(&self, address: &H160) -> U256 {
+        self.accounts.get(address).map(|a| a.balance).unwrap_or(U256::zero())
+    }
+
+    pub fn set_balance(&mut self, address: H160, balance: U256) {
+        let account = self.account_mut(address);
+        let previous = account.balance;
+        account.balance = balance;
+        self.journal.push(EvmJournalEntry::BalanceChanged { address, previous });
+    }
+
+    pub fn transfer(&mut self, from: H160, to: H160, value: U256) -> Result<(), EvmStateError> {
+        let from_balance = self.balance(&from);
+        if from_balance < value {
+            return Err(EvmStateError::InsufficientBalance);
+        }
+        self.set_balance(from, from_balance - value);
+        let to_balance = self.balance(&to);
+        self.set_balance(to, to_balance.overflowing_add(value).0);
+        Ok(())
+    }
+
+
+    // STORAGE
+
+    fn warm_slot(&mut self, address: H160, key: U256) -> bool {
+        let is_cold = self.warm_slots.insert((address, key));
+        if is_cold {
+            self.journal.push(EvmJournalEntry::StorageWarmed { address, key });
+        }
+        is_cold
+    }
+
+    fn storage_get(&self, address: &H160, key: &U256) -> Option<U256> {
+        self.accounts.get(address).and_then(|a| a.storage.get(key)).copied()
+    }
+
+    pub fn sload(&mut self, address: H160, key: U256) -> EvmSload {
+        let is_cold = self.warm_slot(address, key);
+        let value = self.storage_get(&address, &key);
+        self.original_storage.entry((address, key)).or_insert(value.unwrap_or(U256::zero()));
+        EvmSload { value, is_cold }
+    }
+
+    pub fn sstore(&mut self, address: H160, key: U256, value: U256) -> EvmSstore {
+        let is_cold = self.warm_slot(address, key);
+        let previous = self.storage_get(&address, &key);
+        let current = previous.unwrap_or(U256::zero());
+        let original = *self.original_storage.entry((address, key)).or_insert(current);
+
+        self.account_mut(address).storage.insert(key, value);
+        self.journal.push(EvmJournalEntry::StorageChanged { address, key, previous });
+
+        EvmSstore { original, current, new: value, is_cold }
+    }
+
+    pub fn tload(&self, address: H160, key: U256) -> U256 {
+        self.transient_storage.get(&(address, key)).copied().unwrap_or(U256::zero())
+    }
+
+    pub fn tstore(&mut self, address: H160, key: U256, value: U256) {
+        let previous = self.transient_storage.insert((address, key), value).unwrap_or(U256::zero());
+        self.journal.push(EvmJournalEntry::TransientStorageChanged { address, key, previous });
+    }
+
+
+    // LOGS
+
+    pub fn log(&mut self, address: H160, topics: Vec<U256>, data: Vec<u8>) {
+        self.logs.push(EvmLog { address, topics, data });
+    }
+
+    pub fn logs(&self) -> &[EvmLog] {
+        &self.logs
+    }
+}
+
+
+// the journaled state as seen from the code running at one address
+pub struct EvmJournaledHost<'a> {
+    pub state: &'a mut EvmJournaledState,
+    pub address: H160,
+}
+
+impl EvmHost for EvmJournaledHost<'_> {
+    fn sload(&mut self, key: U256) -> Result<U256, EvmStateError> {
+        Ok(self.state.sload(self.address, key).value.unwrap_or(U256::zero()))
+    }
+
+    fn sstore(&mut self, key: U256, value: U256) -> Result<(), EvmStateError> {
+        self.state.sstore(self.address, key, value);
+        Ok(())
+    }
+
+    fn tload(&mut self, key: U256) -> U256 {
+        self.state.tload(self.address, key)
+    }
+
+    fn tstore(&mut self, key: U256, value: U256) {
+        self.state.tstore(self.address, key, value);
+    }
+}
@@ -0,0 +1,116 @@
+use primitive_types::{H160, U256};
+use crate::state::{EvmJournaledState, EvmHost, EvmSload, EvmSstore, EvmStateError};
+
+
+fn addr(a: u64) -> H160 {
+    H160::from_low_u64_be(a)
+}
+
+fn val(v: u64) -> U256 {
+    U256::zero() + v
+}
+
+
+#[test]
+fn revert_undoes_storage_balances_accounts_and_logs() {
+    let mut state = EvmJournaledState::new();
+    state.set_balance(addr(1), val(100));
+    state.sstore(addr(1), val(1), val(11));
+    state.end_transaction();
+
+    let cp = state.checkpoint();
+    state.sstore(addr(1), val(1), val(12));
+    state.sstore(addr(1), val(2), val(22));
+    state.transfer(addr(1), addr(2), val(30)).unwrap();
+    state.tstore(addr(1), val(1), val(5));
+    state.log(addr(1), vec![val(7)], vec![1, 2, 3]);
+    assert!(state.exists(&addr(2)));
+    assert_eq!(state.logs().len(), 1);
+    state.revert(cp).unwrap();
+
+    assert_eq!(state.accounts[&addr(1)].storage.get(&val(1)), Some(&val(11)));
+    assert_eq!(state.accounts[&addr(1)].storage.get(&val(2)), None);
+    assert_eq!(state.balance(&addr(1)), val(100));
+    assert!(!state.exists(&addr(2)));
+    assert_eq!(state.tload(addr(1), val(1)), U256::zero());
+    assert!(state.logs().is_empty());
+    assert_eq!(state.depth(), 0);
+}
+
+#[test]
+fn nested_checkpoints() {
+    let mut state = EvmJournaledState::new();
+
+    let outer = state.checkpoint();
+    state.sstore(addr(1), val(1), val(1));
+
+    // committed inner frame survives ...
+    let inner = state.checkpoint();
+    state.sstore(addr(1), val(2), val(2));
+    state.commit(inner).unwrap();
+
+    // ... reverted inner frame does not
+    let inner = state.checkpoint();
+    state.sstore(addr(1), val(3), val(3));
+    assert_eq!(state.commit(outer), Err(EvmStateError::CheckpointOutOfOrder));
+    state.revert(inner).unwrap();
+
+    {
+        let mut host = state.host(addr(1));
+        assert_eq!(host.sload(val(1)), Ok(val(1)));
+        assert_eq!(host.sload(val(2)), Ok(val(2)));
+        assert_eq!(host.sload(val(3)), Ok(U256::zero()));
+    }
+
+    // reverting the outer frame also drops the committed inner frame
+    state.revert(outer).unwrap();
+    assert!(!state.exists(&addr(1)));
+}
+
+#[test]
+fn original_values_and_access_sets() {
+    let mut state = EvmJournaledState::new();
+    state.sstore(addr(1), val(1), val(10));
+    state.end_transaction();
+
+    assert_eq!(state.sload(addr(1), val(1)), EvmSload { value: Some(val(10)), is_cold: true });
+    assert_eq!(state.sload(addr(1), val(1)), EvmSload { value: Some(val(10)), is_cold: false });
+    assert_eq!(state.sload(addr(1), val(2)), EvmSload { value: None, is_cold: true });
+
+    assert_eq!(state.sstore(addr(1), val(1), val(20)), EvmSstore { original: val(10), current: val(10), new: val(20), is_cold: false });
+    assert_eq!(state.sstore(addr(1), val(1), val(30)), EvmSstore { original: val(10), current: val(20), new: val(30), is_cold: false });
+
+    // slots warmed inside a reverted frame are cold again, original values stay
+    let cp = state.checkpoint();
+    assert_eq!(state.sstore(addr(1), val(3), val(1)), EvmSstore { original: U256::zero(), current: U256::zero(), new: val(1), is_cold: true });
+    assert!(state.warm_address(addr(2)));
+    assert!(!state.warm_address(addr(2)));
+    state.revert(cp).unwrap();
+    assert!(state.sload(addr(1), val(3)).is_cold);
+    assert!(state.warm_address(addr(2)));
+
+    // a new transaction starts cold, with the committed values as originals
+    state.end_transaction();
+    assert_eq!(state.sstore(addr(1), val(1), val(40)), EvmSstore { original: val(30), current: val(30), new: val(40), is_cold: true });
+}
+
+#[test]
+fn transfers_and_transient_storage() {
+    let mut state = EvmJournaledState::new();
+    state.set_balance(addr(1), val(10));
+    assert_eq!(state.transfer(addr(1), addr(2), val(11)), Err(EvmStateError::InsufficientBalance));
+    state.transfer(addr(1), addr(2), val(10)).unwrap();
+    assert_eq!(state.balance(&addr(1)), U256::zero());
+    assert_eq!(state.balance(&addr(2)), val(10));
+    assert!(!state.create_account(addr(2)));
+    assert!(state.create_account(addr(3)));
+
+    // transient storage is per address and cleared with the transaction
+    state.tstore(addr(1), val(1), val(5));
+    assert_eq!(state.host(addr(1)).tload(val(1)), val(5));
+    assert_eq!(state.host(addr(2)).tload(val(1)), U256::zero());
+    state.log(addr(1), vec![], vec![]);
+    assert_eq!(state.end_transaction().len(), 1);
+    assert_eq!(state.tload(addr(1), val(1)), U256::zero());
+    assert!(state.logs().is_empty());
+}
@@ -0,0 +1,378 @@
+use thiserror::Error;
+use primitive_types::{H160, H256, U256};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+use crate::code::{EvmCode, EvmOpParserMode};
+use crate::fork::EvmFork;
+use crate::state::{EvmAccount, EvmJournaledState};
+
+pub mod trie;
+
+#[cfg(test)]
+mod test;
+
+
+// Runs fixtures in the GeneralStateTests format of ethereum/tests: the pre-state is set up in
+// an EvmJournaledState, the transaction is executed by the interpreter or the JIT, and the
+// resulting state root and logs hash are compared with those expected for each fork.
+//
+// Limitations: gas is not metered, so the sender is not charged and the coinbase is not
+// paid. Fixtures with a nonzero gas price are skipped, since their state roots depend on
+// the fee. Contract creation and transactions without an explicit "sender" are skipped,
+// as are fixtures using instructions that a backend does not implement yet.
+
+
+#[derive(Error, Debug)]
+pub enum EvmStateTestError {
+    #[error("state test error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("state test error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("state test error: malformed fixture: {0}")]
+    Malformed(String),
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EvmStateTestBackend {
+    Interpreter,
+    Jit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvmStateTestOutcome {
+    Pass,
+    Fail(String),
+    Skip(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct EvmStateTestResult {
+    pub name: String,
+    pub fork: String,
+    pub index: usize,   // of the expectation for the fork
+    pub backend: EvmStateTestBackend,
+    pub outcome: EvmStateTestOutcome,
+}
+
+
+// FIXTURE PARSING
+
+fn malformed(what: &str) -> EvmStateTestError {
+    EvmStateTestError::Malformed(what.to_string())
+}
+
+fn parse_bytes(v: &Value) -> Result<Vec<u8>, EvmStateTestError> {
+    let s = v.as_str().ok_or_else(|| malformed("expected a hex string"))?;
+    hex::decode(s.trim_start_matches("0x")).map_err(|_| malformed(s))
+}
+
+fn parse_u256(v: &Value) -> Result<U256, EvmStateTestError> {
+    let s = v.as_str().ok_or_else(|| malformed("expected a hex number"))?;
+    let digits = s.trim_start_matches("0x");
+    if digits.is_empty() {
+        return Ok(U256::zero());
+    }
+    U256::from_str_radix(digits, 16).map_err(|_| malformed(s))
+}
+
+fn parse_h160(v: &Value) -> Result<H160, EvmStateTestError> {
+    let b = parse_bytes(v)?;
+    if b.len() != 20 {
+        return Err(malformed("expected an address"));
+    }
+    Ok(H160::from_slice(&b))
+}
+
+fn parse_h256(v: &Value) -> Result<H256, EvmStateTestError> {
+    let b = parse_bytes(v)?;
+    if b.len() != 32 {
+        return Err(malformed("expected a hash"));
+    }
+    Ok(H256::from_slice(&b))
+}
+
+fn parse_index(v: &Value, what: &str) -> Result<usize, EvmStateTestError> {
+    v[what].as_u64().map(|i| i as usize).ok_or_else(|| malformed(what))
+}
+
+// accounts (without code) and the code of each account
+pub type EvmStateTestPre = (HashMap<H160, EvmAccount>, HashMap<H160, Vec<u8>>);
+
+pub fn parse_pre(pre: &Value) -> Result<EvmStateTestPre, EvmStateTestError> {
+    let mut accounts = HashMap::new();
+    let mut codes = HashMap::new();
+
+    for (address, account) in pre.as_object().ok_or_else(|| malformed("pre"))? {
+        let address = parse_h160(&Value::String(address.clone()))?;
+        let mut storage = HashMap::new();
+        for (k, v) in account["storage"].as_object().ok_or_else(|| malformed("storage"))? {
+            storage.insert(parse_u256(&Value::String(k.clone()))?, parse_u256(v)?);
+        }
+        accounts.insert(address, EvmAccount {
+            balance: parse_u256(&account["balance"])?,
+            nonce: parse_u256(&account["nonce"])?.low_u64(),
+            storage,
+        });
+        codes.insert(address, parse_bytes(&account["code"])?);
+    }
+
+    Ok((accounts, codes))
+}
+
+
+// EXECUTION
+
+struct EvmStateTestTransaction {
+    sender: H160,
+    to: H160,
+    data: Vec<u8>,
+    value: U256,
+}
+
+// whether the code ran successfully, Err if the backend cannot run it
+fn run_code(backend: EvmStateTestBackend, state: &mut EvmJournaledState, address: H160, code: &[u8], calldata: &[u8], callvalue: U256) -> Result<bool, String> {
+    use crate::interpreter::{EvmContext, EvmInnerContext, EvmOuterContext, EvmInterpreterError};
+    use crate::jit::{JitEvmEngine, JitEvmEngineError, JitEvmExecutionContext, JitEvmExecutionContextHolder, EVM_JIT_STATUS_OK};
+    use crate::constants::EVM_STACK_SIZE;
+    use inkwell::context::Context;
+
+    let code = EvmCode::new_from_bytes(code, EvmOpParserMode::Lax).map_err(|e| e.to_string())?.index();
+
+    match backend {
+        EvmStateTestBackend::Interpreter => {
+            let mut ctx = EvmContext {
+                outer: EvmOuterContext {
+                    calldata: calldata.to_vec(),
+                    state: std::mem::take(state),
+                    address,
+                    callvalue,
+                },
+                inner: EvmInnerContext {
+                    code: &code,
+                    stack: [U256::zero(); EVM_STACK_SIZE],
+                    pc: 0,
+                    sp: 0,
+                    memory: vec![],
+                },
+            };
+
+            let ret = loop {
+                match ctx.tick() {
+                    Ok(true) => {},
+                    Ok(false) => break Ok(true),
+                    Err(EvmInterpreterError::UnknownInstruction(op)) => break Err(format!("unsupported instruction {:?}", op)),
+                    Err(_) => break Ok(false),
+                }
+            };
+            *state = ctx.outer.state;
+            ret
+        },
+        EvmStateTestBackend::Jit => {
+            let context = Context::create();
+            let engine = JitEvmEngine::new_from_context(&context).map_err(|e| e.to_string())?;
+            let fn_contract = match engine.jit_compile_contract(&code, None, None) {
+                Ok(f) => f,
+                Err(JitEvmEngineError::UnsupportedOperation(op)) => return Err(format!("unsupported instruction {:?}", op)),
+                Err(e) => return Err(e.to_string()),
+            };
+
+            let mut holder = JitEvmExecutionContextHolder::new_from_empty();
+            holder.state = std::mem::take(state);
+            holder.address = address;
+            let mut ctx = JitEvmExecutionContext::new_from_holder(&mut holder);
+            let ret = unsafe { fn_contract.call(&mut ctx as *mut _ as usize) };
+            *state = std::mem::take(&mut holder.state);
+            Ok(ret == EVM_JIT_STATUS_OK)
+        },
+    }
+}
+
+// post-state root and logs hash, Err (with the reason) if the transaction cannot be run
+fn execute(
+    backend: EvmStateTestBackend,
+    fork: EvmFork,
+    accounts: &HashMap<H160, EvmAccount>,
+    codes: &HashMap<H160, Vec<u8>>,
+    tx: &EvmStateTestTransaction) -> Result<(H256, H256), String>
+{
+    use trie::{keccak, state_root, logs_hash};
+
+    let mut state = EvmJournaledState::new_from_accounts(accounts.clone());
+    state.warm_address(tx.sender);
+    state.warm_address(tx.to);
+
+    // the nonce is incremented even if execution fails
+    match state.accounts.get_mut(&tx.sender) {
+        Some(sender) => sender.nonce += 1,
+        None => return Err("sender does not exist".to_string()),
+    }
+
+    let checkpoint = state.checkpoint();
+    if state.transfer(tx.sender, tx.to, tx.value).is_err() {
+        return Err("insufficient balance for value transfer".to_string());
+    }
+    let success = match codes.get(&tx.to) {
+        Some(code) if !code.is_empty() => run_code(backend, &mut state, tx.to, code, &tx.data, tx.value)?,
+        _ => true,
+    };
+    if success {
+        state.commit(checkpoint).unwrap();
+    } else {
+        state.revert(checkpoint).unwrap();
+    }
+
+    // since Spurious Dragon (EIP-161), touched accounts that end up empty are deleted
+    if fork > EvmFork::Homestead {
+        state.accounts.retain(|address, account| {
+            let touched = !accounts.contains_key(address) || *address == tx.to;
+            let has_code = codes.get(address).is_some_and(|c| !c.is_empty());
+            !touched || account.nonce > 0 || !account.balance.is_zero() || has_code
+        });
+    }
+
+    let empty_code_hash = keccak(&[]);
+    let code_hashes: HashMap<H160, H256> = codes.iter().map(|(a, c)| (*a, keccak(c))).collect();
+    let root = state_root(state.accounts.iter().map(|(address, account)| {
+        (address, account, code_hashes.get(address).copied().unwrap_or(empty_code_hash))
+    }));
+    let logs = state.end_transaction();
+
+    Ok((root, logs_hash(&logs)))
+}
+
+
+// RUNNER
+
+// runs every expectation of every test whose name contains the filter
+pub fn run_fixtures(json: &str, filter: Option<&str>, backends: &[EvmStateTestBackend]) -> Result<Vec<EvmStateTestResult>, EvmStateTestError> {
+    let fixtures: Value = serde_json::from_str(json)?;
+    let mut results = Vec::new();
+
+    for (name, test) in fixtures.as_object().ok_or_else(|| malformed("expected an object of tests"))? {
+        if let Some(filter) = filter {
+            if !name.contains(filter) {
+                continue;
+            }
+        }
+
+        let (accounts, codes) = parse_pre(&test["pre"])?;
+        let transaction = &test["transaction"];
+        let mut charges_gas = false;
+        for field in ["gasPrice", "maxFeePerGas"] {
+            if !transaction[field].is_null() {
+                charges_gas |= !parse_u256(&transaction[field])?.is_zero();
+            }
+        }
+
+        for (fork_name, expectations) in test["post"].as_object().ok_or_else(|| malformed("post"))? {
+            for (index, expected) in expectations.as_array().ok_or_else(|| malformed("post"))?.iter().enumerate() {
+                let indexes = &expected["indexes"];
+                let tx = match (&transaction["sender"], transaction["to"].as_str()) {
+                    _ if charges_gas => Err("nonzero gas price, gas is not metered".to_string()),
+                    (Value::Null, _) => Err("transaction without sender".to_string()),
+                    (_, None) | (_, Some("")) => Err("contract creation".to_string()),
+                    (sender, Some(_)) => Ok(EvmStateTestTransaction {
+                        sender: parse_h160(sender)?,
+                        to: parse_h160(&transaction["to"])?,
+                        data: parse_bytes(&transaction["data"][parse_index(indexes, "data")?])?,
+                        value: parse_u256(&transaction["value"][parse_index(indexes, "value")?])?,
+                    }),
+                };
+                let expected_hash = parse_h256(&expected["hash"])?;
+                let expected_logs = parse_h256(&expected["logs"])?;
+
+                for backend in backends {
+                    let outcome = match (EvmFork::from_name(fork_name), &tx) {
+                        (None, _) => EvmStateTestOutcome::Skip(format!("unknown fork {}", fork_name)),
+                        (_, Err(reason)) => EvmStateTestOutcome::Skip(reason.clone()),
+                        (_, _) if !expected["expectException"].is_null() => EvmStateTestOutcome::Skip("invalid transaction".to_string()),
+                        (Some(fork), Ok(tx)) => match execute(*backend, fork, &accounts, &codes, tx) {
+                            Err(reason) => EvmStateTestOutcome::Skip(reason),
+                            Ok((hash, _)) if hash != expected_hash => EvmStateTestOutcome::Fail(format!("state root {:?}, expected {:?}", hash, expected_hash)),
+                            Ok((_, logs)) if logs != expected_logs => EvmStateTestOutcome::Fail(format!("logs hash {:?}, expected {:?}", logs, expected_logs)),
+                            Ok(_) => EvmStateTestOutcome::Pass,
+                        },
+                    };
+
+                    results.push(EvmStateTestResult {
+                        name: name.clone(),
+                        fork: fork_name.clone(),
+                        index,
+                        backend: *backend,
+                        outcome,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+// a fixture file, or all fixture files (*.json) below a directory
+pub fn run_path(path: &Path, filter: Option<&str>, backends: &[EvmStateTestBackend]) -> Result<Vec<EvmStateTestResult>, EvmStateTestError> {
+    if path.is_dir() {
+        let mut entries: Vec<_> = std::fs::read_dir(path)?.collect::<Result<_, _>>()?;
+        entries.sort_by_key(|e| e.path());
+
+        let mut results = Vec::new();
+        for entry in entries {
+            let path = entry.path();
+            if path.is_dir() || path.extension().is_some_and(|e| e == "json") {
+                results.extend(run_path(&path, filter, backends)?);
+            }
+        }
+        Ok(results)
+    } else {
+        run_fixtures(&std::fs::read_to_string(path)?, filter, backends)
+    }
+}
+
+
+// REPORT
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvmStateTestCounts {
+    pub pass: usize,
+    pub fail: usize,
+    pub skip: usize,
+}
+
+// pass/fail/skip counts per fork and backend. gas used is not compared with that of the
+// fixtures (it is not metered), the report says so in a column of its own
+#[derive(Debug, Clone, Default)]
+pub struct EvmStateTestReport {
+    pub counts: BTreeMap<(Option<EvmFork>, String, EvmStateTestBackend), EvmStateTestCounts>,
+}
+
+impl EvmStateTestReport {
+    pub fn new(results: &[EvmStateTestResult]) -> Self {
+        let mut counts: BTreeMap<_, EvmStateTestCounts> = BTreeMap::new();
+        for r in results {
+            let c = counts.entry((EvmFork::from_name(&r.fork), r.fork.clone(), r.backend)).or_default();
+            match r.outcome {
+                EvmStateTestOutcome::Pass => c.pass += 1,
+                EvmStateTestOutcome::Fail(_) => c.fail += 1,
+                EvmStateTestOutcome::Skip(_) => c.skip += 1,
+            }
+        }
+        Self { counts }
+    }
+
+    pub fn failures(&self) -> usize {
+        self.counts.values().map(|c| c.fail).sum()
+    }
+}
+
+impl fmt::Display for EvmStateTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<20} {:<12} {:>6} {:>6} {:>6} {:>10}", "fork", "backend", "pass", "fail", "skip", "gas")?;
+        for ((_, fork, backend), c) in &self.counts {
+            writeln!(f, "{:<20} {:<12} {:>6} {:>6} {:>6} {:>10}", fork, format!("{:?}", backend), c.pass, c.fail, c.skip, "unchecked")?;
+        }
+        Ok(())
+    }
+}
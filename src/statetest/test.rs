@@ -0,0 +1,101 @@
+use primitive_types::{H256, U256};
+use std::collections::HashMap;
+use crate::statetest::{run_fixtures, EvmStateTestBackend, EvmStateTestOutcome, EvmStateTestReport};
+use crate::statetest::trie::{keccak, trie_root, storage_root, logs_hash};
+
+
+fn h256(s: &str) -> H256 {
+    H256::from_slice(&hex::decode(s).unwrap())
+}
+
+// a call of the code, expecting a zero state root (which no execution produces) in London
+fn fixture(code: &str, gas_price: &str) -> String {
+    format!(r#"{{
+        "fixture": {{
+            "pre": {{
+                "0xa94f5374fce5edbc8e2a8697c15331677e6ebf0b": {{ "balance": "0x0de0b6b3a7640000", "code": "0x", "nonce": "0x00", "storage": {{}} }},
+                "0x095e7baea6a6c7c4c2dfeb977efac326af552d87": {{ "balance": "0x00", "code": "0x{}", "nonce": "0x00", "storage": {{}} }}
+            }},
+            "post": {{
+                "London": [{{
+                    "hash": "0x0000000000000000000000000000000000000000000000000000000000000000",
+                    "logs": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+                    "indexes": {{ "data": 0, "gas": 0, "value": 0 }}
+                }}]
+            }},
+            "transaction": {{
+                "data": ["0x"],
+                "gasLimit": ["0x0f4240"],
+                "gasPrice": "{}",
+                "nonce": "0x00",
+                "sender": "0xa94f5374fce5edbc8e2a8697c15331677e6ebf0b",
+                "to": "0x095e7baea6a6c7c4c2dfeb977efac326af552d87",
+                "value": ["0x00"]
+            }}
+        }}
+    }}"#, code, gas_price)
+}
+
+
+#[test]
+fn trie_vectors() {
+    let items = [("do", "verb"), ("dog", "puppy"), ("doge", "coin"), ("horse", "stallion")];
+    let root = trie_root(items.iter().map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec())));
+    assert_eq!(root, h256("5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84"));
+
+    let empty = h256("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421");
+    assert_eq!(trie_root(vec![]), empty);
+    // zero slots are not part of the trie
+    assert_eq!(storage_root(&HashMap::from([(U256::zero() + 1, U256::zero())])), empty);
+
+    assert_eq!(logs_hash(&[]), h256("1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347"));
+    assert_eq!(keccak(&[]), h256("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"));
+}
+
+#[test]
+fn backends_agree_on_fixtures() {
+    // no upstream fixtures are vendored, the backends are checked against each other
+    let backends = [EvmStateTestBackend::Interpreter, EvmStateTestBackend::Jit];
+    for code in [
+        "602a60005500",       // SSTORE 42 into slot 0
+        "602a600055600056",   // same, then an invalid jump reverts it
+    ] {
+        let results = run_fixtures(&fixture(code, "0x00"), None, &backends).unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(results[0].outcome, EvmStateTestOutcome::Fail(_)), "{:?}", results[0]);
+        assert_eq!(results[0].outcome, results[1].outcome, "{}", code);
+    }
+
+    assert!(run_fixtures(&fixture("00", "0x00"), Some("other"), &backends).unwrap().is_empty());
+}
+
+#[test]
+fn callvalue_is_the_transaction_value() {
+    use primitive_types::H160;
+    use crate::state::{EvmAccount, EvmJournaledState};
+
+    let address = H160::from_low_u64_be(0x1000);
+    let mut state = EvmJournaledState::new_from_accounts(HashMap::from([(address, EvmAccount::default())]));
+    let code = hex::decode("34600055").unwrap();   // CALLVALUE PUSH1 0 SSTORE
+    let ok = super::run_code(EvmStateTestBackend::Interpreter, &mut state, address, &code, &[], U256::zero() + 5).unwrap();
+    assert!(ok);
+    assert_eq!(state.accounts[&address].storage[&U256::zero()], U256::zero() + 5);
+}
+
+#[test]
+fn report_has_unchecked_gas_column() {
+    let results = run_fixtures(&fixture("00", "0x00"), None, &[EvmStateTestBackend::Interpreter]).unwrap();
+    let report = EvmStateTestReport::new(&results);
+    assert_eq!(report.failures(), 1);
+    let report = report.to_string();
+    let lines: Vec<_> = report.lines().collect();
+    assert!(lines[0].ends_with(" gas"));
+    assert!(lines[1..].iter().all(|l| l.ends_with(" unchecked")));
+}
+
+#[test]
+fn fixtures_with_gas_price_are_skipped() {
+    let results = run_fixtures(&fixture("602a60005500", "0x0a"), None, &[EvmStateTestBackend::Interpreter]).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].outcome, EvmStateTestOutcome::Skip("nonzero gas price, gas is not metered".to_string()));
+}
@@ -0,0 +1,142 @@
+use primitive_types::{H160, H256, U256};
+use rlp::RlpStream;
+use std::collections::HashMap;
+use tiny_keccak::{Hasher, Keccak};
+use crate::state::{EvmAccount, EvmLog};
+
+
+// Just enough of the Merkle-Patricia trie to compute the roots that state tests compare
+// against: the trie is built in memory from all items at once and only its root is kept.
+
+
+pub fn keccak(data: &[u8]) -> H256 {
+    let mut hasher = Keccak::v256();
+    let mut out = [0u8; 32];
+    hasher.update(data);
+    hasher.finalize(&mut out);
+    H256(out)
+}
+
+// U256 as a big-endian byte string without leading zeros (RLP's encoding of integers)
+fn u256_bytes(v: &U256) -> Vec<u8> {
+    let mut b = [0u8; 32];
+    v.to_big_endian(&mut b);
+    b[(v.leading_zeros() / 8) as usize..].to_vec()
+}
+
+fn nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+// hex-prefix encoding of a path, with the node type (leaf or extension) in the first nibble
+fn hex_prefix(path: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 2 } else { 0 };
+    let mut out = Vec::with_capacity(path.len() / 2 + 1);
+    let rest = if path.len() % 2 == 1 {
+        out.push(((flag + 1) << 4) | path[0]);
+        &path[1..]
+    } else {
+        out.push(flag << 4);
+        path
+    };
+    out.extend(rest.chunks(2).map(|c| (c[0] << 4) | c[1]));
+    out
+}
+
+// nodes shorter than a hash are embedded into their parent
+fn append_node_ref(s: &mut RlpStream, node: &[u8]) {
+    if node.len() < 32 {
+        s.append_raw(node, 1);
+    } else {
+        s.append(&keccak(node).as_bytes());
+    }
+}
+
+// RLP of the node holding the given items (sorted by path, paths share the first `depth` nibbles)
+fn encode_node(items: &[(Vec<u8>, Vec<u8>)], depth: usize) -> Vec<u8> {
+    if items.is_empty() {
+        return rlp::NULL_RLP.to_vec();
+    }
+
+    if items.len() == 1 {
+        let mut s = RlpStream::new_list(2);
+        s.append(&hex_prefix(&items[0].0[depth..], true));
+        s.append(&items[0].1);
+        return s.out().to_vec();
+    }
+
+    // items are sorted, so the first and the last path share the longest common prefix
+    let (first, last) = (&items[0].0, &items[items.len() - 1].0);
+    let shared = first[depth..].iter().zip(last[depth..].iter()).take_while(|(a, b)| a == b).count();
+    if shared > 0 {
+        let mut s = RlpStream::new_list(2);
+        s.append(&hex_prefix(&first[depth..depth + shared], false));
+        append_node_ref(&mut s, &encode_node(items, depth + shared));
+        return s.out().to_vec();
+    }
+
+    let mut s = RlpStream::new_list(17);
+    let mut value: &[u8] = &[];
+    let mut rest = items;
+    if rest[0].0.len() == depth {
+        value = &rest[0].1;
+        rest = &rest[1..];
+    }
+    for nibble in 0..16u8 {
+        let n = rest.iter().take_while(|(path, _)| path[depth] == nibble).count();
+        if n == 0 {
+            s.append_empty_data();
+        } else {
+            append_node_ref(&mut s, &encode_node(&rest[..n], depth + 1));
+        }
+        rest = &rest[n..];
+    }
+    s.append(&value);
+    s.out().to_vec()
+}
+
+pub fn trie_root(items: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) -> H256 {
+    let mut items: Vec<_> = items.into_iter().map(|(k, v)| (nibbles(&k), v)).collect();
+    items.sort();
+    keccak(&encode_node(&items, 0))
+}
+
+// keys are hashed before they are inserted ("secure" trie, as for accounts and storage)
+pub fn secure_trie_root(items: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) -> H256 {
+    trie_root(items.into_iter().map(|(k, v)| (keccak(&k).as_bytes().to_vec(), v)))
+}
+
+pub fn storage_root(storage: &HashMap<U256, U256>) -> H256 {
+    secure_trie_root(storage.iter().filter(|(_, v)| !v.is_zero()).map(|(k, v)| {
+        let mut key = [0u8; 32];
+        k.to_big_endian(&mut key);
+        (key.to_vec(), rlp::encode(&u256_bytes(v)).to_vec())
+    }))
+}
+
+pub fn state_root<'a>(accounts: impl IntoIterator<Item = (&'a H160, &'a EvmAccount, H256)>) -> H256 {
+    secure_trie_root(accounts.into_iter().map(|(address, account, code_hash)| {
+        let mut s = RlpStream::new_list(4);
+        s.append(&account.nonce);
+        s.append(&u256_bytes(&account.balance));
+        s.append(&storage_root(&account.storage).as_bytes());
+        s.append(&code_hash.as_bytes());
+        (address.as_bytes().to_vec(), s.out().to_vec())
+    }))
+}
+
+pub fn logs_hash(logs: &[EvmLog]) -> H256 {
+    let mut s = RlpStream::new_list(logs.len());
+    for log in logs {
+        s.begin_list(3);
+        s.append(&log.address.as_bytes());
+        s.begin_list(log.topics.len());
+        for topic in &log.topics {
+            let mut t = [0u8; 32];
+            topic.to_big_endian(&mut t);
+            s.append(&t.as_slice());
+        }
+        s.append(&log.data);
+    }
+    keccak(&s.out())
+}